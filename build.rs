@@ -0,0 +1,149 @@
+//! Preprocesses and validates every shader in `shaders/` with naga, so that mistakes are build
+//! errors rather than panics when a pipeline is created.
+
+use std::{fs, path::Path};
+
+#[allow(dead_code)]
+#[path = "src/preprocess.rs"]
+mod preprocess;
+
+use preprocess::{Preprocessor, ShaderDefines};
+
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    Binding, Module, ScalarKind, ShaderStage, TypeInner, VectorSize,
+};
+
+/// The vertex inputs `shape.wgsl` and `material_shape.wgsl` must declare: the attributes of `ShapeVertex::desc` followed by
+/// those of `ShapeInstance::desc`, as (location, number of f32 components).
+const SHAPE_VERTEX_INPUTS: &[(u32, u8)] = &[(0, 3), (1, 4), (2, 2), (3, 2), (4, 2), (5, 4)];
+
+/// The defines of the variants the renderer builds of each shader, besides the one without any.
+const VARIANTS: &[(&str, &[&str])] = &[("sprite.wgsl", &["INSTANCED"])];
+
+fn main() {
+    println!("cargo:rerun-if-changed=shaders");
+
+    let mut entries = fs::read_dir("shaders")
+        .expect("failed to read the shaders directory")
+        .map(|entry| entry.expect("failed to read the shaders directory").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wgsl")
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.set_include_dir(Some("shaders".into()));
+
+    for path in entries {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let name = path.file_name().unwrap().to_string_lossy();
+
+        let mut variants = vec![ShaderDefines::new()];
+        for (_, defines) in VARIANTS.iter().filter(|(file, _)| *file == name) {
+            variants.push(
+                defines
+                    .iter()
+                    .fold(ShaderDefines::new(), |variant, define| variant.with(define)),
+            );
+        }
+
+        for defines in &variants {
+            let module = validate(&preprocessor, &name, defines);
+            if name == "shape.wgsl" || name == "material_shape.wgsl" {
+                check_vertex_inputs(&path, &module, SHAPE_VERTEX_INPUTS);
+            }
+        }
+    }
+}
+
+fn validate(preprocessor: &Preprocessor, name: &str, defines: &ShaderDefines) -> Module {
+    let label = format!("{} {:?}", name, defines);
+    let source = preprocessor
+        .preprocess_file(name, defines)
+        .unwrap_or_else(|error| panic!("failed to preprocess {}: {:#}", label, error));
+    // Parse and validation errors are located in the preprocessed source.
+    let located = |line: u32| match source.origin(line) {
+        Some((file, file_line)) => format!("\nline {} is {}:{}", line, file, file_line),
+        None => String::new(),
+    };
+
+    let module = naga::front::wgsl::parse_str(source.source()).unwrap_or_else(|error| {
+        let location = error
+            .location(source.source())
+            .map_or(String::new(), |location| located(location.line_number));
+        panic!(
+            "failed to parse {}:\n{}{}",
+            label,
+            error.emit_to_string(source.source()),
+            location
+        )
+    });
+    Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .unwrap_or_else(|error| panic!("{} is invalid: {:?}", label, error));
+    module
+}
+
+fn check_vertex_inputs(path: &Path, module: &Module, expected: &[(u32, u8)]) {
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|entry_point| entry_point.stage == ShaderStage::Vertex)
+        .unwrap_or_else(|| panic!("{} has no vertex entry point", path.display()));
+
+    // Inputs can be passed directly or as the members of a struct.
+    let mut found = Vec::new();
+    let mut add_input = |binding: &Binding, ty: naga::Handle<naga::Type>| {
+        if let Binding::Location { location, .. } = *binding {
+            found.push((location, components(&module.types[ty].inner)));
+        }
+    };
+    for argument in &entry_point.function.arguments {
+        match (&argument.binding, &module.types[argument.ty].inner) {
+            (Some(binding), _) => add_input(binding, argument.ty),
+            (None, TypeInner::Struct { members, .. }) => {
+                for member in members {
+                    if let Some(binding) = &member.binding {
+                        add_input(binding, member.ty);
+                    }
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    found.sort();
+
+    let expected = expected
+        .iter()
+        .map(|&(location, size)| (location, Some(size)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        expected,
+        "the vertex inputs of {} don't match the shape vertex format, as (location, f32 components)",
+        path.display()
+    );
+}
+
+/// The number of components in an f32 scalar or vector type.
+fn components(inner: &TypeInner) -> Option<u8> {
+    match *inner {
+        TypeInner::Scalar {
+            kind: ScalarKind::Float,
+            width: 4,
+        } => Some(1),
+        TypeInner::Vector {
+            size,
+            kind: ScalarKind::Float,
+            width: 4,
+        } => Some(match size {
+            VectorSize::Bi => 2,
+            VectorSize::Tri => 3,
+            VectorSize::Quad => 4,
+        }),
+        _ => None,
+    }
+}
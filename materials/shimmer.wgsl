@@ -0,0 +1,9 @@
+// A band of light sweeping across a sprite material, in world space.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_sprite, s_sprite, in.tex_coords) * in.tint;
+    let band = sin(in.world_position.x * 0.02 - material.time * material.speed);
+    let glow = smoothstep(0.8, 1.0, band) * material.glow_color.a;
+    return vec4<f32>(mix(color.rgb, material.glow_color.rgb, glow), color.a);
+}
@@ -0,0 +1,20 @@
+// Clears a camera's viewport, when it doesn't cover the whole target and so can't be cleared
+// when the render pass begins. Drawn with the viewport and scissor set, at the far depth.
+
+struct Clear {
+    color: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> clear: Clear;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return clear.color;
+}
@@ -0,0 +1,28 @@
+// The vertex stage of shape materials. A material's fragment shader is appended to this, so it
+// can use `VertexOutput`, `view_projection` and `world_to_clip`.
+
+#include "view_projection.wgsl"
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // The vertex color times the instance tint. Shapes built with a gradient or pattern paint
+    // have white vertices, as the material replaces the paint.
+    @location(0) color: vec4<f32>,
+    // The position before the instance transform.
+    @location(1) local_position: vec2<f32>,
+    @location(2) world_position: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) a_position: vec3<f32>,
+    @location(1) a_color: vec4<f32>,
+    @location(2) i_transform_x: vec2<f32>,
+    @location(3) i_transform_y: vec2<f32>,
+    @location(4) i_translation: vec2<f32>,
+    @location(5) i_tint: vec4<f32>,
+) -> VertexOutput {
+    var world_position = i_transform_x * a_position.x + i_transform_y * a_position.y + i_translation;
+    var clip_position = world_to_clip(vec3<f32>(world_position, a_position.z));
+    return VertexOutput(clip_position, a_color * i_tint, a_position.xy, world_position);
+}
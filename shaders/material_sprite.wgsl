@@ -0,0 +1,43 @@
+// The vertex stage of sprite materials. A material's fragment shader is appended to this, so it
+// can use `VertexOutput`, `view_projection` and `world_to_clip`.
+
+#include "view_projection.wgsl"
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(3) transform_x: vec2<f32>,
+    @location(4) transform_y: vec2<f32>,
+    @location(5) translation: vec2<f32>,
+    @location(6) uv_rect: vec4<f32>,
+    @location(7) tint: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+    @location(2) world_position: vec2<f32>,
+    // Where in the sprite, from (0, 0) at the top left to (1, 1) at the bottom right,
+    // independent of the uv rect.
+    @location(3) local_uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let world = instance.transform_x * model.position.x + instance.transform_y * model.position.y + instance.translation;
+
+    var out: VertexOutput;
+    out.clip_position = world_to_clip(vec3<f32>(world, model.position.z));
+    out.tex_coords = mix(instance.uv_rect.xy, instance.uv_rect.zw, model.tex_coords);
+    out.tint = instance.tint;
+    out.world_position = world;
+    out.local_uv = model.tex_coords;
+    return out;
+}
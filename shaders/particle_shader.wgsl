@@ -0,0 +1,50 @@
+// Vertex shader
+
+#include "view_projection.wgsl"
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(3) position: vec2<f32>,
+    @location(4) size: f32,
+    @location(5) rotation: f32,
+    @location(6) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let c = cos(instance.rotation);
+    let s = sin(instance.rotation);
+    let corner = model.position.xy * instance.size;
+    let world = vec2<f32>(corner.x * c - corner.y * s, corner.x * s + corner.y * c) + instance.position;
+
+    var out: VertexOutput;
+    out.clip_position = world_to_clip(vec3<f32>(world, model.position.z));
+    out.tex_coords = model.tex_coords;
+    out.color = instance.color;
+    return out;
+}
+
+// Fragment shader
+
+@group(1) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.color;
+}
@@ -0,0 +1,27 @@
+// Shared by the post processing passes: a full screen triangle, the texture being processed
+// and a second texture for passes that combine two.
+//
+// Each pass declares its own `Params` uniform at binding 4. It must start with `texel_size`,
+// the size of one texel of `t_input`, and match the values written in postprocess.rs.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Covers the screen with uvs from (0, 0) at the top left to (1, 1) at the bottom right.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return VertexOutput(position, uv);
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+@group(0) @binding(2)
+var t_extra: texture_2d<f32>;
+@group(0) @binding(3)
+var s_extra: sampler;
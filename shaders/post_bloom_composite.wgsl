@@ -0,0 +1,18 @@
+// Adds the blurred bright parts, in the extra texture, back onto the input.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    intensity: f32,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let bloom = textureSample(t_extra, s_extra, in.uv).rgb;
+    return vec4<f32>(color.rgb + bloom * params.intensity, color.a);
+}
@@ -0,0 +1,31 @@
+// Keeps the parts of the input brighter than a threshold, downsampled to the bloom target.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    threshold: f32,
+    // How gradually colors below the threshold fade out, as a fraction of the threshold.
+    soft_knee: f32,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Average a 2x2 block of input texels, one for each output texel.
+    let offset = params.texel_size * 0.5;
+    let color = (
+        textureSample(t_input, s_input, in.uv + vec2<f32>(-offset.x, -offset.y)) +
+        textureSample(t_input, s_input, in.uv + vec2<f32>(offset.x, -offset.y)) +
+        textureSample(t_input, s_input, in.uv + vec2<f32>(-offset.x, offset.y)) +
+        textureSample(t_input, s_input, in.uv + vec2<f32>(offset.x, offset.y))
+    ).rgb * 0.25;
+
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = max(params.threshold * params.soft_knee, 0.0001);
+    let soft = clamp(brightness - params.threshold + knee, 0.0, 2.0 * knee);
+    let contribution = max(soft * soft / (4.0 * knee), brightness - params.threshold);
+    return vec4<f32>(color * max(contribution, 0.0) / max(brightness, 0.0001), 1.0);
+}
@@ -0,0 +1,26 @@
+// One direction of a separable gaussian blur.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    // The distance between taps, in texels, along the blur direction.
+    step: vec2<f32>,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let step = params.step * params.texel_size;
+
+    var color = textureSample(t_input, s_input, in.uv) * weights[0];
+    for (var i = 1; i < 5; i = i + 1) {
+        let offset = step * f32(i);
+        color = color + textureSample(t_input, s_input, in.uv + offset) * weights[i];
+        color = color + textureSample(t_input, s_input, in.uv - offset) * weights[i];
+    }
+    return color;
+}
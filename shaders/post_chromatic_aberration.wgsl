@@ -0,0 +1,21 @@
+// Splits the red and blue channels apart towards the edges of the screen, like a cheap lens.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    // How far apart the channels are at the edges, in texels.
+    amount: f32,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * 2.0 * params.amount * params.texel_size;
+    let red = textureSample(t_input, s_input, in.uv + offset).r;
+    let center = textureSample(t_input, s_input, in.uv);
+    let blue = textureSample(t_input, s_input, in.uv - offset).b;
+    return vec4<f32>(red, center.g, blue, center.a);
+}
@@ -0,0 +1,44 @@
+// Maps colors through a lookup table in the extra texture: `size` slices of `size` x `size`
+// texels side by side, red increasing to the right within a slice, green downwards and blue
+// from slice to slice. The table is indexed by, and holds, sRGB encoded colors.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    intensity: f32,
+    size: f32,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn lookup(color: vec3<f32>) -> vec3<f32> {
+    let size = params.size;
+    let blue = color.b * (size - 1.0);
+    let slice = floor(blue);
+    let next_slice = min(slice + 1.0, size - 1.0);
+    // The centers of the texels for the red and green channels.
+    let red_green = color.rg * (size - 1.0) + 0.5;
+    let uv = vec2<f32>((slice * size + red_green.x) / (size * size), red_green.y / size);
+    let next_uv = vec2<f32>((next_slice * size + red_green.x) / (size * size), red_green.y / size);
+    // The table is sampled in linear space, as its texture is sRGB.
+    return mix(
+        textureSampleLevel(t_extra, s_extra, uv, 0.0).rgb,
+        textureSampleLevel(t_extra, s_extra, next_uv, 0.0).rgb,
+        blue - slice
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let graded = lookup(linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0))));
+    return vec4<f32>(mix(color.rgb, graded, params.intensity), color.a);
+}
@@ -0,0 +1,8 @@
+// Copies the input, when there are no effects to apply.
+
+#include "post.wgsl"
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_input, s_input, in.uv);
+}
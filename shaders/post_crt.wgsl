@@ -0,0 +1,30 @@
+// An old CRT monitor: a curved screen with dark scanlines between the rows.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    curvature: f32,
+    scanline_intensity: f32,
+    scanline_count: f32,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+let PI: f32 = 3.14159265;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var position = in.uv * 2.0 - 1.0;
+    position = position * (1.0 + params.curvature * position.yx * position.yx);
+    let uv = position * 0.5 + 0.5;
+
+    let color = textureSample(t_input, s_input, uv);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let scanline = 0.5 + 0.5 * sin(uv.y * params.scanline_count * 2.0 * PI);
+    return vec4<f32>(color.rgb * (1.0 - params.scanline_intensity * scanline), color.a);
+}
@@ -0,0 +1,28 @@
+// Darkens the edges of the screen towards a color.
+
+#include "post.wgsl"
+
+struct Params {
+    texel_size: vec2<f32>,
+    intensity: f32,
+    // Distance from the center, where the corners are 1, at which darkening starts.
+    radius: f32,
+    // Distance over which it fades in.
+    softness: f32,
+    color: vec4<f32>,
+};
+
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    // Keep the vignette round on screens that aren't square.
+    let aspect = params.texel_size.y / params.texel_size.x;
+    let centered = (in.uv - 0.5) * vec2<f32>(aspect, 1.0);
+    let corner = length(vec2<f32>(aspect, 1.0) * 0.5);
+    let distance = length(centered) / corner;
+    let amount = smoothstep(params.radius, params.radius + params.softness, distance) * params.intensity;
+    return vec4<f32>(mix(color.rgb, params.color.rgb, amount * params.color.a), color.a);
+}
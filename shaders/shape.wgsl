@@ -0,0 +1,136 @@
+// Vertex
+
+#include "view_projection.wgsl"
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) v_color: vec4<f32>,
+    // The position before the instance transform, which paints are described in.
+    @location(1) v_local_position: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) a_position: vec3<f32>,
+    @location(1) a_color: vec4<f32>,
+    @location(2) i_transform_x: vec2<f32>,
+    @location(3) i_transform_y: vec2<f32>,
+    @location(4) i_translation: vec2<f32>,
+    @location(5) i_tint: vec4<f32>,
+) -> VertexOutput {
+    var world_position = i_transform_x * a_position.x + i_transform_y * a_position.y + i_translation;
+    var clip_position = world_to_clip(vec3<f32>(world_position, a_position.z));
+    return VertexOutput(clip_position, a_color * i_tint, a_position.xy);
+}
+
+
+// Fragment
+
+// Must match the constants in paint.rs.
+let PAINT_SOLID: u32 = 0u;
+let PAINT_LINEAR: u32 = 1u;
+let PAINT_RADIAL: u32 = 2u;
+let PAINT_CONIC: u32 = 3u;
+let PAINT_PATTERN: u32 = 4u;
+
+let SPREAD_PAD: u32 = 0u;
+let SPREAD_REPEAT: u32 = 1u;
+
+let TAU: f32 = 6.283185307179586;
+
+struct Paint {
+    // Rows of the affine transform from local space to paint space.
+    paint_from_local_x: vec4<f32>,
+    paint_from_local_y: vec4<f32>,
+    // Linear: start, end. Radial: center, radius. Conic: center, start angle.
+    params: vec4<f32>,
+    // Radial: focal point.
+    focal: vec4<f32>,
+    kind: u32,
+    spread: u32,
+    stop_count: u32,
+    stop_offsets: array<vec4<f32>, 4>,
+    stop_colors: array<vec4<f32>, 16>,
+};
+
+@group(1) @binding(0)
+var<uniform> paint: Paint;
+@group(1) @binding(1)
+var t_pattern: texture_2d<f32>;
+@group(1) @binding(2)
+var s_pattern: sampler;
+
+fn stop_offset(i: u32) -> f32 {
+    return paint.stop_offsets[i / 4u][i % 4u];
+}
+
+fn apply_spread(t: f32) -> f32 {
+    if (paint.spread == SPREAD_PAD) {
+        return clamp(t, 0.0, 1.0);
+    }
+    if (paint.spread == SPREAD_REPEAT) {
+        return fract(t);
+    }
+    // Reflect
+    return 1.0 - abs(t - 2.0 * floor(t * 0.5) - 1.0);
+}
+
+fn sample_stops(t: f32) -> vec4<f32> {
+    if (t <= stop_offset(0u)) {
+        return paint.stop_colors[0];
+    }
+    var i: u32 = 1u;
+    loop {
+        if (i >= paint.stop_count) {
+            break;
+        }
+        let t1 = stop_offset(i);
+        if (t < t1) {
+            let t0 = stop_offset(i - 1u);
+            let s = (t - t0) / max(t1 - t0, 0.00001);
+            return mix(paint.stop_colors[i - 1u], paint.stop_colors[i], s);
+        }
+        i = i + 1u;
+    }
+    return paint.stop_colors[paint.stop_count - 1u];
+}
+
+// Find `t` such that `p` lies on the circle centred on `focal + t * (center - focal)` with
+// radius `t * radius`.
+fn radial_offset(p: vec2<f32>, center: vec2<f32>, radius: f32, focal: vec2<f32>) -> f32 {
+    let d = center - focal;
+    let q = p - focal;
+    let a = dot(d, d) - radius * radius;
+    let b = dot(q, d);
+    let c = dot(q, q);
+    if (abs(a) < 0.00001) {
+        return c / max(2.0 * b, 0.00001);
+    }
+    let discriminant = max(b * b - a * c, 0.0);
+    return (b - sqrt(discriminant)) / a;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let local = vec3<f32>(in.v_local_position, 1.0);
+    let p = vec2<f32>(dot(paint.paint_from_local_x.xyz, local), dot(paint.paint_from_local_y.xyz, local));
+
+    // Sampled outside of the branches below, which aren't uniform control flow.
+    let pattern = textureSample(t_pattern, s_pattern, p);
+
+    var color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    if (paint.kind == PAINT_LINEAR) {
+        let start = paint.params.xy;
+        let d = paint.params.zw - start;
+        color = sample_stops(apply_spread(dot(p - start, d) / max(dot(d, d), 0.00001)));
+    } else if (paint.kind == PAINT_RADIAL) {
+        let t = radial_offset(p, paint.params.xy, paint.params.z, paint.focal.xy);
+        color = sample_stops(apply_spread(t));
+    } else if (paint.kind == PAINT_CONIC) {
+        let d = p - paint.params.xy;
+        color = sample_stops(fract((atan2(d.y, d.x) - paint.params.z) / TAU));
+    } else if (paint.kind == PAINT_PATTERN) {
+        color = pattern;
+    }
+    return color * in.v_color;
+}
@@ -1,36 +0,0 @@
-// Vertex shader
-
-struct ViewProjection {
-    view: mat4x4<f32>,
-    projection: mat4x4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> view_projection: ViewProjection;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(2) color: vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
-
-// Fragment shader
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
-}
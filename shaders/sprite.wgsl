@@ -0,0 +1,69 @@
+// Textured quads. Defining INSTANCED draws one quad per instance, placed and tinted by the
+// instance, instead of single sprites colored by their vertices.
+
+// Vertex shader
+
+#include "view_projection.wgsl"
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+#ifndef INSTANCED
+    @location(2) color: vec3<f32>,
+#endif
+}
+
+#ifdef INSTANCED
+struct InstanceInput {
+    @location(3) transform_x: vec2<f32>,
+    @location(4) transform_y: vec2<f32>,
+    @location(5) translation: vec2<f32>,
+    @location(6) uv_rect: vec4<f32>,
+    @location(7) tint: vec4<f32>,
+}
+#endif
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+#ifdef INSTANCED
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let world = instance.transform_x * model.position.x + instance.transform_y * model.position.y + instance.translation;
+
+    var out: VertexOutput;
+    out.clip_position = world_to_clip(vec3<f32>(world, model.position.z));
+    out.tex_coords = mix(instance.uv_rect.xy, instance.uv_rect.zw, model.tex_coords);
+    out.color = instance.tint;
+    return out;
+}
+#else
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = world_to_clip(model.position);
+    out.tex_coords = model.tex_coords;
+    out.color = vec4<f32>(model.color, 1.0);
+    return out;
+}
+#endif
+
+// Fragment shader
+
+@group(1) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1)
+var s_diffuse: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.color;
+}
@@ -1,78 +0,0 @@
-// // Vertex shader
-
-// struct VertexInput {
-//     @location(0) position: vec3<f32>,
-//     @location(1) tex_coords: vec2<f32>,
-// }
-
-// struct VertexOutput {
-//     @builtin(position) clip_position: vec4<f32>,
-//     @location(0) tex_coords: vec2<f32>,
-// }
-
-// @vertex
-// fn vs_main(
-//     model: VertexInput,
-// ) -> VertexOutput {
-//     var out: VertexOutput;
-//     out.tex_coords = model.tex_coords;
-//     out.clip_position = vec4<f32>(model.position, 1.0);
-//     return out;
-// }
-
-// // Fragment shader
-
-// @group(0) @binding(0)
-// var t_diffuse: texture_2d<f32>;
-// @group(0) @binding(1)
-// var s_diffuse: sampler;
-
-// @fragment
-// fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-//     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-// }
-
-// Vertex shader
-
-struct ViewProjection {
-    view: mat4x4<f32>,
-    projection: mat4x4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> view_projection: ViewProjection;
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-    @location(2) color: vec3<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-    @location(1) color: vec3<f32>,
-}
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = view_projection.projection * view_projection.view * vec4<f32>(model.position, 1.0);
-    out.tex_coords = model.tex_coords;
-    out.color = model.color;
-    return out;
-}
-
-// Fragment shader
-
-@group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(1) @binding(1)
-var s_diffuse: sampler;
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * vec4<f32>(in.color, 1.0);;
-}
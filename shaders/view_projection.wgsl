@@ -0,0 +1,13 @@
+// The view projection uniform, group 0 of every pipeline.
+
+struct ViewProjection {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> view_projection: ViewProjection;
+
+fn world_to_clip(position: vec3<f32>) -> vec4<f32> {
+    return view_projection.projection * view_projection.view * vec4<f32>(position, 1.0);
+}
@@ -0,0 +1,382 @@
+//! A single file packing many assets, for release builds to ship instead of a directory. Written
+//! by [`ArchiveBuilder`], or the `pack` binary, and read by [`Archive`].
+//!
+//! The format is little endian:
+//!
+//! - the magic bytes `PCAR` and a `u32` version, currently 1,
+//! - a `u32` count of entries, then for each entry its path as a `u16` length and UTF-8 bytes,
+//!   and `u64`s for its offset in the file, stored length and original length, and a `u8`
+//!   compression, 0 for stored and 1 for deflate,
+//! - the entries' data.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::*;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+const MAGIC: &[u8; 4] = b"PCAR";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compressed {
+    Stored = 0,
+    Deflate = 1,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    offset: u64,
+    stored_len: u64,
+    len: u64,
+    compressed: Compressed,
+}
+
+/// An archive file opened for reading, whose index is read up front and whose entries are read
+/// on demand. It can be read from several threads at once.
+#[derive(Debug)]
+pub struct Archive {
+    file: Mutex<File>,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Archive {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let file_len = file.metadata()?.len();
+        let entries = read_index(&mut file, file_len)
+            .with_context(|| format!("{} isn't a valid archive", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            entries,
+        })
+    }
+
+    /// The paths of every entry, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// The original size of the entry at `path`.
+    pub fn len_of(&self, path: &str) -> Option<u64> {
+        self.entries.get(path).map(|entry| entry.len)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Read and decompress the entry at `path`.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = *self
+            .entries
+            .get(path)
+            .with_context(|| format!("{} isn't in the archive", path))?;
+        let mut stored = vec![0; entry.stored_len as usize];
+        {
+            let mut file = self.file.lock().expect("an archive read panicked");
+            file.seek(SeekFrom::Start(entry.offset))?;
+            file.read_exact(&mut stored)
+                .with_context(|| format!("{} is truncated", path))?;
+        }
+        match entry.compressed {
+            Compressed::Stored => Ok(stored),
+            Compressed::Deflate => {
+                // Reading one byte past the length is enough to tell the entry is wrong.
+                let mut bytes = Vec::new();
+                DeflateDecoder::new(stored.as_slice())
+                    .take(entry.len.saturating_add(1))
+                    .read_to_end(&mut bytes)
+                    .with_context(|| format!("failed to decompress {}", path))?;
+                ensure!(
+                    bytes.len() as u64 == entry.len,
+                    "{} decompressed to {} bytes instead of {}",
+                    path,
+                    bytes.len(),
+                    entry.len
+                );
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Read the index of an archive `file_len` bytes long, checking every entry lies inside it.
+fn read_index(file: &mut impl Read, file_len: u64) -> Result<BTreeMap<String, Entry>> {
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "the magic bytes are wrong");
+    let version = read_u32(file)?;
+    ensure!(version == VERSION, "unsupported version {}", version);
+
+    let mut entries = BTreeMap::new();
+    for _ in 0..read_u32(file)? {
+        let mut path = vec![0; read_u16(file)? as usize];
+        file.read_exact(&mut path)?;
+        let path = String::from_utf8(path).context("an entry's path isn't UTF-8")?;
+        let offset = read_u64(file)?;
+        let stored_len = read_u64(file)?;
+        let len = read_u64(file)?;
+        let mut compressed = [0];
+        file.read_exact(&mut compressed)?;
+        let compressed = match compressed[0] {
+            0 => Compressed::Stored,
+            1 => Compressed::Deflate,
+            other => bail!("{} has unknown compression {}", path, other),
+        };
+        ensure!(
+            offset
+                .checked_add(stored_len)
+                .is_some_and(|end| end <= file_len),
+            "{} lies past the end of the file",
+            path
+        );
+        ensure!(
+            compressed == Compressed::Deflate || len == stored_len,
+            "{} is stored as {} bytes but its length is {}",
+            path,
+            stored_len,
+            len
+        );
+        entries.insert(
+            path,
+            Entry {
+                offset,
+                stored_len,
+                len,
+                compressed,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Collects files to write an [`Archive`]. Each is deflated, unless that doesn't make it smaller,
+/// as with PNGs.
+#[derive(Debug, Default)]
+pub struct ArchiveBuilder {
+    entries: BTreeMap<String, (Vec<u8>, u64, Compressed)>,
+}
+
+impl ArchiveBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `bytes` as the entry at `path`, replacing any entry already there.
+    pub fn add(&mut self, path: &str, bytes: &[u8]) -> Result<()> {
+        ensure!(
+            path.len() <= u16::MAX as usize,
+            "{} is too long a path",
+            path
+        );
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(bytes)?;
+        let deflated = encoder.finish()?;
+        let entry = if deflated.len() < bytes.len() {
+            (deflated, bytes.len() as u64, Compressed::Deflate)
+        } else {
+            (bytes.to_vec(), bytes.len() as u64, Compressed::Stored)
+        };
+        self.entries.insert(path.to_string(), entry);
+        Ok(())
+    }
+
+    /// Add every file under `dir`, at paths relative to it separated by `/`, skipping hidden
+    /// files and directories.
+    pub fn add_dir(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        self.add_dir_at(dir.as_ref(), "")
+    }
+
+    fn add_dir_at(&mut self, dir: &Path, prefix: &str) -> Result<()> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("failed to read directory {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name
+                .to_str()
+                .with_context(|| format!("{} isn't UTF-8", entry.path().display()))?;
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = format!("{}{}", prefix, name);
+            if entry.file_type()?.is_dir() {
+                self.add_dir_at(&entry.path(), &format!("{}/", path))?;
+            } else {
+                let bytes = std::fs::read(entry.path())
+                    .with_context(|| format!("failed to read {}", entry.path().display()))?;
+                self.add(&path, &bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+
+        let index_len: usize = self
+            .entries
+            .keys()
+            .map(|path| 2 + path.len() + 3 * 8 + 1)
+            .sum();
+        let mut offset = (MAGIC.len() + 4 + 4 + index_len) as u64;
+        for (path, (stored, len, compressed)) in &self.entries {
+            writer.write_all(&(path.len() as u16).to_le_bytes())?;
+            writer.write_all(path.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&(stored.len() as u64).to_le_bytes())?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(&[*compressed as u8])?;
+            offset += stored.len() as u64;
+        }
+        for (stored, _, _) in self.entries.values() {
+            writer.write_all(stored)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut file = std::io::BufWriter::new(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        );
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in the temporary directory that's removed when dropped.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "papercut-archive-{}-{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, bytes).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn archive_bytes() -> Vec<u8> {
+        let mut builder = ArchiveBuilder::new();
+        builder.add("empty.txt", b"").unwrap();
+        builder.add("repeated.txt", &b"abcd".repeat(1000)).unwrap();
+        builder.add("dir/noise.bin", &[7, 3, 250, 1, 99]).unwrap();
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn open(name: &str, bytes: &[u8]) -> Result<Archive> {
+        let file = TempFile::new(name, bytes);
+        Archive::open(&file.0)
+    }
+
+    #[test]
+    fn round_trips() {
+        let archive = open("round_trips", &archive_bytes()).unwrap();
+        assert_eq!(
+            archive.paths().collect::<Vec<_>>(),
+            ["dir/noise.bin", "empty.txt", "repeated.txt"]
+        );
+        assert_eq!(archive.read("empty.txt").unwrap(), b"");
+        assert_eq!(archive.read("repeated.txt").unwrap(), b"abcd".repeat(1000));
+        assert_eq!(archive.read("dir/noise.bin").unwrap(), [7, 3, 250, 1, 99]);
+        assert_eq!(archive.len_of("repeated.txt"), Some(4000));
+        assert!(archive.read("missing.txt").is_err());
+    }
+
+    #[test]
+    fn compresses_only_when_smaller() {
+        let archive = open("compresses", &archive_bytes()).unwrap();
+        let entry = |path: &str| archive.entries[path];
+        assert_eq!(entry("repeated.txt").compressed, Compressed::Deflate);
+        assert!(entry("repeated.txt").stored_len < 4000);
+        assert_eq!(entry("dir/noise.bin").compressed, Compressed::Stored);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = archive_bytes();
+        bytes[0] = b'X';
+        assert!(open("bad_magic", &bytes).is_err());
+
+        let mut bytes = archive_bytes();
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        let error = open("bad_version", &bytes).unwrap_err();
+        assert!(format!("{:#}", error).contains("unsupported version 2"));
+
+        assert!(open("empty", b"").is_err());
+    }
+
+    #[test]
+    fn rejects_entries_past_the_end() {
+        let bytes = archive_bytes();
+        assert!(open("truncated", &bytes[..bytes.len() - 1]).is_err());
+
+        // The stored length of the first entry, after the header, its path and offset.
+        let stored_len = 12 + 2 + "dir/noise.bin".len() + 8;
+        let mut bytes = archive_bytes();
+        bytes[stored_len..stored_len + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = open("huge_entry", &bytes).unwrap_err();
+        assert!(format!("{:#}", error).contains("past the end of the file"));
+    }
+}
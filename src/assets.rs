@@ -0,0 +1,1143 @@
+//! Loading textures, shaders, fonts and sprite sheets by path into typed handles.
+//!
+//! An [`AssetServer`] loads each path once, however many times it's asked for, and keeps the
+//! asset until every [`Handle`] to it has been dropped. Files are read and decoded on worker
+//! threads, and the assets created from them on the main thread by [`AssetServer::update`],
+//! which is where textures are uploaded. Until then a placeholder can be drawn instead.
+//!
+//! Loading never fails outright: a handle is returned either way, and the error is kept for that
+//! asset to be reported.
+//!
+//! While developing, [`AssetServer::watch_for_changes`] reloads assets into the same handles when
+//! their files change.
+
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::*;
+
+use crate::{
+    archive::Archive,
+    compressed::CompressedImage,
+    renderer::{Bananas, Renderer},
+    shader::Shader,
+    texture::{load_image, Texture, TextureOptions},
+};
+
+/// Something an [`AssetServer`] can load from a file. Loading is split in two, so that the slow
+/// part can happen off the main thread.
+pub trait Asset: Sized + 'static {
+    /// How the asset is loaded, such as a texture's format.
+    type Settings: Clone + Default + Send + 'static;
+    /// The decoded file, ready to create the asset from.
+    type Data: Send + 'static;
+
+    /// Decode the file on a worker thread.
+    fn decode(bytes: Vec<u8>, path: &str, settings: &Self::Settings) -> Result<Self::Data>;
+
+    /// Create the asset on the main thread, uploading it to the GPU. This should be quick.
+    fn create(data: Self::Data, context: &mut LoadContext) -> Result<Self>;
+}
+
+/// Whether an asset is ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed,
+}
+
+/// What happened to an asset in an [`AssetServer::update`], for recreating whatever was made
+/// from its placeholder, such as bind groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetEvent {
+    Loaded(AssetId),
+    Failed(AssetId),
+    /// Its file changed and it was loaded again into the same handle.
+    Reloaded(AssetId),
+}
+
+impl AssetEvent {
+    pub fn id(&self) -> AssetId {
+        match *self {
+            AssetEvent::Loaded(id) | AssetEvent::Failed(id) | AssetEvent::Reloaded(id) => id,
+        }
+    }
+}
+
+/// How far along the assets being loaded are, for loading screens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadProgress {
+    pub loaded: usize,
+    pub failed: usize,
+    pub loading: usize,
+}
+
+impl LoadProgress {
+    pub fn total(&self) -> usize {
+        self.loaded + self.failed + self.loading
+    }
+
+    /// The fraction of assets that have finished loading or failed, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        match self.total() {
+            0 => 1.0,
+            total => (self.loaded + self.failed) as f32 / total as f32,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.loading == 0
+    }
+}
+
+/// Identifies an asset among those of its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AssetId(u64);
+
+/// A reference counted handle to an asset of type `T`. The asset is freed by
+/// [`AssetServer::collect_garbage`] once every clone of its handle has been dropped.
+pub struct Handle<T> {
+    inner: Arc<HandleInner>,
+    marker: PhantomData<fn() -> T>,
+}
+
+struct HandleInner {
+    id: AssetId,
+    type_id: TypeId,
+    dropped: Sender<(TypeId, AssetId)>,
+}
+
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        // The server may have been dropped first, in which case there's nothing to free.
+        let _ = self.dropped.send((self.type_id, self.id));
+    }
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        self.inner.id
+    }
+
+    /// How many handles to the asset there are, including this one.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Handle<{}>({})",
+            std::any::type_name::<T>(),
+            self.inner.id.0
+        )
+    }
+}
+
+/// What [`Asset::create`] can use: the GPU, and the server to load the assets it depends on.
+/// Those are loaded in the background like any other, so may not be ready yet.
+pub struct LoadContext<'a> {
+    /// Only missing when assets that don't use the GPU are created in tests.
+    bananas: Option<&'a Bananas>,
+    server: &'a mut AssetServer,
+    path: &'a str,
+}
+
+impl<'a> LoadContext<'a> {
+    pub fn bananas(&self) -> &Bananas {
+        self.bananas
+            .expect("assets that use the GPU can only be created with one")
+    }
+
+    /// The path of the asset being created.
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Load an asset this one depends on, from a path relative to this one's directory.
+    pub fn load<T: Asset>(&mut self, path: &str) -> Handle<T> {
+        self.load_with(path, T::Settings::default())
+    }
+
+    pub fn load_with<T: Asset>(&mut self, path: &str, settings: T::Settings) -> Handle<T> {
+        let path = match self.path.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", dir, path),
+            None => path.to_string(),
+        };
+        self.server.load_with(&path, settings)
+    }
+}
+
+/// A file read and decoded by a worker, waiting for the main thread to create its asset.
+struct Decoded {
+    id: AssetId,
+    path: String,
+    create: CreateAsset,
+}
+
+/// Creates and stores the asset with the given ID.
+type CreateAsset = Box<dyn FnOnce(&mut LoadContext, AssetId) -> Result<()> + Send>;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Makes the job that reads and decodes an asset's file, so that it can be loaded again.
+type LoadJob = Box<dyn Fn() -> Job>;
+
+/// The assets of one type, behind a trait so that the server can free them without knowing it.
+trait Storage: Any {
+    fn remove(&mut self, id: AssetId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> Storage for HashMap<AssetId, Arc<T>> {
+    fn remove(&mut self, id: AssetId) {
+        HashMap::remove(self, &id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Where an [`AssetServer`] reads files from.
+#[derive(Debug, Clone)]
+pub enum AssetSource {
+    /// Loose files under a directory, for development.
+    Dir(PathBuf),
+    /// One packed file, for release builds.
+    Archive(Arc<Archive>),
+}
+
+impl AssetSource {
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        match self {
+            AssetSource::Dir(dir) => {
+                std::fs::read(dir.join(path)).with_context(|| format!("failed to read {}", path))
+            }
+            AssetSource::Archive(archive) => archive.read(path),
+        }
+    }
+
+    /// When the file at `path` was last modified, if it's a loose file.
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        match self {
+            AssetSource::Dir(dir) => std::fs::metadata(dir.join(path)).ok()?.modified().ok(),
+            AssetSource::Archive(_) => None,
+        }
+    }
+}
+
+/// Loads assets from a directory or archive, see the [module docs](self).
+pub struct AssetServer {
+    source: AssetSource,
+    next_id: u64,
+    storages: HashMap<TypeId, Box<dyn Storage>>,
+    /// The handle of each path loaded, to give out again while it's alive.
+    handles: HashMap<(TypeId, String), Weak<HandleInner>>,
+    paths: HashMap<AssetId, String>,
+    errors: HashMap<AssetId, Error>,
+    loading: HashSet<AssetId>,
+    load_jobs: HashMap<AssetId, LoadJob>,
+    /// Assets loaded before and being loaded again.
+    reloading: HashSet<AssetId>,
+    /// When each asset's file was last modified, if changes are being watched for.
+    modified: Option<HashMap<AssetId, SystemTime>>,
+    last_poll: Instant,
+    /// An `Arc<T>` for each type with a placeholder.
+    placeholders: HashMap<TypeId, Box<dyn Any>>,
+    dropped: Receiver<(TypeId, AssetId)>,
+    dropped_sender: Sender<(TypeId, AssetId)>,
+    jobs: Sender<Job>,
+    decoded: Receiver<Decoded>,
+    decoded_sender: Sender<Decoded>,
+}
+
+impl AssetServer {
+    /// A server loading files under `root`, with a worker thread per core up to 4.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self::with_source(AssetSource::Dir(root.into()))
+    }
+
+    /// A server loading files from an archive, or anywhere else, with a worker thread per core
+    /// up to 4.
+    pub fn with_source(source: AssetSource) -> Self {
+        let workers = thread::available_parallelism().map_or(1, |count| count.get().min(4));
+        Self::with_workers(source, workers)
+    }
+
+    pub fn with_workers(source: AssetSource, workers: usize) -> Self {
+        let (dropped_sender, dropped) = mpsc::channel();
+        let (decoded_sender, decoded) = mpsc::channel();
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        // The workers take turns at the queue, and stop when the server drops its end.
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for index in 0..workers.max(1) {
+            let job_receiver = job_receiver.clone();
+            thread::Builder::new()
+                .name(format!("asset loader {}", index))
+                .spawn(move || loop {
+                    let job = job_receiver.lock().expect("an asset job panicked").recv();
+                    match job {
+                        Result::Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to spawn an asset loader thread");
+        }
+        Self {
+            source,
+            next_id: 0,
+            storages: HashMap::new(),
+            handles: HashMap::new(),
+            paths: HashMap::new(),
+            errors: HashMap::new(),
+            loading: HashSet::new(),
+            load_jobs: HashMap::new(),
+            reloading: HashSet::new(),
+            modified: None,
+            last_poll: Instant::now(),
+            placeholders: HashMap::new(),
+            dropped,
+            dropped_sender,
+            jobs,
+            decoded,
+            decoded_sender,
+        }
+    }
+
+    pub fn source(&self) -> &AssetSource {
+        &self.source
+    }
+
+    /// How often files are checked for changes, however often [`Self::update`] is called.
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Reload assets when their files are modified, checking in [`Self::update`]. Meant for
+    /// development, with the source a directory in the source tree. Archives are never checked.
+    pub fn watch_for_changes(&mut self) {
+        if self.modified.is_none() {
+            self.modified = Some(HashMap::new());
+            self.last_poll = Instant::now() - Self::POLL_INTERVAL;
+        }
+    }
+
+    /// Start loading the asset at `path`, relative to the source and separated by `/`, with the
+    /// default settings.
+    pub fn load<T: Asset>(&mut self, path: &str) -> Handle<T> {
+        self.load_with(path, T::Settings::default())
+    }
+
+    /// Start loading the asset at `path`, or give out another handle to it if it's already
+    /// loaded or loading, in which case `settings` are ignored.
+    pub fn load_with<T: Asset>(&mut self, path: &str, settings: T::Settings) -> Handle<T> {
+        let key = (TypeId::of::<T>(), path.to_string());
+        if let Some(inner) = self.handles.get(&key).and_then(Weak::upgrade) {
+            return Handle {
+                inner,
+                marker: PhantomData,
+            };
+        }
+
+        let handle = self.allocate::<T>();
+        let id = handle.id();
+        self.handles.insert(key, Arc::downgrade(&handle.inner));
+        self.paths.insert(id, path.to_string());
+        self.loading.insert(id);
+
+        let source = self.source.clone();
+        let path = path.to_string();
+        let decoded = self.decoded_sender.clone();
+        let load_job: LoadJob = Box::new(move || {
+            let (source, path, settings) = (source.clone(), path.clone(), settings.clone());
+            let decoded = decoded.clone();
+            Box::new(move || {
+                // A panicking decoder fails the asset rather than leaving it loading forever.
+                let data = panic::catch_unwind(AssertUnwindSafe(|| {
+                    source
+                        .read(&path)
+                        .and_then(|bytes| T::decode(bytes, &path, &settings))
+                }))
+                .unwrap_or_else(|_| Err(anyhow!("{} panicked while decoding", path)));
+                let create: CreateAsset = Box::new(move |context, id| {
+                    let asset = T::create(data?, context)?;
+                    context
+                        .server
+                        .storage_mut::<T>()
+                        .insert(id, Arc::new(asset));
+                    Ok(())
+                });
+                // The server may have been dropped while this was decoding.
+                let _ = decoded.send(Decoded { id, path, create });
+            })
+        });
+        self.jobs
+            .send(load_job())
+            .expect("the asset loader threads stopped");
+        self.load_jobs.insert(id, load_job);
+        handle
+    }
+
+    /// Create the assets that have finished decoding, returning what loaded or failed, and
+    /// free the ones that are no longer referenced. Call this once a frame.
+    pub fn update(&mut self, bananas: &Bananas) -> Vec<AssetEvent> {
+        self.update_with(Some(bananas))
+    }
+
+    fn update_with(&mut self, bananas: Option<&Bananas>) -> Vec<AssetEvent> {
+        self.reload_changed();
+        let mut events = Vec::new();
+        while let Result::Ok(decoded) = self.decoded.try_recv() {
+            events.extend(self.create(bananas, decoded));
+        }
+        self.collect_garbage();
+        events
+    }
+
+    /// Block until everything that's loading has loaded or failed, such as at the end of a
+    /// loading screen.
+    pub fn finish_loading(&mut self, bananas: &Bananas) -> Vec<AssetEvent> {
+        self.finish_loading_with(Some(bananas))
+    }
+
+    fn finish_loading_with(&mut self, bananas: Option<&Bananas>) -> Vec<AssetEvent> {
+        let mut events = self.update_with(bananas);
+        while !self.loading.is_empty() {
+            // Every job sends its result, even if decoding panics, so this doesn't wait forever.
+            // The server holds a sender, so it never fails either.
+            let decoded = self
+                .decoded
+                .recv()
+                .expect("the asset loader threads stopped");
+            events.extend(self.create(bananas, decoded));
+        }
+        events
+    }
+
+    fn create(&mut self, bananas: Option<&Bananas>, decoded: Decoded) -> Option<AssetEvent> {
+        let id = decoded.id;
+        // Assets whose handles were dropped while loading aren't worth creating.
+        let reloading = if self.loading.remove(&id) {
+            false
+        } else if self.reloading.remove(&id) {
+            true
+        } else {
+            return None;
+        };
+        let result = (decoded.create)(
+            &mut LoadContext {
+                bananas,
+                server: self,
+                path: &decoded.path,
+            },
+            id,
+        );
+        match result {
+            Result::Ok(()) if reloading => {
+                log::info!("reloaded {}", decoded.path);
+                self.errors.remove(&id);
+                Some(AssetEvent::Reloaded(id))
+            }
+            Result::Ok(()) => Some(AssetEvent::Loaded(id)),
+            // A reload that fails keeps the asset as it was, so that a half saved file doesn't
+            // break the game.
+            Err(error) if reloading && !self.errors.contains_key(&id) => {
+                log::error!("failed to reload {}: {:#}", decoded.path, error);
+                None
+            }
+            Err(error) => {
+                log::error!("failed to load {}: {:#}", decoded.path, error);
+                self.errors.insert(id, error);
+                (!reloading).then_some(AssetEvent::Failed(id))
+            }
+        }
+    }
+
+    /// Start loading again every asset whose file has changed since it was last checked.
+    fn reload_changed(&mut self) {
+        let modified = match &mut self.modified {
+            Some(modified) if self.last_poll.elapsed() >= Self::POLL_INTERVAL => modified,
+            _ => return,
+        };
+        self.last_poll = Instant::now();
+
+        for (&id, path) in &self.paths {
+            if self.loading.contains(&id) || self.reloading.contains(&id) {
+                continue;
+            }
+            // Editors often replace files on save, so a file can briefly be missing.
+            let time = match self.source.modified(path) {
+                Some(time) => time,
+                None => continue,
+            };
+            // Files are only reloaded once they've been seen, and then changed.
+            if modified.insert(id, time).is_some_and(|last| last != time) {
+                if let Some(load_job) = self.load_jobs.get(&id) {
+                    self.reloading.insert(id);
+                    self.jobs
+                        .send(load_job())
+                        .expect("the asset loader threads stopped");
+                }
+            }
+        }
+    }
+
+    /// How many of the assets loaded from files are still loading.
+    pub fn progress(&self) -> LoadProgress {
+        let loading = self.loading.len();
+        let failed = self.errors.len();
+        LoadProgress {
+            loaded: self.paths.len() - loading - failed,
+            failed,
+            loading,
+        }
+    }
+
+    pub fn state<T>(&self, handle: &Handle<T>) -> LoadState {
+        if self.loading.contains(&handle.id()) {
+            LoadState::Loading
+        } else if self.errors.contains_key(&handle.id()) {
+            LoadState::Failed
+        } else {
+            LoadState::Loaded
+        }
+    }
+
+    /// Add an asset that wasn't loaded from a file.
+    pub fn add<T: 'static>(&mut self, asset: T) -> Handle<T> {
+        let handle = self.allocate::<T>();
+        self.storage_mut::<T>().insert(handle.id(), Arc::new(asset));
+        handle
+    }
+
+    /// The asset, or `None` if it's still loading or failed to load.
+    pub fn get<T: 'static>(&self, handle: &Handle<T>) -> Option<&T> {
+        self.get_arc(handle).map(Arc::as_ref)
+    }
+
+    /// The asset, shared for things that hold on to it such as materials. It's kept alive by
+    /// them after its handles are dropped.
+    pub fn get_arc<T: 'static>(&self, handle: &Handle<T>) -> Option<&Arc<T>> {
+        self.get_by_id(handle.id())
+    }
+
+    fn get_by_id<T: 'static>(&self, id: AssetId) -> Option<&Arc<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<HashMap<AssetId, Arc<T>>>()?
+            .get(&id)
+    }
+
+    /// What to draw in place of assets of type `T` that aren't loaded yet or failed to load,
+    /// such as a small gray texture.
+    pub fn set_placeholder<T: 'static>(&mut self, placeholder: T) {
+        self.placeholders
+            .insert(TypeId::of::<T>(), Box::new(Arc::new(placeholder)));
+    }
+
+    /// The asset if it's loaded, or else the placeholder for its type if there is one.
+    pub fn get_or_placeholder<T: 'static>(&self, handle: &Handle<T>) -> Option<&Arc<T>> {
+        self.get_arc(handle).or_else(|| {
+            self.placeholders
+                .get(&TypeId::of::<T>())?
+                .downcast_ref::<Arc<T>>()
+        })
+    }
+
+    /// Why the asset failed to load, if it did.
+    pub fn error<T>(&self, handle: &Handle<T>) -> Option<&Error> {
+        self.errors.get(&handle.id())
+    }
+
+    /// The path and error of every asset that failed to load and is still referenced.
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.errors.iter().map(|(id, error)| {
+            let path = self.paths.get(id).map_or("", String::as_str);
+            (path, error)
+        })
+    }
+
+    /// The path the asset was loaded from, or `None` if it was added.
+    pub fn path<T>(&self, handle: &Handle<T>) -> Option<&str> {
+        self.paths.get(&handle.id()).map(String::as_str)
+    }
+
+    /// Free every asset whose handles have all been dropped, returning how many were freed.
+    /// This is part of [`Self::update`].
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut freed = 0;
+        while let Result::Ok((type_id, id)) = self.dropped.try_recv() {
+            if let Some(storage) = self.storages.get_mut(&type_id) {
+                storage.remove(id);
+            }
+            self.errors.remove(&id);
+            self.loading.remove(&id);
+            self.reloading.remove(&id);
+            self.load_jobs.remove(&id);
+            if let Some(modified) = &mut self.modified {
+                modified.remove(&id);
+            }
+            if let Some(path) = self.paths.remove(&id) {
+                let key = (type_id, path);
+                // The path may have been loaded again since, under a new handle.
+                if self
+                    .handles
+                    .get(&key)
+                    .is_some_and(|handle| handle.strong_count() == 0)
+                {
+                    self.handles.remove(&key);
+                }
+            }
+            freed += 1;
+        }
+        freed
+    }
+
+    fn allocate<T: 'static>(&mut self) -> Handle<T> {
+        let id = AssetId(self.next_id);
+        self.next_id += 1;
+        Handle {
+            inner: Arc::new(HandleInner {
+                id,
+                type_id: TypeId::of::<T>(),
+                dropped: self.dropped_sender.clone(),
+            }),
+            marker: PhantomData,
+        }
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut HashMap<AssetId, Arc<T>> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(HashMap::<AssetId, Arc<T>>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("assets are stored by their type")
+    }
+}
+
+impl fmt::Debug for AssetServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AssetServer")
+            .field("source", &self.source)
+            .field("paths", &self.paths)
+            .field("errors", &self.errors)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Bind groups from [`Renderer::create_sprite_bind_group`] for textures loaded by an
+/// [`AssetServer`], made from the placeholder until a texture loads and made again whenever it
+/// reloads.
+#[derive(Debug, Default)]
+pub struct SpriteBindGroups {
+    bind_groups: HashMap<AssetId, wgpu::BindGroup>,
+}
+
+impl SpriteBindGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the bind group for `handle`, if there's a texture or placeholder to bind.
+    pub fn insert(
+        &mut self,
+        renderer: &Renderer,
+        device: &wgpu::Device,
+        assets: &AssetServer,
+        handle: &Handle<Texture>,
+    ) {
+        if let Some(texture) = assets.get_or_placeholder(handle) {
+            self.bind_groups.insert(
+                handle.id(),
+                renderer.create_sprite_bind_group(texture, device),
+            );
+        }
+    }
+
+    pub fn get(&self, handle: &Handle<Texture>) -> Option<&wgpu::BindGroup> {
+        self.bind_groups.get(&handle.id())
+    }
+
+    pub fn remove(&mut self, handle: &Handle<Texture>) {
+        self.bind_groups.remove(&handle.id());
+    }
+
+    /// Recreate the bind groups of the textures that loaded or reloaded, given the events from
+    /// [`AssetServer::update`].
+    pub fn update(
+        &mut self,
+        renderer: &Renderer,
+        device: &wgpu::Device,
+        assets: &AssetServer,
+        events: &[AssetEvent],
+    ) {
+        for event in events {
+            let id = event.id();
+            if !self.bind_groups.contains_key(&id) {
+                continue;
+            }
+            if let Some(texture) = assets.get_by_id::<Texture>(id) {
+                self.bind_groups
+                    .insert(id, renderer.create_sprite_bind_group(texture, device));
+            }
+        }
+    }
+}
+
+/// A decoded texture, before it's uploaded.
+#[derive(Debug)]
+pub enum TextureData {
+    Image(image::DynamicImage, TextureOptions),
+    /// From a `.ktx2` or `.dds` file, which only uses the options' sampler.
+    Compressed(CompressedImage, TextureOptions),
+}
+
+impl Asset for Texture {
+    type Settings = TextureOptions;
+    type Data = TextureData;
+
+    fn decode(bytes: Vec<u8>, path: &str, settings: &TextureOptions) -> Result<TextureData> {
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+        Ok(match extension {
+            Some("ktx2") => {
+                TextureData::Compressed(CompressedImage::from_ktx2(&bytes)?, settings.clone())
+            }
+            Some("dds") => {
+                TextureData::Compressed(CompressedImage::from_dds(&bytes)?, settings.clone())
+            }
+            _ => TextureData::Image(load_image(&bytes, path)?, settings.clone()),
+        })
+    }
+
+    fn create(data: TextureData, context: &mut LoadContext) -> Result<Self> {
+        let bananas = context.bananas();
+        match data {
+            TextureData::Image(image, options) => Texture::from_image_with_options(
+                &bananas.device,
+                &bananas.queue,
+                &image,
+                Some(context.path()),
+                &options,
+            ),
+            TextureData::Compressed(image, options) => Texture::from_compressed(
+                &bananas.device,
+                &bananas.queue,
+                &image,
+                Some(context.path()),
+                &options.sampler,
+            ),
+        }
+    }
+}
+
+impl Asset for Shader {
+    type Settings = ();
+    type Data = String;
+
+    fn decode(bytes: Vec<u8>, path: &str, _: &()) -> Result<String> {
+        String::from_utf8(bytes).with_context(|| format!("{} isn't UTF-8", path))
+    }
+
+    fn create(source: String, context: &mut LoadContext) -> Result<Self> {
+        Ok(Shader::from_wgsl(context.path(), source)?)
+    }
+}
+
+/// The bytes of a TrueType or OpenType font, checked to be one, for text rendering to parse.
+#[derive(Debug, Clone)]
+pub struct Font {
+    data: Vec<u8>,
+}
+
+impl Font {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        let tag = data.get(..4).context("a font is at least 4 bytes")?;
+        ensure!(
+            matches!(
+                tag,
+                [0, 1, 0, 0] | b"OTTO" | b"true" | b"ttcf" | b"wOFF" | b"wOF2"
+            ),
+            "not a TrueType or OpenType font"
+        );
+        Ok(Self { data })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Asset for Font {
+    type Settings = ();
+    type Data = Font;
+
+    fn decode(bytes: Vec<u8>, _: &str, _: &()) -> Result<Font> {
+        Font::new(bytes)
+    }
+
+    fn create(font: Font, _: &mut LoadContext) -> Result<Self> {
+        Ok(font)
+    }
+}
+
+/// A texture divided into a grid of equally sized frames, numbered along rows from the top left.
+/// Loaded from a `.sheet` file of `key = value` lines naming the image relative to the sheet,
+/// and the number of columns and rows:
+///
+/// ```text
+/// image = explosion.png
+/// columns = 8
+/// rows = 2
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub texture: Handle<Texture>,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl SpriteSheet {
+    pub fn len(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The region of the texture frame `index` covers, for
+    /// [`crate::renderer::SpriteInstance::with_uv_rect`].
+    pub fn uv_rect(&self, index: u32) -> [f32; 4] {
+        let (column, row) = (index % self.columns, index / self.columns % self.rows);
+        let (width, height) = (1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        let (u, v) = (column as f32 * width, row as f32 * height);
+        [u, v, u + width, v + height]
+    }
+}
+
+/// A parsed `.sheet` file.
+#[derive(Debug, Clone)]
+pub struct SpriteSheetData {
+    image: String,
+    columns: u32,
+    rows: u32,
+    texture: TextureOptions,
+}
+
+impl Asset for SpriteSheet {
+    /// How the sheet's texture is loaded.
+    type Settings = TextureOptions;
+    type Data = SpriteSheetData;
+
+    fn decode(bytes: Vec<u8>, path: &str, settings: &TextureOptions) -> Result<SpriteSheetData> {
+        let source = String::from_utf8(bytes).with_context(|| format!("{} isn't UTF-8", path))?;
+        let mut values = HashMap::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| format!("{}:{}: expected `key = value`", path, index + 1))?;
+            values.insert(key.trim(), value.trim());
+        }
+        let count = |key| -> Result<u32> {
+            let value = values
+                .get(key)
+                .with_context(|| format!("{} has no {}", path, key))?;
+            let count = value
+                .parse()
+                .with_context(|| format!("{} of {} isn't a number", key, path))?;
+            ensure!(count > 0, "{} of {} must be at least 1", key, path);
+            Ok(count)
+        };
+        Ok(SpriteSheetData {
+            image: values
+                .get("image")
+                .with_context(|| format!("{} has no image", path))?
+                .to_string(),
+            columns: count("columns")?,
+            rows: count("rows")?,
+            texture: settings.clone(),
+        })
+    }
+
+    fn create(data: SpriteSheetData, context: &mut LoadContext) -> Result<Self> {
+        Ok(SpriteSheet {
+            texture: context.load_with(&data.image, data.texture),
+            columns: data.columns,
+            rows: data.rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of files in the temporary directory that's removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "papercut-assets-{}-{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            for (path, contents) in files {
+                std::fs::write(dir.join(path), contents).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn server(&self) -> AssetServer {
+            AssetServer::with_workers(AssetSource::Dir(self.0.clone()), 2)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A file's text, which fails to load if it's empty, and panics decoding if it's `panic`.
+    #[derive(Debug, PartialEq)]
+    struct Text(String);
+
+    impl Asset for Text {
+        type Settings = ();
+        type Data = String;
+
+        fn decode(bytes: Vec<u8>, path: &str, _: &()) -> Result<String> {
+            let text = String::from_utf8(bytes).with_context(|| format!("{} isn't UTF-8", path))?;
+            ensure!(!text.is_empty(), "{} is empty", path);
+            assert_ne!(text, "panic", "the decoder of {} panicked", path);
+            Ok(text)
+        }
+
+        fn create(text: String, _: &mut LoadContext) -> Result<Self> {
+            Ok(Text(text))
+        }
+    }
+
+    #[test]
+    fn loading_a_path_again_shares_the_asset() {
+        let dir = TempDir::new("shared", &[("a.txt", "a"), ("b.txt", "b")]);
+        let mut server = dir.server();
+        let a = server.load::<Text>("a.txt");
+        let again = server.load::<Text>("a.txt");
+        let b = server.load::<Text>("b.txt");
+        assert_eq!(a, again);
+        assert_ne!(a, b);
+        assert_eq!(a.ref_count(), 2);
+        assert_eq!(server.progress().total(), 2);
+        // The same path as another type is a different asset.
+        let font = server.load::<Font>("a.txt");
+        assert_ne!(font.id(), a.id());
+
+        let mut events = server.finish_loading_with(None);
+        events.sort_by_key(AssetEvent::id);
+        assert_eq!(
+            events,
+            [
+                AssetEvent::Loaded(a.id()),
+                AssetEvent::Loaded(b.id()),
+                AssetEvent::Failed(font.id()),
+            ]
+        );
+        assert_eq!(server.get(&again), Some(&Text("a".to_string())));
+        assert_eq!(server.path(&a), Some("a.txt"));
+
+        // Loaded assets are shared too, without loading them again.
+        assert_eq!(server.load::<Text>("a.txt"), a);
+        assert_eq!(server.finish_loading_with(None), []);
+    }
+
+    #[test]
+    fn assets_are_freed_once_their_handles_are_dropped() {
+        let dir = TempDir::new("freed", &[("a.txt", "a")]);
+        let mut server = dir.server();
+        let a = server.load::<Text>("a.txt");
+        let clone = a.clone();
+        server.finish_loading_with(None);
+        let id = a.id();
+
+        drop(clone);
+        assert_eq!(server.collect_garbage(), 0);
+        assert!(server.get(&a).is_some());
+
+        drop(a);
+        assert_eq!(server.collect_garbage(), 1);
+        assert!(server.get_by_id::<Text>(id).is_none());
+        assert_eq!(server.progress(), LoadProgress::default());
+
+        // Loading the path again loads it from the file again, under a new handle.
+        let a = server.load::<Text>("a.txt");
+        assert_ne!(a.id(), id);
+        assert_eq!(
+            server.finish_loading_with(None),
+            [AssetEvent::Loaded(a.id())]
+        );
+        assert_eq!(server.get(&a), Some(&Text("a".to_string())));
+    }
+
+    #[test]
+    fn assets_dropped_while_loading_are_never_created() {
+        let dir = TempDir::new("dropped", &[("a.txt", "a")]);
+        let mut server = dir.server();
+        let id = server.load::<Text>("a.txt").id();
+        assert_eq!(server.collect_garbage(), 1);
+        assert!(server.progress().is_done());
+        // The worker still decodes the file, but the asset isn't created from it.
+        let decoded = server
+            .decoded
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(server.create(None, decoded), None);
+        assert!(server.get_by_id::<Text>(id).is_none());
+    }
+
+    #[test]
+    fn progress_counts_each_state() {
+        let dir = TempDir::new("progress", &[("a.txt", "a"), ("empty.txt", "")]);
+        let mut server = dir.server();
+        let _handles = (
+            server.load::<Text>("a.txt"),
+            server.load::<Text>("empty.txt"),
+            server.load::<Text>("missing.txt"),
+        );
+        // Added assets aren't loaded, so aren't counted.
+        let _added = server.add(Text("added".to_string()));
+        let progress = server.progress();
+        assert_eq!(
+            progress,
+            LoadProgress {
+                loaded: 0,
+                failed: 0,
+                loading: 3,
+            }
+        );
+        assert_eq!(progress.fraction(), 0.0);
+        assert!(!progress.is_done());
+
+        server.finish_loading_with(None);
+        let progress = server.progress();
+        assert_eq!(
+            progress,
+            LoadProgress {
+                loaded: 1,
+                failed: 2,
+                loading: 0,
+            }
+        );
+        assert_eq!(progress.fraction(), 1.0);
+        assert!(progress.is_done());
+    }
+
+    #[test]
+    fn errors_are_kept_for_each_asset() {
+        let dir = TempDir::new("errors", &[("a.txt", "a"), ("empty.txt", "")]);
+        let mut server = dir.server();
+        let a = server.load::<Text>("a.txt");
+        let empty = server.load::<Text>("empty.txt");
+        let missing = server.load::<Text>("missing.txt");
+        assert_eq!(server.state(&a), LoadState::Loading);
+
+        let events = server.finish_loading_with(None);
+        assert!(events.contains(&AssetEvent::Failed(empty.id())));
+        assert!(events.contains(&AssetEvent::Failed(missing.id())));
+        assert_eq!(server.state(&a), LoadState::Loaded);
+        assert_eq!(server.state(&empty), LoadState::Failed);
+        assert_eq!(server.state(&missing), LoadState::Failed);
+        assert!(server.error(&a).is_none());
+        assert!(server.get(&empty).is_none());
+        assert_eq!(
+            server.error(&empty).unwrap().to_string(),
+            "empty.txt is empty"
+        );
+        assert_eq!(
+            server.error(&missing).unwrap().to_string(),
+            "failed to read missing.txt"
+        );
+
+        let mut paths = server.errors().map(|(path, _)| path).collect::<Vec<_>>();
+        paths.sort_unstable();
+        assert_eq!(paths, ["empty.txt", "missing.txt"]);
+
+        // Errors go with their assets.
+        drop(empty);
+        server.collect_garbage();
+        assert_eq!(server.errors().count(), 1);
+    }
+
+    #[test]
+    fn decoders_that_panic_fail_the_asset() {
+        let dir = TempDir::new("panic", &[("a.txt", "a"), ("panic.txt", "panic")]);
+        // With one worker, it has to survive the panic to load the next file.
+        let mut server = AssetServer::with_workers(AssetSource::Dir(dir.0.clone()), 1);
+        let panics = server.load::<Text>("panic.txt");
+        let a = server.load::<Text>("a.txt");
+
+        let mut events = server.finish_loading_with(None);
+        events.sort_by_key(AssetEvent::id);
+        assert_eq!(
+            events,
+            [AssetEvent::Failed(panics.id()), AssetEvent::Loaded(a.id())]
+        );
+        assert_eq!(server.state(&panics), LoadState::Failed);
+        assert_eq!(
+            server.error(&panics).unwrap().to_string(),
+            "panic.txt panicked while decoding"
+        );
+        assert_eq!(server.get(&a), Some(&Text("a".to_string())));
+    }
+}
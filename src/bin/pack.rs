@@ -0,0 +1,36 @@
+//! Packs a directory of assets into an archive for release builds, or lists an archive.
+//!
+//! ```text
+//! pack <assets directory> <archive>
+//! pack --list <archive>
+//! ```
+
+use anyhow::*;
+use papercut::archive::{Archive, ArchiveBuilder};
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--list", archive] => {
+            let archive = Archive::open(archive)?;
+            for path in archive.paths() {
+                println!("{:>10}  {}", archive.len_of(path).unwrap_or_default(), path);
+            }
+        }
+        [dir, archive] => {
+            let mut builder = ArchiveBuilder::new();
+            builder.add_dir(dir)?;
+            builder.write_to_file(archive)?;
+            let size = std::fs::metadata(archive)?.len();
+            println!(
+                "packed {} files from {} into {} ({} bytes)",
+                builder.len(),
+                dir,
+                archive,
+                size
+            );
+        }
+        _ => bail!("usage: pack <assets directory> <archive>\n       pack --list <archive>"),
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use glam::{Mat4, Vec2, Vec3};
+use lyon::geom::{point, Box2D};
+
+use crate::{
+    renderer::ViewProjectionUniform,
+    transform::Transform2D,
+    uniforms::{FrameUniforms, UniformOffset},
+};
+
+/// The part of a render target a camera draws into, as fractions of the target's size from its
+/// top left corner, so that it keeps its place when the target is resized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// The whole target.
+    pub const FULL: Self = Self::new(0.0, 0.0, 1.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The rectangle of pixels this covers in a target of `width` by `height`, as x, y, width
+    /// and height. It's kept inside the target and at least a pixel wide and high.
+    pub(crate) fn to_pixels(self, width: u32, height: u32) -> [u32; 4] {
+        let span = |start: f32, length: f32, size: u32| {
+            let to_pixel = |fraction: f32| (fraction * size as f32).round().max(0.0) as u32;
+            let first = to_pixel(start).min(size - 1);
+            let last = to_pixel(start + length).clamp(first + 1, size);
+            (first, last - first)
+        };
+        let (x, width) = span(self.x, self.width, width.max(1));
+        let (y, height) = span(self.y, self.height, height.max(1));
+        [x, y, width, height]
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// What a camera clears its viewport to before drawing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearMode {
+    /// Clear the color and depth, for the first camera to draw into an area.
+    Color(wgpu::Color),
+    /// Keep the color but clear the depth, so that everything drawn is in front of what earlier
+    /// cameras drew, as for a HUD.
+    Depth,
+    /// Draw over and among what's already there.
+    None,
+}
+
+/// Which layers a camera draws, or which layers something drawn is on, as a set of 32 bits.
+/// Things are drawn by a camera when they have a layer in common. This is unrelated to the
+/// order things are drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerMask(pub u32);
+
+impl LayerMask {
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(u32::MAX);
+    /// The layer everything is on unless it's moved.
+    pub const DEFAULT: Self = Self::layer(0);
+
+    /// Only layer `index`, which must be less than 32.
+    pub const fn layer(index: u32) -> Self {
+        assert!(index < 32, "there are only 32 layers");
+        Self(1 << index)
+    }
+
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Whether the masks have a layer in common.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for LayerMask {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// An orthographic view of the world, `width` by `height` world units across with `position`
+/// at its bottom left corner, drawn into a viewport of a render target by
+/// [`crate::renderer::Renderer::begin_camera`]. Several cameras can draw into the same frame,
+/// for split screen, a HUD over the world, or picture in picture.
+#[derive(Debug)]
+pub struct Camera {
+    width: f32,
+    height: f32,
+    projection: Mat4,
+    pub position: Vec2,
+    pub viewport: Viewport,
+    pub clear: ClearMode,
+    pub layers: LayerMask,
+    /// Where this frame's view projection and clear color were pushed.
+    uniforms: Option<(UniformOffset, UniformOffset)>,
+}
+
+impl Camera {
+    /// A camera drawing into the whole target, clearing it to black and drawing every layer.
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            projection: orthographic(width, height),
+            position: Vec2::ZERO,
+            viewport: Viewport::FULL,
+            clear: ClearMode::Color(wgpu::Color::BLACK),
+            layers: LayerMask::ALL,
+            uniforms: None,
+        }
+    }
+
+    pub fn with_position(mut self, position: Vec2) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// The viewport is stretched to fit, so its aspect ratio should match the camera's.
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_clear(mut self, clear: ClearMode) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+        self.projection = orthographic(width, height);
+    }
+
+    /// The transform from the camera's space, with `(0, 0)` at its bottom left corner, to the
+    /// world's.
+    pub fn transform(&self) -> Transform2D {
+        Transform2D::from_translation(self.position)
+    }
+
+    /// The part of the world the camera sees.
+    pub fn visible_rect(&self) -> Box2D<f32> {
+        self.transform()
+            .transform_rect(Box2D::new(point(0.0, 0.0), point(self.width, self.height)))
+    }
+
+    /// The inverse of [`Self::transform`], looking along z from `z = -1`, so that the world at
+    /// `z = 0` is inside the projection's depth range.
+    pub fn get_view(&self) -> Mat4 {
+        Mat4::from_translation(Vec3::Z) * self.transform().inverse().to_mat4()
+    }
+
+    pub fn get_projection(&self) -> Mat4 {
+        self.projection
+    }
+
+    /// Push the view projection and clear color to this frame's uniforms. Must be called every
+    /// frame the camera draws, before the uniforms are uploaded.
+    pub fn prepare(&mut self, uniforms: &mut FrameUniforms) {
+        let view_projection = uniforms.push(&ViewProjectionUniform {
+            view: self.get_view().to_cols_array_2d(),
+            projection: self.projection.to_cols_array_2d(),
+        });
+        let color = match self.clear {
+            ClearMode::Color(color) => [color.r, color.g, color.b, color.a].map(|c| c as f32),
+            ClearMode::Depth | ClearMode::None => [0.0; 4],
+        };
+        self.uniforms = Some((view_projection, uniforms.push(&color)));
+    }
+
+    /// Where the view projection, for group 0 of every pipeline, and the clear color, for the
+    /// pipelines that clear a viewport, were pushed.
+    pub(crate) fn uniform_offsets(&self) -> (UniformOffset, UniformOffset) {
+        self.uniforms
+            .expect("a camera was drawn with before being prepared")
+    }
+}
+
+fn orthographic(width: f32, height: f32) -> Mat4 {
+    glam::Mat4::orthographic_lh(0.0, width, 0.0, height, -1.0, 1.0)
+}
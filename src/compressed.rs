@@ -0,0 +1,500 @@
+//! Block-compressed images loaded from KTX2 and DDS containers, with their mip levels, for
+//! [`crate::texture::Texture::from_compressed`]. Only BC1 to BC7 are supported, which every
+//! desktop GPU can sample directly, and which are decompressed on the CPU where it can't.
+
+use anyhow::*;
+
+/// A 2D image of 4x4 pixel blocks in a BC format, and its pre-built mip levels.
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    /// Level 0, the full size, first.
+    levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    /// An image from the blocks of each mip level, level 0 first, checking they're the size
+    /// `format`, `width` and `height` call for.
+    pub fn new(
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        levels: Vec<Vec<u8>>,
+    ) -> Result<Self> {
+        let block_size =
+            block_size(format).with_context(|| format!("{:?} isn't a BC format", format))?;
+        ensure!(width > 0 && height > 0, "a compressed image can't be empty");
+        ensure!(
+            !levels.is_empty(),
+            "a compressed image needs at least one level"
+        );
+        ensure!(
+            levels.len() as u32 <= crate::texture::mip_level_count(width, height),
+            "a {}x{} image can't have {} mip levels",
+            width,
+            height,
+            levels.len()
+        );
+        for (level, bytes) in levels.iter().enumerate() {
+            let expected = level_length(width, height, level as u32, block_size)?;
+            let (width, height) = level_size(width, height, level as u32);
+            ensure!(
+                bytes.len() == expected,
+                "expected {} bytes for mip level {} of a {}x{} {:?} image, found {}",
+                expected,
+                level,
+                width,
+                height,
+                format,
+                bytes.len()
+            );
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// Parse a KTX2 file holding one 2D image without supercompression.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|err| anyhow!("invalid KTX2: {}", err))?;
+        let header = reader.header();
+        ensure!(
+            header.supercompression_scheme.is_none(),
+            "KTX2 supercompression ({:?}) isn't supported",
+            header.supercompression_scheme
+        );
+        ensure!(
+            header.pixel_depth <= 1 && header.layer_count <= 1 && header.face_count == 1,
+            "only 2D KTX2 images are supported, not 3D, arrays or cube maps"
+        );
+        let format = match header.format {
+            Some(format) => ktx2_format(format)
+                .with_context(|| format!("KTX2 format {:?} isn't a BC format", format))?,
+            None => bail!("KTX2 images without a format aren't supported"),
+        };
+        let levels = reader.levels().map(<[u8]>::to_vec).collect();
+        Self::new(format, header.pixel_width, header.pixel_height, levels)
+    }
+
+    /// Parse a DDS file holding one 2D image, with either a DX10 header or a DXT1, DXT3 or
+    /// DXT5 FourCC.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+        let dds = ddsfile::Dds::read(bytes).context("invalid DDS")?;
+        ensure!(
+            dds.get_depth() <= 1 && dds.get_num_array_layers() <= 1,
+            "only 2D DDS images are supported, not 3D, arrays or cube maps"
+        );
+        let format = if let Some(format) = dds.get_dxgi_format() {
+            dxgi_format(format)
+                .with_context(|| format!("DDS format {:?} isn't a BC format", format))?
+        } else if let Some(format) = dds.get_d3d_format() {
+            d3d_format(format)
+                .with_context(|| format!("DDS format {:?} isn't a BC format", format))?
+        } else {
+            bail!("DDS image has no format")
+        };
+
+        let (width, height) = (dds.get_width(), dds.get_height());
+        let level_count = dds.get_num_mipmap_levels().max(1);
+        ensure!(width > 0 && height > 0, "a compressed image can't be empty");
+        ensure!(
+            level_count <= crate::texture::mip_level_count(width, height),
+            "a {}x{} image can't have {} mip levels",
+            width,
+            height,
+            level_count
+        );
+        let block_size = block_size(format).unwrap_or_default();
+        let mut data = dds.data.as_slice();
+        let mut levels = Vec::new();
+        for level in 0..level_count {
+            let length = level_length(width, height, level, block_size)?;
+            ensure!(data.len() >= length, "DDS mip level {} is truncated", level);
+            let (bytes, rest) = data.split_at(length);
+            levels.push(bytes.to_vec());
+            data = rest;
+        }
+        Self::new(format, width, height, levels)
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn levels(&self) -> &[Vec<u8>] {
+        &self.levels
+    }
+
+    /// The width and height of mip `level`.
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        level_size(self.width, self.height, level)
+    }
+
+    pub fn block_size(&self) -> u32 {
+        block_size(self.format).unwrap_or_default()
+    }
+
+    /// Whether the device can create a texture in this format and size. BC textures need
+    /// `Features::TEXTURE_COMPRESSION_BC`, and for level 0 to be a whole number of blocks.
+    pub fn is_supported(&self, device: &wgpu::Device) -> bool {
+        device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+            && self.width % 4 == 0
+            && self.height % 4 == 0
+    }
+
+    /// The uncompressed format [`Self::decompress`] decodes into: `Rgba8UnormSrgb` for the
+    /// sRGB formats, `Rgba8Snorm` for the signed BC4 and BC5 formats, and `Rgba8Unorm` for the
+    /// rest, so that they're sampled the same as when compressed. BC6H is clamped to 0 to 1.
+    pub fn decompressed_format(&self) -> wgpu::TextureFormat {
+        use wgpu::TextureFormat as F;
+
+        match self.format {
+            F::Bc4RSnorm | F::Bc5RgSnorm => F::Rgba8Snorm,
+            format if format.describe().srgb => F::Rgba8UnormSrgb,
+            _ => F::Rgba8Unorm,
+        }
+    }
+
+    /// Decode mip `level` into RGBA8 pixels, in [`Self::decompressed_format`].
+    pub fn decompress(&self, level: u32) -> Result<Vec<u8>> {
+        use wgpu::TextureFormat as F;
+
+        let (width, height) = self.level_size(level);
+        let blocks = self
+            .levels
+            .get(level as usize)
+            .with_context(|| format!("the image has no mip level {}", level))?;
+        match self.format {
+            F::Bc4RSnorm => return Ok(decompress_snorm(blocks, width, height, 1)),
+            F::Bc5RgSnorm => return Ok(decompress_snorm(blocks, width, height, 2)),
+            _ => {}
+        }
+        let mut pixels = vec![0u32; width as usize * height as usize];
+        let (width, height) = (width as usize, height as usize);
+        let decode = match self.format {
+            F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => texture2ddecoder::decode_bc1a,
+            F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => texture2ddecoder::decode_bc2,
+            F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => texture2ddecoder::decode_bc3,
+            F::Bc4RUnorm => texture2ddecoder::decode_bc4,
+            F::Bc5RgUnorm => texture2ddecoder::decode_bc5,
+            F::Bc6hRgbUfloat => texture2ddecoder::decode_bc6_unsigned,
+            F::Bc6hRgbSfloat => texture2ddecoder::decode_bc6_signed,
+            F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => texture2ddecoder::decode_bc7,
+            format => bail!("{:?} can't be decompressed on the CPU", format),
+        };
+        decode(blocks, width, height, &mut pixels)
+            .map_err(|err| anyhow!("failed to decompress {:?}: {}", self.format, err))?;
+
+        // The decoder packs pixels as BGRA.
+        Ok(pixels
+            .into_iter()
+            .flat_map(|pixel| {
+                let [b, g, r, a] = pixel.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect())
+    }
+}
+
+/// Decode signed BC4, with one channel, or BC5, with two, into RGBA8 snorm pixels. Each channel
+/// is an 8 byte block, like BC3's alpha but with signed endpoints.
+fn decompress_snorm(blocks: &[u8], width: u32, height: u32, channels: usize) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    // Blue is zero and alpha is one, as when sampling the compressed formats.
+    let mut pixels = [0, 0, 0, i8::MAX as u8].repeat(width * height);
+    let blocks_wide = width.div_ceil(4);
+    for (index, block) in blocks.chunks_exact(8 * channels).enumerate() {
+        let (block_x, block_y) = (4 * (index % blocks_wide), 4 * (index / blocks_wide));
+        for channel in 0..channels {
+            let values = decode_snorm_channel(&block[8 * channel..8 * channel + 8]);
+            for (texel, value) in values.into_iter().enumerate() {
+                let (x, y) = (block_x + texel % 4, block_y + texel / 4);
+                if x < width && y < height {
+                    pixels[4 * (y * width + x) + channel] = value as u8;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// The 16 values of a signed BC4 channel block, in row order.
+fn decode_snorm_channel(block: &[u8]) -> [i8; 16] {
+    // -128 means the same as -127.
+    let endpoint = |byte: u8| (byte as i8).max(-i8::MAX) as f32;
+    let (a, b) = (endpoint(block[0]), endpoint(block[1]));
+    let mut palette = [a, b, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    if a > b {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i) as f32 * a + i as f32 * b) / 7.0;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i) as f32 * a + i as f32 * b) / 5.0;
+        }
+        palette[6] = -i8::MAX as f32;
+        palette[7] = i8::MAX as f32;
+    }
+
+    let mut indices = u64::from_le_bytes(block[..8].try_into().unwrap()) >> 16;
+    let mut values = [0; 16];
+    for value in &mut values {
+        *value = palette[(indices & 7) as usize].round() as i8;
+        indices >>= 3;
+    }
+    values
+}
+
+fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let size = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+    (size(width), size(height))
+}
+
+/// The bytes of mip `level` of a `width` by `height` image, or an error if it's too big to hold.
+fn level_length(width: u32, height: u32, level: u32, block_size: u32) -> Result<usize> {
+    let (width, height) = level_size(width, height, level);
+    (width.div_ceil(4) as u64)
+        .checked_mul(height.div_ceil(4) as u64)
+        .and_then(|blocks| blocks.checked_mul(block_size as u64))
+        .and_then(|length| usize::try_from(length).ok())
+        .with_context(|| format!("a {}x{} compressed image is too big", width, height))
+}
+
+/// The bytes in each 4x4 block of a BC format.
+fn block_size(format: wgpu::TextureFormat) -> Option<u32> {
+    use wgpu::TextureFormat as F;
+
+    match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb | F::Bc4RUnorm | F::Bc4RSnorm => Some(8),
+        F::Bc2RgbaUnorm
+        | F::Bc2RgbaUnormSrgb
+        | F::Bc3RgbaUnorm
+        | F::Bc3RgbaUnormSrgb
+        | F::Bc5RgUnorm
+        | F::Bc5RgSnorm
+        | F::Bc6hRgbUfloat
+        | F::Bc6hRgbSfloat
+        | F::Bc7RgbaUnorm
+        | F::Bc7RgbaUnormSrgb => Some(16),
+        _ => None,
+    }
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as K;
+    use wgpu::TextureFormat as F;
+
+    Some(match format {
+        K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        K::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        K::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        K::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        K::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        K::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        K::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        K::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        K::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        K::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        K::BC6H_SFLOAT_BLOCK => F::Bc6hRgbSfloat,
+        K::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        K::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as D;
+    use wgpu::TextureFormat as F;
+
+    Some(match format {
+        D::BC1_UNorm => F::Bc1RgbaUnorm,
+        D::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+        D::BC2_UNorm => F::Bc2RgbaUnorm,
+        D::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+        D::BC3_UNorm => F::Bc3RgbaUnorm,
+        D::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+        D::BC4_UNorm => F::Bc4RUnorm,
+        D::BC4_SNorm => F::Bc4RSnorm,
+        D::BC5_UNorm => F::Bc5RgUnorm,
+        D::BC5_SNorm => F::Bc5RgSnorm,
+        D::BC6H_UF16 => F::Bc6hRgbUfloat,
+        D::BC6H_SF16 => F::Bc6hRgbSfloat,
+        D::BC7_UNorm => F::Bc7RgbaUnorm,
+        D::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// DDS files without a DX10 header are assumed to be sRGB, as they usually hold colors.
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<wgpu::TextureFormat> {
+    use ddsfile::D3DFormat as D;
+    use wgpu::TextureFormat as F;
+
+    Some(match format {
+        D::DXT1 => F::Bc1RgbaUnormSrgb,
+        D::DXT3 => F::Bc2RgbaUnormSrgb,
+        D::DXT5 => F::Bc3RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BC4 block of endpoints `a` and `b`, with every texel using palette index `index`.
+    fn bc4_block(a: u8, b: u8, index: u64) -> [u8; 8] {
+        let indices = (0..16).fold(0u64, |indices, texel| indices | index << (3 * texel));
+        let mut block = [0; 8];
+        block[0] = a;
+        block[1] = b;
+        block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        block
+    }
+
+    #[test]
+    fn decompresses_signed_bc4() {
+        let (a, b) = (100i8 as u8, -128i8 as u8);
+        let blocks = [bc4_block(a, b, 0), bc4_block(a, b, 1), bc4_block(a, b, 2)].concat();
+        // Three blocks wide, the last only partly used.
+        let image =
+            CompressedImage::new(wgpu::TextureFormat::Bc4RSnorm, 10, 4, vec![blocks]).unwrap();
+        assert_eq!(image.decompressed_format(), wgpu::TextureFormat::Rgba8Snorm);
+        let pixels = image.decompress(0).unwrap();
+        assert_eq!(pixels.len(), 10 * 4 * 4);
+        let pixel = |x: usize, y: usize| {
+            let i = 4 * (y * 10 + x);
+            [0, 1, 2, 3].map(|c| pixels[i + c] as i8)
+        };
+        assert_eq!(pixel(0, 0), [100, 0, 0, 127]);
+        // -128 clamps to -127.
+        assert_eq!(pixel(5, 3), [-127, 0, 0, 127]);
+        // 6/7 of 100 and 1/7 of -127.
+        assert_eq!(pixel(9, 3), [68, 0, 0, 127]);
+    }
+
+    #[test]
+    fn decompresses_signed_bc5() {
+        let blocks = [bc4_block(0, 50, 6), bc4_block(0, 50, 7)].concat();
+        let image =
+            CompressedImage::new(wgpu::TextureFormat::Bc5RgSnorm, 4, 4, vec![blocks]).unwrap();
+        let pixels = image.decompress(0).unwrap();
+        // With a <= b, indices 6 and 7 are -1 and 1.
+        for pixel in pixels.chunks_exact(4) {
+            assert_eq!(
+                pixel.iter().map(|&c| c as i8).collect::<Vec<_>>(),
+                [-127, 127, 0, 127]
+            );
+        }
+    }
+
+    #[test]
+    fn decompresses_unsigned_bc4() {
+        let image = CompressedImage::new(
+            wgpu::TextureFormat::Bc4RUnorm,
+            4,
+            4,
+            vec![bc4_block(200, 10, 0).to_vec()],
+        )
+        .unwrap();
+        assert_eq!(image.decompressed_format(), wgpu::TextureFormat::Rgba8Unorm);
+        let pixels = image.decompress(0).unwrap();
+        assert_eq!(&pixels[..4], [200, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rejects_levels_of_the_wrong_size() {
+        let error =
+            CompressedImage::new(wgpu::TextureFormat::Bc1RgbaUnorm, 8, 8, vec![vec![0; 16]])
+                .unwrap_err();
+        assert!(error.to_string().contains("expected 32 bytes"));
+    }
+
+    /// A DDS file with a DX10 header, and `data` after it.
+    fn dds_file(
+        format: ddsfile::DxgiFormat,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height: 4,
+            width: 4,
+            depth: None,
+            format,
+            // Any count above 1 sets the header's flag for it.
+            mipmap_levels: Some(2),
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown,
+        })
+        .unwrap();
+        // Set the header directly, as a file could, rather than through checked constructors.
+        dds.header.width = width;
+        dds.header.height = height;
+        dds.header.mip_map_count = Some(mip_levels);
+        dds.data = data.to_vec();
+        let mut file = Vec::new();
+        dds.write(&mut file).unwrap();
+        file
+    }
+
+    #[test]
+    fn loads_dds() {
+        let blocks = [[1; 8], [2; 8], [3; 8]].concat();
+        let file = dds_file(ddsfile::DxgiFormat::BC1_UNorm, 8, 4, 2, &blocks);
+        let image = CompressedImage::from_dds(&file).unwrap();
+        assert_eq!(image.format(), wgpu::TextureFormat::Bc1RgbaUnorm);
+        assert_eq!((image.width(), image.height()), (8, 4));
+        assert_eq!(
+            image.levels(),
+            [blocks[..16].to_vec(), blocks[16..].to_vec()]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_dds() {
+        let file = dds_file(ddsfile::DxgiFormat::BC1_UNorm, 8, 8, 1, &[0; 24]);
+        let error = CompressedImage::from_dds(&file).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_oversized_dds_headers() {
+        let file = dds_file(ddsfile::DxgiFormat::BC3_UNorm, 65536, 65536, 1, &[0; 16]);
+        let error = CompressedImage::from_dds(&file).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+
+        let file = dds_file(
+            ddsfile::DxgiFormat::BC3_UNorm,
+            u32::MAX,
+            u32::MAX,
+            1,
+            &[0; 16],
+        );
+        assert!(CompressedImage::from_dds(&file).is_err());
+
+        let file = dds_file(ddsfile::DxgiFormat::BC3_UNorm, 4, 4, 33, &[0; 16]);
+        let error = CompressedImage::from_dds(&file).unwrap_err();
+        assert!(error.to_string().contains("mip levels"), "{}", error);
+    }
+}
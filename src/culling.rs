@@ -0,0 +1,582 @@
+//! Skipping what cameras can't see. The renderer skips whole batches outside the current
+//! camera's [`Camera::visible_rect`] by itself, and [`Batch::prepare_culled`] uploads only the
+//! instances each camera sees. Content that rarely moves can go in a [`StaticBatch`] instead,
+//! which indexes every instance in a [`SpatialHash`] so that culling it doesn't visit them all.
+
+use std::collections::HashMap;
+
+use glam::{Affine2, Vec2};
+use lyon::geom::{point, Box2D};
+
+use crate::{
+    camera::{Camera, LayerMask},
+    renderer::{Batch, Instance, ShapeInstance, SpriteInstance, QUAD_BOUNDS},
+    vector::VectorGraphic,
+};
+
+/// The range of each coefficient of a set of transforms, to bound everything they place.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TransformRange {
+    min: [[f32; 2]; 3],
+    max: [[f32; 2]; 3],
+}
+
+impl TransformRange {
+    /// The range of `transforms`, or `None` if there are none.
+    pub(crate) fn of(transforms: impl IntoIterator<Item = Affine2>) -> Option<Self> {
+        let mut transforms = transforms.into_iter().map(|t| t.to_cols_array_2d());
+        let first = transforms.next()?;
+        Some(transforms.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |mut range, transform| {
+                for (column, values) in transform.iter().enumerate() {
+                    for (row, &value) in values.iter().enumerate() {
+                        range.min[column][row] = range.min[column][row].min(value);
+                        range.max[column][row] = range.max[column][row].max(value);
+                    }
+                }
+                range
+            },
+        ))
+    }
+
+    /// A box holding `local` placed by every transform in the range. It's exact for a single
+    /// transform, and looser the more the transforms differ in rotation and scale.
+    pub(crate) fn bounds(&self, local: Box2D<f32>) -> Box2D<f32> {
+        let local_min = [local.min.x, local.min.y];
+        let local_max = [local.max.x, local.max.y];
+        let mut min = Vec2::from(self.min[2]);
+        let mut max = Vec2::from(self.max[2]);
+        // Each coordinate is a sum of a coefficient times a local coordinate, so its range is the
+        // sum of the ranges of the products.
+        for axis in 0..2 {
+            for row in 0..2 {
+                let products = [
+                    self.min[axis][row] * local_min[axis],
+                    self.min[axis][row] * local_max[axis],
+                    self.max[axis][row] * local_min[axis],
+                    self.max[axis][row] * local_max[axis],
+                ];
+                min[row] += products.into_iter().fold(f32::INFINITY, f32::min);
+                max[row] += products.into_iter().fold(f32::NEG_INFINITY, f32::max);
+            }
+        }
+        Box2D::new(point(min.x, min.y), point(max.x, max.y))
+    }
+}
+
+/// Identifies an item in a [`SpatialHash`]. Ids of removed items never refer to a different item
+/// later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpatialId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+struct SpatialSlot<T> {
+    generation: u32,
+    item: Option<(Box2D<f32>, T)>,
+}
+
+/// Items with bounding boxes, found by the boxes they intersect. The world is split into square
+/// cells, and each item is listed in every cell its box touches, so queries only look at the
+/// items in the cells they touch.
+///
+/// Cells should be a few times bigger than most items. Items much bigger than a cell are listed
+/// in many cells, which makes them slower to add, move and remove, and items touching more than
+/// [`MAX_ITEM_CELLS`] are checked by every query instead.
+#[derive(Debug)]
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<u32>>,
+    /// The slots of items too big to list in their cells.
+    oversized: Vec<u32>,
+    slots: Vec<SpatialSlot<T>>,
+    free: Vec<u32>,
+}
+
+/// The most cells an item of a [`SpatialHash`] is listed in.
+pub const MAX_ITEM_CELLS: u64 = 256;
+
+/// The first and last cells a box touches.
+#[derive(Debug, Clone, Copy)]
+struct CellRange {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl CellRange {
+    fn count(&self) -> u64 {
+        let span = |min: i32, max: i32| (max as i64 - min as i64 + 1).max(0) as u64;
+        span(self.min.0, self.max.0).saturating_mul(span(self.min.1, self.max.1))
+    }
+
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+
+    fn cells(self) -> impl Iterator<Item = (i32, i32)> {
+        (self.min.1..=self.max.1).flat_map(move |y| (self.min.0..=self.max.0).map(move |x| (x, y)))
+    }
+}
+
+impl<T> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "a spatial hash's cells need a size");
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            oversized: Vec::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Panics if `bounds` isn't finite.
+    pub fn insert(&mut self, bounds: Box2D<f32>, item: T) -> SpatialId {
+        assert_finite(bounds);
+        let id = if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.item = Some((bounds, item));
+            SpatialId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            self.slots.push(SpatialSlot {
+                generation: 0,
+                item: Some((bounds, item)),
+            });
+            SpatialId {
+                index: self.slots.len() as u32 - 1,
+                generation: 0,
+            }
+        };
+        self.add_to_cells(id.index, bounds);
+        id
+    }
+
+    /// Remove the item `id`, returning it, or `None` if it was already removed.
+    pub fn remove(&mut self, id: SpatialId) -> Option<T> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let (bounds, item) = slot.item.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        self.remove_from_cells(id.index, bounds);
+        Some(item)
+    }
+
+    /// Move the item `id` to `bounds`. Returns whether it's in the hash.
+    ///
+    /// Panics if `bounds` isn't finite.
+    pub fn set_bounds(&mut self, id: SpatialId, bounds: Box2D<f32>) -> bool {
+        assert_finite(bounds);
+        let Some(old) = self.bounds(id) else {
+            return false;
+        };
+        self.remove_from_cells(id.index, old);
+        self.add_to_cells(id.index, bounds);
+        if let Some((slot_bounds, _)) = &mut self.slots[id.index as usize].item {
+            *slot_bounds = bounds;
+        }
+        true
+    }
+
+    pub fn get(&self, id: SpatialId) -> Option<&T> {
+        self.entry(id).map(|(_, item)| item)
+    }
+
+    pub fn get_mut(&mut self, id: SpatialId) -> Option<&mut T> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.item.as_mut().map(|(_, item)| item)
+    }
+
+    pub fn bounds(&self, id: SpatialId) -> Option<Box2D<f32>> {
+        self.entry(id).map(|(bounds, _)| *bounds)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.oversized.clear();
+        self.free.clear();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.item.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+            }
+            self.free.push(index as u32);
+        }
+    }
+
+    /// Every item, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (SpatialId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let (_, item) = slot.item.as_ref()?;
+            Some((
+                SpatialId {
+                    index: index as u32,
+                    generation: slot.generation,
+                },
+                item,
+            ))
+        })
+    }
+
+    /// The items whose bounds intersect `rect`, each once, in the order of their slots.
+    pub fn query(&self, rect: Box2D<f32>) -> impl Iterator<Item = (SpatialId, &T)> {
+        let mut indices = Vec::new();
+        self.query_indices(rect, &mut indices);
+        indices.sort_unstable();
+        indices.dedup();
+        indices.into_iter().map(|index| {
+            let slot = &self.slots[index as usize];
+            let (_, item) = slot
+                .item
+                .as_ref()
+                .expect("a removed item was still in a cell");
+            (
+                SpatialId {
+                    index,
+                    generation: slot.generation,
+                },
+                item,
+            )
+        })
+    }
+
+    /// Push the slots of the items intersecting `rect` to `indices`, with repeats.
+    fn query_indices(&self, rect: Box2D<f32>, indices: &mut Vec<u32>) {
+        let intersects = |index: &u32| {
+            self.slots[*index as usize]
+                .item
+                .as_ref()
+                .is_some_and(|(bounds, _)| bounds.intersects(&rect))
+        };
+        indices.extend(self.oversized.iter().copied().filter(intersects));
+
+        let range = self.cell_range(rect);
+        // Huge queries, such as of an infinite rect, look through the cells that are in use.
+        if range.count() > self.cells.len() as u64 {
+            for (_, cell) in self.cells.iter().filter(|(key, _)| range.contains(**key)) {
+                indices.extend(cell.iter().copied().filter(intersects));
+            }
+        } else {
+            for key in range.cells() {
+                if let Some(cell) = self.cells.get(&key) {
+                    indices.extend(cell.iter().copied().filter(intersects));
+                }
+            }
+        }
+    }
+
+    fn entry(&self, id: SpatialId) -> Option<&(Box2D<f32>, T)> {
+        let slot = self.slots.get(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.item.as_ref()
+    }
+
+    /// The cells `rect` touches. Coordinates past the range of `i32` cells saturate.
+    fn cell_range(&self, rect: Box2D<f32>) -> CellRange {
+        let cell = |x: f32, y: f32| {
+            (
+                (x / self.cell_size).floor() as i32,
+                (y / self.cell_size).floor() as i32,
+            )
+        };
+        CellRange {
+            min: cell(rect.min.x, rect.min.y),
+            max: cell(rect.max.x, rect.max.y),
+        }
+    }
+
+    fn add_to_cells(&mut self, index: u32, bounds: Box2D<f32>) {
+        let range = self.cell_range(bounds);
+        if range.count() > MAX_ITEM_CELLS {
+            self.oversized.push(index);
+            return;
+        }
+        for key in range.cells() {
+            self.cells.entry(key).or_default().push(index);
+        }
+    }
+
+    fn remove_from_cells(&mut self, index: u32, bounds: Box2D<f32>) {
+        let range = self.cell_range(bounds);
+        if range.count() > MAX_ITEM_CELLS {
+            self.oversized.retain(|&other| other != index);
+            return;
+        }
+        for key in range.cells() {
+            if let Some(cell) = self.cells.get_mut(&key) {
+                cell.retain(|&other| other != index);
+                if cell.is_empty() {
+                    self.cells.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+fn assert_finite(bounds: Box2D<f32>) {
+    assert!(
+        [bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y]
+            .iter()
+            .all(|value| value.is_finite()),
+        "the bounds of a spatial hash's item must be finite, not {:?}",
+        bounds
+    );
+}
+
+/// Many instances that rarely move, kept in a [`SpatialHash`]. Each frame, only those that one of
+/// the cameras drawing it can see are uploaded to its batch.
+pub struct StaticBatch<T> {
+    index: SpatialHash<T>,
+    batch: Batch<T>,
+    /// The bounds of the geometry each instance places.
+    local_bounds: Box2D<f32>,
+    /// The visible slots, reused each frame.
+    visible: Vec<u32>,
+}
+
+/// Sprites that rarely move, drawn with [`crate::renderer::Renderer::draw_sprites_instanced`]
+/// and [`StaticBatch::batch`].
+pub type StaticSprites = StaticBatch<SpriteInstance>;
+
+/// Placements of one piece of geometry that rarely move, drawn with
+/// [`crate::renderer::Renderer::draw_vector_graphic`] and [`StaticBatch::batch`].
+pub type StaticShapes = StaticBatch<ShapeInstance>;
+
+impl StaticBatch<SpriteInstance> {
+    pub fn sprites(device: &wgpu::Device, cell_size: f32) -> Self {
+        Self::new(device, QUAD_BOUNDS, cell_size)
+    }
+}
+
+impl StaticBatch<ShapeInstance> {
+    /// Placements of `graphic`, which is only used for its bounds.
+    pub fn shapes(device: &wgpu::Device, graphic: &VectorGraphic, cell_size: f32) -> Self {
+        Self::new(device, graphic.bounds(), cell_size)
+    }
+}
+
+impl<T: Instance> StaticBatch<T> {
+    /// Instances of geometry within `local_bounds`, indexed in cells `cell_size` world units
+    /// across.
+    pub fn new(device: &wgpu::Device, local_bounds: Box2D<f32>, cell_size: f32) -> Self {
+        Self {
+            index: SpatialHash::new(cell_size),
+            batch: Batch::new(device),
+            local_bounds,
+            visible: Vec::new(),
+        }
+    }
+
+    /// Only draw the instances with cameras that draw one of `layers`.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.batch.set_layers(layers);
+        self
+    }
+
+    /// Panics if the instance's bounds aren't finite.
+    pub fn insert(&mut self, instance: T) -> SpatialId {
+        let bounds = self.bounds_of(&instance);
+        self.index.insert(bounds, instance)
+    }
+
+    pub fn remove(&mut self, id: SpatialId) -> Option<T> {
+        self.index.remove(id)
+    }
+
+    /// Replace the instance `id`, moving it in the index. Returns whether it's in the batch.
+    pub fn set(&mut self, id: SpatialId, instance: T) -> bool {
+        let bounds = self.bounds_of(&instance);
+        match self.index.get_mut(id) {
+            Some(old) => *old = instance,
+            None => return false,
+        }
+        self.index.set_bounds(id, bounds)
+    }
+
+    pub fn get(&self, id: SpatialId) -> Option<&T> {
+        self.index.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Upload, for each of `cameras` that draws the batch's layers, the instances it can see, as
+    /// with [`Batch::prepare_culled`]. The cameras must have been prepared with
+    /// [`Camera::prepare`] this frame, and other cameras draw none of the batch. Must be called
+    /// before the render passes of those cameras begin.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, cameras: &[&Camera]) {
+        let (index, indices) = (&self.index, &mut self.visible);
+        self.batch
+            .prepare_views(device, queue, cameras, |camera, _, visible| {
+                indices.clear();
+                index.query_indices(camera.visible_rect(), indices);
+                // Keep the order instances were added in, so that the draw order is stable.
+                indices.sort_unstable();
+                indices.dedup();
+                visible.extend(indices.iter().map(|&slot| {
+                    index.slots[slot as usize]
+                        .item
+                        .as_ref()
+                        .expect("a removed item was still in a cell")
+                        .1
+                }));
+            });
+    }
+
+    /// The visible instances, as of the last [`Self::prepare`].
+    pub fn batch(&self) -> &Batch<T> {
+        &self.batch
+    }
+
+    fn bounds_of(&self, instance: &T) -> Box2D<f32> {
+        TransformRange::of([instance.transform()])
+            .expect("one transform has a range")
+            .bounds(self.local_bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Box2D<f32> {
+        Box2D::new(point(min_x, min_y), point(max_x, max_y))
+    }
+
+    fn found(hash: &SpatialHash<&'static str>, rect: Box2D<f32>) -> Vec<&'static str> {
+        hash.query(rect).map(|(_, &item)| item).collect()
+    }
+
+    #[test]
+    fn queries_find_intersecting_items() {
+        let mut hash = SpatialHash::new(10.0);
+        let a = hash.insert(rect(0.0, 0.0, 5.0, 5.0), "a");
+        hash.insert(rect(8.0, 8.0, 25.0, 12.0), "b");
+        hash.insert(rect(-30.0, -30.0, -25.0, -25.0), "c");
+        assert_eq!(hash.len(), 3);
+        assert_eq!(hash.get(a), Some(&"a"));
+
+        assert_eq!(found(&hash, rect(-1.0, -1.0, 9.0, 9.0)), ["a", "b"]);
+        // In the same cells as "b", but not touching it.
+        assert_eq!(found(&hash, rect(21.0, 0.0, 29.0, 7.0)), [] as [&str; 0]);
+        assert_eq!(found(&hash, rect(-26.0, -26.0, -1.0, -1.0)), ["c"]);
+        assert_eq!(
+            found(&hash, rect(-100.0, -100.0, 100.0, 100.0)),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn removed_items_are_gone() {
+        let mut hash = SpatialHash::new(10.0);
+        let a = hash.insert(rect(0.0, 0.0, 15.0, 15.0), "a");
+        hash.insert(rect(5.0, 5.0, 6.0, 6.0), "b");
+        assert_eq!(hash.remove(a), Some("a"));
+        assert_eq!(hash.remove(a), None);
+        assert_eq!(hash.get(a), None);
+        assert_eq!(hash.len(), 1);
+        assert_eq!(found(&hash, rect(0.0, 0.0, 20.0, 20.0)), ["b"]);
+
+        // The slot is reused, but the old id doesn't refer to the new item.
+        let c = hash.insert(rect(12.0, 12.0, 13.0, 13.0), "c");
+        assert_ne!(a, c);
+        assert_eq!(hash.get(a), None);
+        assert_eq!(hash.get(c), Some(&"c"));
+        assert_eq!(found(&hash, rect(11.0, 11.0, 20.0, 20.0)), ["c"]);
+
+        hash.clear();
+        assert!(hash.is_empty());
+        assert_eq!(hash.get(c), None);
+        assert_eq!(found(&hash, rect(0.0, 0.0, 20.0, 20.0)), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn items_move() {
+        let mut hash = SpatialHash::new(10.0);
+        let a = hash.insert(rect(0.0, 0.0, 1.0, 1.0), "a");
+        assert!(hash.set_bounds(a, rect(50.0, 50.0, 51.0, 51.0)));
+        assert_eq!(hash.bounds(a), Some(rect(50.0, 50.0, 51.0, 51.0)));
+        assert_eq!(found(&hash, rect(0.0, 0.0, 5.0, 5.0)), [] as [&str; 0]);
+        assert_eq!(found(&hash, rect(45.0, 45.0, 55.0, 55.0)), ["a"]);
+
+        hash.remove(a);
+        assert!(!hash.set_bounds(a, rect(0.0, 0.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn huge_items_and_queries() {
+        let mut hash = SpatialHash::new(1.0);
+        let huge = hash.insert(rect(-1e30, -1e30, 1e30, 1e30), "huge");
+        hash.insert(rect(0.0, 0.0, 1.0, 1.0), "small");
+        let wide = hash.insert(rect(0.0, 100.0, 1000.0, 101.0), "wide");
+        assert_eq!(hash.cells.len(), 4);
+
+        assert_eq!(found(&hash, rect(5.0, 5.0, 6.0, 6.0)), ["huge"]);
+        assert_eq!(
+            found(&hash, rect(500.0, 100.5, 501.0, 100.6)),
+            ["huge", "wide"]
+        );
+        let everywhere = rect(
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+            f32::INFINITY,
+            f32::INFINITY,
+        );
+        assert_eq!(found(&hash, everywhere), ["huge", "small", "wide"]);
+
+        // Items move in and out of the oversized list.
+        assert!(hash.set_bounds(huge, rect(2.0, 2.0, 3.0, 3.0)));
+        assert!(hash.set_bounds(wide, rect(-1e20, 0.0, 1e20, 0.5)));
+        assert_eq!(found(&hash, rect(5.0, 5.0, 6.0, 6.0)), [] as [&str; 0]);
+        assert_eq!(found(&hash, rect(-5.0, 0.0, -4.0, 0.1)), ["wide"]);
+        hash.remove(wide);
+        assert!(hash.oversized.is_empty());
+        assert_eq!(found(&hash, everywhere), ["huge", "small"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be finite")]
+    fn nan_bounds_panic() {
+        SpatialHash::new(1.0).insert(rect(0.0, 0.0, f32::NAN, 1.0), ());
+    }
+
+    #[test]
+    #[should_panic(expected = "must be finite")]
+    fn infinite_bounds_panic() {
+        let mut hash = SpatialHash::new(1.0);
+        let id = hash.insert(rect(0.0, 0.0, 1.0, 1.0), ());
+        hash.set_bounds(id, rect(0.0, 0.0, f32::INFINITY, 1.0));
+    }
+}
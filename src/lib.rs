@@ -0,0 +1,20 @@
+pub mod archive;
+pub mod assets;
+pub mod camera;
+pub mod compressed;
+pub mod culling;
+pub mod material;
+pub mod paint;
+pub mod particles;
+pub(crate) mod pipeline;
+pub mod postprocess;
+pub mod preprocess;
+pub mod renderer;
+pub mod scene;
+pub mod shader;
+pub mod shape;
+pub mod target;
+pub mod texture;
+pub mod transform;
+pub mod uniforms;
+pub mod vector;
@@ -1,6 +1,27 @@
-use std::iter;
+use std::{iter, sync::Arc, time::Instant};
 
-use renderer::{Bananas, Camera, Renderer, ViewProjectionUniform};
+use lyon::math::{point, Box2D};
+
+use papercut::{
+    archive::Archive,
+    assets::{AssetEvent, AssetServer, AssetSource, Handle, SpriteBindGroups},
+    camera::{Camera, ClearMode, LayerMask, Viewport},
+    culling::StaticSprites,
+    material::{Material, MaterialDescriptor, MaterialInstance, MaterialKind, UniformType},
+    paint::{Gradient, Pattern, SpreadMode},
+    particles::{Curve, Emitter, EmitterConfig, EmitterShape, ParticleSystem},
+    postprocess::{
+        Bloom, Blur, ChromaticAberration, ColorGrade, ColorLut, Crt, PostEffect, PostProcessor,
+        Vignette,
+    },
+    renderer::{Bananas, Renderer, ShapeBatch, ShapeInstance, SpriteBatch, SpriteInstance},
+    scene::{Node, NodeId, SceneGraph},
+    shape::{LineCap, LineJoin, ShapeBuilder, StrokeStyle},
+    target::RenderTarget,
+    texture,
+    transform::Transform2D,
+    vector::{SvgOptions, VectorGraphic},
+};
 use winit::{
     dpi::LogicalSize,
     event::*,
@@ -8,9 +29,6 @@ use winit::{
     window::WindowBuilder,
 };
 
-mod renderer;
-mod texture;
-
 fn main() {
     pollster::block_on(run());
 }
@@ -19,6 +37,9 @@ const ASPECT_RATIO: f32 = 16_f32 / 9_f32;
 pub const DEFAULT_WINDOW_WIDTH: u32 = 1024;
 pub const DEFAULT_WINDOW_HEIGHT: u32 = (DEFAULT_WINDOW_WIDTH as f32 / ASPECT_RATIO) as u32;
 
+/// Drawn by the HUD camera, on top of the post processed world, and by no other.
+const HUD: LayerMask = LayerMask::layer(1);
+
 pub async fn run() {
     env_logger::init();
 
@@ -55,7 +76,15 @@ pub async fn run() {
         bananas.config.format,
         clear_color,
         blend_state,
-    );
+    )
+    .expect("failed to create the renderer");
+    // Edit the shaders while the game is running in development builds.
+    if cfg!(debug_assertions) {
+        if let Err(error) = renderer.watch_shaders(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders"))
+        {
+            log::warn!("not watching shaders: {:#}", error);
+        }
+    }
 
     ////// Start game state stuff
     let shape_bytes: &[u8] = &[255, 255, 255, 255];
@@ -68,19 +97,332 @@ pub async fn run() {
         Some("shape texture"),
     )
     .expect("TODO");
-    let shape_bind_group = renderer.create_sprite_bind_group(&shape_texture, &bananas.device); // TODO: <--- This is all renderer stuff
+    let shape_bind_group =
+        Arc::new(renderer.create_sprite_bind_group(&shape_texture, &bananas.device)); // TODO: <--- This is all renderer stuff
 
-    let sprite_bytes = include_bytes!("../tree.png");
-    let sprite_texture = texture::Texture::from_image_bytes(
+    let mut assets = asset_server();
+    // Reload art while the game is running in development builds.
+    if cfg!(debug_assertions) {
+        assets.watch_for_changes();
+    }
+    // The minimap draws the tree much smaller, so it's mipmapped to keep it from shimmering.
+    let mipmap_generator = Arc::new(texture::MipmapGenerator::new(&bananas.device).expect("TODO"));
+    let tree = assets.load_with::<texture::Texture>(
+        "tree.png",
+        texture::TextureOptions::default()
+            .with_sampler(texture::SamplerConfig::linear())
+            .with_mipmaps(texture::Mipmaps::Gpu(mipmap_generator)),
+    );
+    // The scene is built from the tree, so wait for it as a loading screen would.
+    assets.finish_loading(&bananas);
+    let sprite_texture = match assets.get_arc(&tree) {
+        Some(texture) => texture.clone(),
+        None => panic!("{:#}", assets.error(&tree).expect("TODO")),
+    };
+    let mut sprite_bind_groups = SpriteBindGroups::new();
+    sprite_bind_groups.insert(&renderer, &bananas.device, &assets, &tree);
+
+    let mut camera = Camera::new(size.width as f32, size.height as f32)
+        .with_position(glam::Vec2::new(-200.0, -200.0))
+        .with_clear(ClearMode::Color(clear_color))
+        .with_layers(LayerMask::ALL.without(HUD));
+    let mut hud_camera = Camera::new(size.width as f32, size.height as f32)
+        .with_clear(ClearMode::Depth)
+        .with_layers(HUD);
+    // A close up of the fire in the top right corner.
+    let pip_camera = Camera::new(256.0, 144.0)
+        .with_position(glam::Vec2::new(272.0, -40.0))
+        .with_viewport(Viewport::new(0.74, 0.02, 0.24, 0.24))
+        .with_clear(ClearMode::Color(wgpu::Color::BLACK))
+        .with_layers(LayerMask::ALL.without(HUD));
+
+    let mut particles = ParticleSystem::new(&bananas.device);
+    particles.add_emitter(Emitter::new(
+        glam::Vec2::new(400.0, 0.0),
+        EmitterConfig {
+            shape: EmitterShape::Circle { radius: 10.0 },
+            spawn_rate: 200.0,
+            lifetime: 1.0..2.5,
+            speed: 80.0..160.0,
+            spread: std::f32::consts::FRAC_PI_4,
+            angular_velocity: -3.0..3.0,
+            gravity: glam::Vec2::new(0.0, -98.0),
+            color_over_life: Curve::linear([1.0, 0.9, 0.2, 1.0], [0.8, 0.1, 0.0, 0.0]),
+            size_over_life: Curve::linear(8.0, 2.0),
+            ..Default::default()
+        },
+    ));
+
+    // A forest stretching far off screen, of which only what the cameras can see is drawn.
+    let mut forest = StaticSprites::sprites(&bananas.device, 128.0);
+    for row in 0..10 {
+        for column in 0..400 {
+            let x = column as f32 * 24.0 - 200.0;
+            let y = row as f32 * 24.0 + 300.0;
+            let shade = 0.5 + 0.05 * row as f32;
+            forest.insert(
+                SpriteInstance::new(Transform2D::from_scale_rotation_translation(
+                    glam::Vec2::splat(20.0),
+                    0.0,
+                    glam::Vec2::new(x, y),
+                ))
+                .with_tint([shade, shade, shade, 1.0]),
+            );
+        }
+    }
+
+    // A row of trees drawn with a custom material.
+    let shimmer = renderer
+        .create_material(
+            &bananas.device,
+            &MaterialDescriptor::new(
+                "shimmer.wgsl",
+                MaterialKind::Sprite,
+                include_str!("../materials/shimmer.wgsl"),
+            )
+            .with_uniform("time", UniformType::F32)
+            .with_uniform("speed", UniformType::F32)
+            .with_uniform("glow_color", UniformType::Vec4)
+            .with_texture("sprite"),
+        )
+        .expect("TODO");
+    let mut shimmer_instance = shimmer.create_instance();
+    shimmer_instance.set_uniform("speed", 4.0).expect("TODO");
+    shimmer_instance
+        .set_uniform("glow_color", [1.0, 0.95, 0.6, 0.8])
+        .expect("TODO");
+    shimmer_instance
+        .set_texture("sprite", sprite_texture.clone())
+        .expect("TODO");
+    let mut glade = SpriteBatch::new(&bananas.device);
+    for column in 0..20 {
+        glade.push(SpriteInstance::new(
+            Transform2D::from_scale_rotation_translation(
+                glam::Vec2::splat(40.0),
+                0.0,
+                glam::Vec2::new(column as f32 * 44.0 - 200.0, 580.0),
+            ),
+        ));
+    }
+    glade.prepare(&bananas.device, &bananas.queue);
+
+    let badge = Arc::new(
+        VectorGraphic::from_svg_data(
+            &bananas.device,
+            renderer.paint_bind_group_layout(),
+            include_bytes!("../badge.svg"),
+            "badge.svg",
+            &SvgOptions::default(),
+        )
+        .expect("TODO"),
+    );
+    let mut badges = ShapeBatch::new(&bananas.device);
+    for (i, scale) in [0.5, 1.0, 2.0].into_iter().enumerate() {
+        badges.push(ShapeInstance::new(
+            Transform2D::from_scale_rotation_translation(
+                glam::Vec2::splat(scale),
+                0.0,
+                glam::Vec2::new(600.0 + i as f32 * 150.0, 250.0),
+            ),
+        ));
+    }
+    badges.prepare(&bananas.device, &bananas.queue);
+    let mut hud_badges = ShapeBatch::new(&bananas.device).with_layers(HUD);
+    hud_badges.push(ShapeInstance::new(
+        Transform2D::from_scale_rotation_translation(
+            glam::Vec2::splat(0.5),
+            0.0,
+            glam::Vec2::new(DEFAULT_WINDOW_WIDTH as f32 - 80.0, 40.0),
+        ),
+    ));
+    hud_badges.prepare(&bananas.device, &bananas.queue);
+
+    let mut shapes = ShapeBuilder::new();
+    let mut zigzag = lyon::path::Path::builder();
+    zigzag.begin(point(560.0, 40.0));
+    for i in 1..6 {
+        let y = if i % 2 == 0 { 40.0 } else { 100.0 };
+        zigzag.line_to(point(560.0 + i as f32 * 40.0, y));
+    }
+    zigzag.end(false);
+    shapes
+        .stroke(
+            &zigzag.build(),
+            [1.0, 0.8, 0.2, 1.0],
+            &StrokeStyle::new(8.0)
+                .with_line_join(LineJoin::Round)
+                .with_line_cap(LineCap::Round),
+        )
+        .expect("TODO");
+    shapes
+        .stroke_circle(
+            glam::Vec2::new(900.0, 70.0),
+            40.0,
+            [0.9, 0.9, 1.0, 1.0],
+            &StrokeStyle::new(4.0)
+                .with_line_cap(LineCap::Round)
+                .with_dash(&[12.0, 8.0], 0.0),
+        )
+        .expect("TODO");
+    shapes
+        .fill_circle(
+            glam::Vec2::new(560.0, 420.0),
+            50.0,
+            Gradient::radial(glam::Vec2::new(560.0, 420.0), 50.0)
+                .with_stop(0.0, [1.0, 1.0, 0.8, 1.0])
+                .with_stop(0.6, [1.0, 0.5, 0.1, 1.0])
+                .with_stop(1.0, [0.6, 0.0, 0.2, 0.0]),
+        )
+        .expect("TODO");
+    shapes
+        .fill_circle(
+            glam::Vec2::new(700.0, 420.0),
+            50.0,
+            Gradient::conic(glam::Vec2::new(700.0, 420.0), 0.0)
+                .with_stop(0.0, [1.0, 0.0, 0.0, 1.0])
+                .with_stop(0.33, [0.0, 1.0, 0.0, 1.0])
+                .with_stop(0.67, [0.0, 0.0, 1.0, 1.0])
+                .with_stop(1.0, [1.0, 0.0, 0.0, 1.0]),
+        )
+        .expect("TODO");
+    let tile = Box2D::new(point(800.0, 370.0), point(832.0, 402.0));
+    shapes
+        .fill_rect(
+            Box2D::new(point(800.0, 370.0), point(960.0, 470.0)),
+            Pattern::new(sprite_texture.clone(), tile),
+        )
+        .expect("TODO");
+    shapes
+        .stroke_rect(
+            Box2D::new(point(800.0, 370.0), point(960.0, 470.0)),
+            Gradient::linear(glam::Vec2::new(800.0, 0.0), glam::Vec2::new(840.0, 0.0))
+                .with_stop(0.0, [1.0, 1.0, 1.0, 1.0])
+                .with_stop(1.0, [0.2, 0.2, 0.2, 1.0])
+                .with_spread(SpreadMode::Reflect),
+            &StrokeStyle::new(6.0),
+        )
+        .expect("TODO");
+    let outlines = shapes.build(
+        &bananas.device,
+        renderer.paint_bind_group_layout(),
+        "Outlines",
+    );
+    let mut outline_instances = ShapeBatch::new(&bananas.device);
+    outline_instances.push(ShapeInstance::IDENTITY);
+    outline_instances.prepare(&bananas.device, &bananas.queue);
+
+    // Warm the shadows and highlights a little.
+    let warm = ColorLut::from_fn(
         &bananas.device,
         &bananas.queue,
-        sprite_bytes,
-        "tree.png",
+        16,
+        "Warm LUT",
+        |[r, g, b]| [r * 1.05 + 0.02, g, b * 0.9],
+    )
+    .expect("TODO");
+    let post = renderer
+        .create_post_processor(&bananas)
+        .expect("failed to create the post processor")
+        .with_effect(Bloom::default())
+        .with_effect(ColorGrade::new(warm))
+        .with_effect(Vignette::default())
+        .with_effect(ChromaticAberration { amount: 1.5 });
+
+    // A zoomed out view of the scene in the corner of the HUD, rendered into its own texture
+    // first.
+    let minimap = renderer.create_render_target(&bananas.device, 256, 144, "Minimap");
+    let minimap_camera = Camera::new(2048.0, 1152.0)
+        .with_position(glam::Vec2::new(-700.0, -400.0))
+        .with_clear(ClearMode::Color(wgpu::Color {
+            r: 0.05,
+            g: 0.1,
+            b: 0.15,
+            a: 1.0,
+        }))
+        .with_layers(LayerMask::ALL.without(HUD));
+    let minimap_bind_group = renderer.create_sprite_bind_group(minimap.color(), &bananas.device);
+    let mut minimap_sprite = SpriteBatch::new(&bananas.device).with_layers(HUD);
+    minimap_sprite.push(SpriteInstance::new(
+        Transform2D::from_scale_rotation_translation(
+            glam::Vec2::new(256.0, 144.0),
+            0.0,
+            glam::Vec2::new(136.0, 80.0),
+        ),
+    ));
+    minimap_sprite.prepare(&bananas.device, &bananas.queue);
+
+    // A plasma next to the minimap, computed on the CPU and uploaded every frame.
+    let plasma = texture::DynamicTexture::new(
+        &bananas.device,
+        PLASMA_SIZE,
+        PLASMA_SIZE,
+        "Plasma",
+        &texture::TextureOptions::default(),
     )
     .expect("TODO");
-    let sprite_bind_group = renderer.create_sprite_bind_group(&sprite_texture, &bananas.device);
+    let plasma_bind_groups = std::array::from_fn(|index| {
+        renderer.create_sprite_bind_group(&plasma.textures()[index], &bananas.device)
+    });
+    let mut plasma_sprite = SpriteBatch::new(&bananas.device).with_layers(HUD);
+    plasma_sprite.push(SpriteInstance::new(
+        Transform2D::from_scale_rotation_translation(
+            glam::Vec2::splat(64.0),
+            0.0,
+            glam::Vec2::new(312.0, 80.0),
+        ),
+    ));
+    plasma_sprite.prepare(&bananas.device, &bananas.queue);
+
+    // An orrery: a spinning badge, carrying a planet on an arm, carrying a moon.
+    let mut orrery = SceneGraph::new();
+    let orrery_sun = orrery.add(
+        Node::shape(badge.clone())
+            .with_translation(glam::Vec2::new(300.0, 100.0))
+            .with_scale(glam::Vec2::splat(0.5)),
+    );
+    let orrery_planet = orrery.add_child(
+        orrery_sun,
+        Node::sprite(shape_bind_group.clone(), glam::Vec2::splat(40.0))
+            .with_translation(glam::Vec2::new(200.0, 0.0))
+            .with_tint([0.3, 0.5, 1.0, 1.0]),
+    );
+    orrery.add_child(
+        orrery_planet,
+        Node::sprite(shape_bind_group.clone(), glam::Vec2::splat(16.0))
+            .with_translation(glam::Vec2::new(50.0, 0.0))
+            .with_tint([0.8, 0.8, 0.8, 1.0]),
+    );
 
-    let mut camera = Camera::new(size.width as f32, size.height as f32);
+    let mut scene = Scene {
+        shape_bind_group,
+        tree,
+        sprite_bind_groups,
+        forest,
+        glade,
+        shimmer,
+        shimmer_instance,
+        badge,
+        badges,
+        hud_badges,
+        outlines,
+        outline_instances,
+        particles,
+        post,
+        minimap,
+        minimap_camera,
+        minimap_bind_group,
+        minimap_sprite,
+        pip_camera,
+        plasma,
+        plasma_bind_groups,
+        plasma_sprite,
+        orrery,
+        orrery_sun,
+        orrery_planet,
+    };
+
+    let start = Instant::now();
+    let mut last_frame = start;
     ////// End game state stuff
 
     window.set_visible(true);
@@ -102,17 +444,47 @@ pub async fn run() {
                             },
                         ..
                     } => *control_flow = ControlFlow::Exit,
+                    // Toggle the CRT and blur effects.
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key @ (VirtualKeyCode::C | VirtualKeyCode::B)),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let effects = &mut scene.post.effects;
+                        let position = effects.iter().position(|effect| {
+                            matches!(
+                                (key, effect),
+                                (VirtualKeyCode::C, PostEffect::Crt(_))
+                                    | (VirtualKeyCode::B, PostEffect::Blur(_))
+                            )
+                        });
+                        match (position, key) {
+                            (Some(position), _) => {
+                                effects.remove(position);
+                            }
+                            (None, VirtualKeyCode::C) => effects.push(Crt::default().into()),
+                            (None, _) => effects.push(Blur::default().into()),
+                        }
+                    }
                     WindowEvent::Resized(physical_size) => {
                         // TODO: Resize should scale the view up or down, not show more or less of it.
                         bananas.resize(*physical_size);
                         camera.resize(physical_size.width as f32, physical_size.height as f32);
+                        hud_camera.resize(physical_size.width as f32, physical_size.height as f32);
                         renderer.resize(&bananas);
+                        scene.post.resize(&bananas);
                     }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                         // TODO: Resize should scale the view up or down, not show more or less of it.
                         bananas.resize(**new_inner_size);
                         camera.resize(new_inner_size.width as f32, new_inner_size.height as f32);
+                        hud_camera.resize(new_inner_size.width as f32, new_inner_size.height as f32);
                         renderer.resize(&bananas);
+                        scene.post.resize(&bananas);
                     }
                     _ => {}
                 }
@@ -120,19 +492,68 @@ pub async fn run() {
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
                 // state.update();
-                match make_piccys(
-                    &bananas,
-                    &renderer,
-                    &shape_bind_group,
-                    &sprite_bind_group,
-                    &camera,
-                ) {
+                let now = Instant::now();
+                let dt = (now - last_frame).as_secs_f32();
+                last_frame = now;
+                scene.particles.update(dt);
+                scene.particles.prepare(&bananas.device, &bananas.queue);
+                renderer.reload_changed_shaders(&bananas.device);
+                let events = assets.update(&bananas);
+                scene
+                    .sprite_bind_groups
+                    .update(&renderer, &bananas.device, &assets, &events);
+                if events.contains(&AssetEvent::Reloaded(scene.tree.id())) {
+                    if let Some(tree) = assets.get_arc(&scene.tree) {
+                        scene
+                            .shimmer_instance
+                            .set_texture("sprite", tree.clone())
+                            .expect("TODO");
+                    }
+                }
+                scene
+                    .shimmer_instance
+                    .set_uniform("time", (now - start).as_secs_f32())
+                    .expect("TODO");
+                scene
+                    .plasma
+                    .write(&bananas.queue, &plasma_pixels((now - start).as_secs_f32()))
+                    .expect("TODO");
+
+                let time = (now - start).as_secs_f32();
+                if let Some(sun) = scene.orrery.get_mut(scene.orrery_sun) {
+                    sun.transform.rotation = time * 0.5;
+                }
+                if let Some(planet) = scene.orrery.get_mut(scene.orrery_planet) {
+                    planet.transform.rotation = time * 2.0;
+                }
+                scene.orrery.prepare(&bananas.device, &bananas.queue);
+
+                let uniforms = renderer.begin_frame();
+                scene
+                    .shimmer_instance
+                    .prepare(&bananas.device, uniforms)
+                    .expect("TODO");
+                camera.prepare(uniforms);
+                hud_camera.prepare(uniforms);
+                scene.minimap_camera.prepare(uniforms);
+                scene.pip_camera.prepare(uniforms);
+                renderer.upload_uniforms(&bananas.device, &bananas.queue);
+                // Culled for each camera, so after they're prepared.
+                scene.forest.prepare(
+                    &bananas.device,
+                    &bananas.queue,
+                    &[&camera, &scene.pip_camera, &scene.minimap_camera],
+                );
+
+                match make_piccys(&bananas, &renderer, &mut scene, &camera, &hud_camera) {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         bananas.resize(bananas.size);
                         camera.resize(bananas.size.width as f32, bananas.size.height as f32);
+                        hud_camera.resize(bananas.size.width as f32, bananas.size.height as f32);
                         renderer.resize(&bananas);
+                        scene.post.resize(&bananas);
                     }
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
@@ -150,12 +571,76 @@ pub async fn run() {
     });
 }
 
+/// Development builds read the assets directory of the source tree, and release builds the
+/// archive packed from it next to the executable, with `cargo run --bin pack assets assets.pak`.
+fn asset_server() -> AssetServer {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets");
+    if cfg!(debug_assertions) {
+        return AssetServer::new(dir);
+    }
+    let archive = std::env::current_exe()
+        .map_err(anyhow::Error::from)
+        .and_then(|exe| Archive::open(exe.with_file_name("assets.pak")));
+    match archive {
+        Ok(archive) => AssetServer::with_source(AssetSource::Archive(Arc::new(archive))),
+        Err(error) => {
+            log::warn!("reading {} instead of the archive: {:#}", dir, error);
+            AssetServer::new(dir)
+        }
+    }
+}
+
+/// Everything the demo draws each frame.
+struct Scene {
+    shape_bind_group: Arc<wgpu::BindGroup>,
+    tree: Handle<texture::Texture>,
+    sprite_bind_groups: SpriteBindGroups,
+    forest: StaticSprites,
+    glade: SpriteBatch,
+    shimmer: Material,
+    shimmer_instance: MaterialInstance,
+    badge: Arc<VectorGraphic>,
+    badges: ShapeBatch,
+    hud_badges: ShapeBatch,
+    outlines: VectorGraphic,
+    outline_instances: ShapeBatch,
+    particles: ParticleSystem,
+    post: PostProcessor,
+    minimap: RenderTarget,
+    minimap_camera: Camera,
+    minimap_bind_group: wgpu::BindGroup,
+    minimap_sprite: SpriteBatch,
+    pip_camera: Camera,
+    plasma: texture::DynamicTexture,
+    plasma_bind_groups: [wgpu::BindGroup; 2],
+    plasma_sprite: SpriteBatch,
+    orrery: SceneGraph,
+    orrery_sun: NodeId,
+    orrery_planet: NodeId,
+}
+
+const PLASMA_SIZE: u32 = 32;
+
+/// A frame of the HUD's plasma at `time`, as RGBA pixels.
+fn plasma_pixels(time: f32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((4 * PLASMA_SIZE * PLASMA_SIZE) as usize);
+    for y in 0..PLASMA_SIZE {
+        for x in 0..PLASMA_SIZE {
+            let (x, y) = (x as f32 / 4.0, y as f32 / 4.0);
+            let value = (x + time).sin() + (y - time).sin() + ((x + y) / 2.0 + time).sin();
+            let channel = |phase: f32| ((value + phase).sin() * 127.5 + 127.5) as u8;
+            pixels.extend([channel(0.0), channel(2.1), channel(4.2), 255]);
+        }
+    }
+    pixels
+}
+
 fn make_piccys(
     bananas: &Bananas,
     renderer: &Renderer,
-    shape_bind_group: &wgpu::BindGroup,
-    sprite_bind_group: &wgpu::BindGroup,
+    scene: &mut Scene,
     camera: &Camera,
+    hud_camera: &Camera,
 ) -> Result<(), wgpu::SurfaceError> {
     // TODO: Textures / sprites
     // TODO: Camera
@@ -170,35 +655,34 @@ fn make_piccys(
             label: Some("Render Encoder"),
         });
 
-    let view_projection_uniform = ViewProjectionUniform {
-        view: camera.get_view().to_cols_array_2d(),
-        projection: camera.get_projection().to_cols_array_2d(),
-    };
-
-    let view_projection_uniform_buffer = wgpu::util::DeviceExt::create_buffer_init(
-        &bananas.device,
-        &wgpu::util::BufferInitDescriptor {
-            label: Some("View Projection Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[view_projection_uniform]),
-            usage: wgpu::BufferUsages::COPY_SRC,
-        },
-    );
-
-    encoder.copy_buffer_to_buffer(
-        &view_projection_uniform_buffer,
-        0,
-        &renderer.view_projection_uniform_buffer,
-        0,
-        std::mem::size_of::<ViewProjectionUniform>() as wgpu::BufferAddress,
-    );
-
+    // Every camera is offered the whole scene, and draws what's on its layers.
     {
-        let mut render_pass = renderer.begin(&mut encoder, &render_target);
+        let mut render_pass =
+            renderer.begin_camera(&mut encoder, &scene.minimap, &scene.minimap_camera);
+        draw_scene(renderer, &mut render_pass, scene);
+    }
+
+    // The world is drawn offscreen, then post processed onto the surface.
+    for camera in [camera, &scene.pip_camera] {
+        let mut render_pass =
+            renderer.begin_camera(&mut encoder, scene.post.scene_target(), camera);
         // let mut gfx = renderer.begin(&mut encoder, &render_target); ???
         // gfx.draw_shape(shape); ???
         // gfx.draw_sprite(sprite); ???
         // renderer.end(gfx); ???
-        renderer.render(&mut render_pass, shape_bind_group, sprite_bind_group);
+        draw_scene(renderer, &mut render_pass, scene);
+    }
+
+    scene.post.apply(
+        &bananas.device,
+        &bananas.queue,
+        &mut encoder,
+        &render_target,
+    );
+
+    {
+        let mut render_pass = renderer.begin_camera(&mut encoder, &render_target, hud_camera);
+        draw_scene(renderer, &mut render_pass, scene);
     }
 
     bananas.queue.submit(iter::once(encoder.finish()));
@@ -207,4 +691,36 @@ fn make_piccys(
     Ok(())
 }
 
+fn draw_scene<'pass>(
+    renderer: &'pass Renderer,
+    render_pass: &mut wgpu::RenderPass<'pass>,
+    scene: &'pass Scene,
+) {
+    let tree = scene.sprite_bind_groups.get(&scene.tree).expect("TODO");
+    renderer.render(render_pass, tree);
+    renderer.draw_sprites_instanced(render_pass, scene.forest.batch(), tree);
+    renderer.draw_sprites_with_material(
+        render_pass,
+        &scene.glade,
+        &scene.shimmer,
+        &scene.shimmer_instance,
+    );
+    renderer.draw_vector_graphic(render_pass, &scene.badge, &scene.badges);
+    renderer.draw_vector_graphic(render_pass, &scene.outlines, &scene.outline_instances);
+    renderer.draw_particles(render_pass, &scene.particles, &scene.shape_bind_group);
+    renderer.draw_scene_graph(render_pass, &scene.orrery);
+    // On the HUD layer.
+    renderer.draw_sprites_instanced(
+        render_pass,
+        &scene.minimap_sprite,
+        &scene.minimap_bind_group,
+    );
+    renderer.draw_vector_graphic(render_pass, &scene.badge, &scene.hud_badges);
+    renderer.draw_sprites_instanced(
+        render_pass,
+        &scene.plasma_sprite,
+        &scene.plasma_bind_groups[scene.plasma.front_index()],
+    );
+}
+
 // struct Shape {}
@@ -0,0 +1,667 @@
+use std::sync::Arc;
+
+use anyhow::*;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    preprocess::ShaderDefines,
+    texture::Texture,
+    uniforms::{FrameUniforms, UniformOffset, MAX_UNIFORM_BLOCK_SIZE},
+};
+
+/// What a material draws, which decides the vertex stage its fragment shader is combined with
+/// and the `VertexOutput` it receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialKind {
+    /// Instanced sprites, drawn with [`crate::renderer::Renderer::draw_sprites_with_material`].
+    /// See `shaders/material_sprite.wgsl` for the fragment inputs.
+    Sprite,
+    /// Vector graphics, drawn with
+    /// [`crate::renderer::Renderer::draw_vector_graphic_with_material`]. The material replaces
+    /// the graphic's paints. See `shaders/material_shape.wgsl` for the fragment inputs.
+    Shape,
+}
+
+impl MaterialKind {
+    fn vertex_include(self) -> &'static str {
+        match self {
+            Self::Sprite => "material_sprite.wgsl",
+            Self::Shape => "material_shape.wgsl",
+        }
+    }
+}
+
+/// The type of a material uniform, as declared in WGSL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformType {
+    F32,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat4,
+}
+
+impl UniformType {
+    fn wgsl(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::Vec2 => "vec2<f32>",
+            Self::Vec3 => "vec3<f32>",
+            Self::Vec4 => "vec4<f32>",
+            Self::Mat4 => "mat4x4<f32>",
+        }
+    }
+
+    fn size(self) -> u64 {
+        match self {
+            Self::F32 => 4,
+            Self::Vec2 => 8,
+            Self::Vec3 => 12,
+            Self::Vec4 => 16,
+            Self::Mat4 => 64,
+        }
+    }
+
+    /// As required by WGSL's uniform address space.
+    fn align(self) -> u64 {
+        match self {
+            Self::F32 => 4,
+            Self::Vec2 => 8,
+            Self::Vec3 | Self::Vec4 | Self::Mat4 => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    F32(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Mat4(Mat4),
+}
+
+impl UniformValue {
+    pub fn ty(&self) -> UniformType {
+        match self {
+            Self::F32(_) => UniformType::F32,
+            Self::Vec2(_) => UniformType::Vec2,
+            Self::Vec3(_) => UniformType::Vec3,
+            Self::Vec4(_) => UniformType::Vec4,
+            Self::Mat4(_) => UniformType::Mat4,
+        }
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        let floats: &[f32] = match self {
+            Self::F32(value) => &[*value],
+            Self::Vec2(value) => &value.to_array(),
+            Self::Vec3(value) => &value.to_array(),
+            Self::Vec4(value) => &value.to_array(),
+            Self::Mat4(value) => &value.to_cols_array(),
+        };
+        bytes[..floats.len() * 4].copy_from_slice(bytemuck::cast_slice(floats));
+    }
+}
+
+impl From<f32> for UniformValue {
+    fn from(value: f32) -> Self {
+        Self::F32(value)
+    }
+}
+
+impl From<Vec2> for UniformValue {
+    fn from(value: Vec2) -> Self {
+        Self::Vec2(value)
+    }
+}
+
+impl From<Vec3> for UniformValue {
+    fn from(value: Vec3) -> Self {
+        Self::Vec3(value)
+    }
+}
+
+impl From<Vec4> for UniformValue {
+    fn from(value: Vec4) -> Self {
+        Self::Vec4(value)
+    }
+}
+
+/// Colors are passed as a `vec4<f32>`.
+impl From<[f32; 4]> for UniformValue {
+    fn from(value: [f32; 4]) -> Self {
+        Self::Vec4(Vec4::from(value))
+    }
+}
+
+impl From<Mat4> for UniformValue {
+    fn from(value: Mat4) -> Self {
+        Self::Mat4(value)
+    }
+}
+
+/// Describes a material: a WGSL fragment shader and the uniforms and textures it uses.
+///
+/// The fragment shader must define `fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>`,
+/// and can use the preprocessor directives of [`crate::preprocess`]. The renderer declares
+/// everything else for it:
+///
+/// - each texture `name`, as `t_name` with its sampler `s_name`, in bind group 1,
+/// - the uniforms, as the members of `material`, e.g. `material.speed`, in bind group 2.
+#[derive(Debug, Clone)]
+pub struct MaterialDescriptor {
+    pub label: String,
+    pub kind: MaterialKind,
+    pub fragment_source: String,
+    uniforms: Vec<(String, UniformType)>,
+    textures: Vec<String>,
+    /// Overrides the renderer's blend state.
+    pub blend: Option<wgpu::BlendState>,
+    /// Defined when the shader is preprocessed, to select a variant.
+    pub defines: ShaderDefines,
+}
+
+impl MaterialDescriptor {
+    pub fn new(label: &str, kind: MaterialKind, fragment_source: impl Into<String>) -> Self {
+        Self {
+            label: label.to_string(),
+            kind,
+            fragment_source: fragment_source.into(),
+            uniforms: Vec::new(),
+            textures: Vec::new(),
+            blend: None,
+            defines: ShaderDefines::new(),
+        }
+    }
+
+    pub fn with_uniform(mut self, name: &str, ty: UniformType) -> Self {
+        self.uniforms.push((name.to_string(), ty));
+        self
+    }
+
+    pub fn with_texture(mut self, name: &str) -> Self {
+        self.textures.push(name.to_string());
+        self
+    }
+
+    pub fn with_blend(mut self, blend: wgpu::BlendState) -> Self {
+        self.blend = Some(blend);
+        self
+    }
+
+    pub fn with_define(mut self, name: &str) -> Self {
+        self.defines.define(name, "");
+        self
+    }
+
+    pub fn uniforms(&self) -> &[(String, UniformType)] {
+        &self.uniforms
+    }
+
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+
+    /// Lay out the uniforms and generate the bind group layout and full shader source.
+    pub(crate) fn layout(&self) -> Result<MaterialLayout> {
+        let mut names = Vec::new();
+        for name in self
+            .uniforms
+            .iter()
+            .map(|(name, _)| name)
+            .chain(&self.textures)
+        {
+            ensure!(
+                is_identifier(name),
+                "{}: `{}` isn't a valid WGSL identifier",
+                self.label,
+                name
+            );
+            ensure!(
+                !names.contains(&name),
+                "{}: `{}` is declared twice",
+                self.label,
+                name
+            );
+            names.push(name);
+        }
+
+        let mut uniforms = Vec::new();
+        let mut offset = 0;
+        for (name, ty) in &self.uniforms {
+            offset = align_to(offset, ty.align());
+            uniforms.push(UniformField {
+                name: name.clone(),
+                ty: *ty,
+                offset,
+            });
+            offset += ty.size();
+        }
+        // Uniform structs are sized to a multiple of 16 bytes.
+        let uniform_size = align_to(offset, 16);
+        ensure!(
+            uniform_size <= MAX_UNIFORM_BLOCK_SIZE,
+            "{}: the uniforms take {} bytes, more than the limit of {}",
+            self.label,
+            uniform_size,
+            MAX_UNIFORM_BLOCK_SIZE
+        );
+
+        let mut entries = Vec::new();
+        let mut declarations = String::new();
+        if !uniforms.is_empty() {
+            declarations += "struct MaterialUniforms {\n";
+            for field in &uniforms {
+                declarations += &format!("    {}: {},\n", field.name, field.ty.wgsl());
+            }
+            declarations +=
+                "};\n\n@group(2) @binding(0)\nvar<uniform> material: MaterialUniforms;\n";
+        }
+        for (i, name) in self.textures.iter().enumerate() {
+            let binding = texture_binding(i);
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+            declarations += &format!(
+                "\n@group(1) @binding({})\nvar t_{}: texture_2d<f32>;\n@group(1) @binding({})\nvar s_{}: sampler;\n",
+                binding,
+                name,
+                binding + 1,
+                name
+            );
+        }
+
+        let source = format!(
+            "#include \"{}\"\n\n// Material bindings\n\n{}\n// Material fragment shader\n\n{}",
+            self.kind.vertex_include(),
+            declarations,
+            self.fragment_source
+        );
+
+        Ok(MaterialLayout {
+            uniforms,
+            uniform_size,
+            textures: self.textures.clone(),
+            entries,
+            source,
+        })
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
+
+/// The texture of the `i`th material texture. Its sampler is the next binding.
+fn texture_binding(i: usize) -> u32 {
+    2 * i as u32
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct UniformField {
+    name: String,
+    ty: UniformType,
+    offset: u64,
+}
+
+/// Everything about a material that doesn't need the GPU.
+#[derive(Debug, Clone)]
+pub(crate) struct MaterialLayout {
+    uniforms: Vec<UniformField>,
+    uniform_size: u64,
+    textures: Vec<String>,
+    /// Group 1 of the material's pipeline, its textures.
+    pub(crate) entries: Vec<wgpu::BindGroupLayoutEntry>,
+    /// Includes the vertex stage, then the generated bindings and the fragment shader.
+    pub(crate) source: String,
+}
+
+impl MaterialLayout {
+    /// Whether the material's pipeline binds the frame's uniforms as group 2.
+    pub(crate) fn has_uniforms(&self) -> bool {
+        self.uniform_size > 0
+    }
+
+    /// Write `value` to uniform `name` in `data`, the uniforms of an instance of `label`.
+    fn write_uniform(
+        &self,
+        label: &str,
+        data: &mut [u8],
+        name: &str,
+        value: UniformValue,
+    ) -> Result<()> {
+        let field = self
+            .uniforms
+            .iter()
+            .find(|field| field.name == name)
+            .with_context(|| format!("{} has no uniform `{}`", label, name))?;
+        ensure!(
+            field.ty == value.ty(),
+            "{}: uniform `{}` is {:?}, not {:?}",
+            label,
+            name,
+            field.ty,
+            value.ty()
+        );
+        value.write(&mut data[field.offset as usize..]);
+        Ok(())
+    }
+}
+
+/// A compiled material, created with [`crate::renderer::Renderer::create_material`]. The values
+/// of its uniforms and textures are set on a [`MaterialInstance`], so many instances can share
+/// one pipeline.
+#[derive(Debug)]
+pub struct Material {
+    label: String,
+    kind: MaterialKind,
+    layout: MaterialLayout,
+    /// Group 1, the textures. Shared with every instance to create their bind groups.
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub(crate) pipeline: wgpu::RenderPipeline,
+}
+
+impl Material {
+    pub(crate) fn new(
+        label: &str,
+        kind: MaterialKind,
+        layout: MaterialLayout,
+        bind_group_layout: wgpu::BindGroupLayout,
+        pipeline: wgpu::RenderPipeline,
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            kind,
+            layout,
+            bind_group_layout: Arc::new(bind_group_layout),
+            pipeline,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn kind(&self) -> MaterialKind {
+        self.kind
+    }
+
+    /// Create a set of values for this material's uniforms, all zero, and textures, all unset.
+    pub fn create_instance(&self) -> MaterialInstance {
+        MaterialInstance {
+            label: self.label.clone(),
+            layout: self.layout.clone(),
+            bind_group_layout: self.bind_group_layout.clone(),
+            uniform_data: vec![0; self.layout.uniform_size as usize],
+            uniform_offset: None,
+            textures: vec![None; self.layout.textures.len()],
+            bind_group: None,
+        }
+    }
+}
+
+/// The uniform values and textures a [`Material`] is drawn with.
+#[derive(Debug)]
+pub struct MaterialInstance {
+    label: String,
+    layout: MaterialLayout,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    uniform_data: Vec<u8>,
+    /// Where this frame's uniforms were pushed.
+    uniform_offset: Option<UniformOffset>,
+    textures: Vec<Option<Arc<Texture>>>,
+    /// The textures. Recreated by [`Self::prepare`] after they change.
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl MaterialInstance {
+    pub fn set_uniform(&mut self, name: &str, value: impl Into<UniformValue>) -> Result<()> {
+        self.layout
+            .write_uniform(&self.label, &mut self.uniform_data, name, value.into())
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: Arc<Texture>) -> Result<()> {
+        let index = self
+            .layout
+            .textures
+            .iter()
+            .position(|texture| texture == name)
+            .with_context(|| format!("{} has no texture `{}`", self.label, name))?;
+        self.textures[index] = Some(texture);
+        self.bind_group = None;
+        Ok(())
+    }
+
+    /// Push the uniforms to this frame's uniforms, and create the bind group for the textures if
+    /// needed. Must be called every frame the instance is drawn, after every texture has been
+    /// set and before the uniforms are uploaded.
+    pub fn prepare(&mut self, device: &wgpu::Device, uniforms: &mut FrameUniforms) -> Result<()> {
+        if !self.uniform_data.is_empty() {
+            self.uniform_offset = Some(uniforms.push_bytes(&self.uniform_data));
+        }
+        if self.bind_group.is_some() {
+            return Ok(());
+        }
+
+        let mut entries = Vec::new();
+        for (i, (texture, name)) in self.textures.iter().zip(&self.layout.textures).enumerate() {
+            let texture = texture
+                .as_ref()
+                .with_context(|| format!("{}: texture `{}` hasn't been set", self.label, name))?;
+            entries.push(wgpu::BindGroupEntry {
+                binding: texture_binding(i),
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: texture_binding(i) + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&self.label),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        }));
+        Ok(())
+    }
+
+    /// The bind group for the textures and where the uniforms were pushed, if the instance has
+    /// been prepared.
+    pub(crate) fn prepared(&self) -> Option<(&wgpu::BindGroup, Option<UniformOffset>)> {
+        let bind_group = self.bind_group.as_ref()?;
+        if self.uniform_data.is_empty() {
+            Some((bind_group, None))
+        } else {
+            Some((bind_group, Some(self.uniform_offset?)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        preprocess::Preprocessor, renderer::BUILTIN_SHADER_SOURCES, shader::Shader,
+        uniforms::FRAME_UNIFORMS_LAYOUT_ENTRIES,
+    };
+
+    use super::*;
+
+    const FRAGMENT: &str = "
+        @fragment
+        fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+            let color = textureSample(t_base, s_base, in.tex_coords);
+            return color * material.strength + vec4<f32>(material.tint, material.fade);
+        }
+    ";
+
+    fn descriptor() -> MaterialDescriptor {
+        MaterialDescriptor::new("test", MaterialKind::Sprite, FRAGMENT)
+            .with_uniform("strength", UniformType::F32)
+            .with_uniform("tint", UniformType::Vec3)
+            .with_uniform("fade", UniformType::F32)
+            .with_texture("base")
+    }
+
+    fn offsets(layout: &MaterialLayout) -> Vec<(&str, u64)> {
+        layout
+            .uniforms
+            .iter()
+            .map(|field| (field.name.as_str(), field.offset))
+            .collect()
+    }
+
+    #[test]
+    fn uniforms_are_aligned_and_padded() {
+        let layout = descriptor().layout().unwrap();
+        // The vec3 is aligned to 16 bytes, and the f32 after it fits in its padding.
+        assert_eq!(
+            offsets(&layout),
+            [("strength", 0), ("tint", 16), ("fade", 28)]
+        );
+        assert_eq!(layout.uniform_size, 32);
+
+        let layout = MaterialDescriptor::new("test", MaterialKind::Shape, "")
+            .with_uniform("a", UniformType::Vec2)
+            .with_uniform("b", UniformType::F32)
+            .with_uniform("c", UniformType::Mat4)
+            .with_uniform("d", UniformType::Vec2)
+            .layout()
+            .unwrap();
+        assert_eq!(offsets(&layout), [("a", 0), ("b", 8), ("c", 16), ("d", 80)]);
+        // Rounded up to a multiple of 16.
+        assert_eq!(layout.uniform_size, 96);
+
+        let layout = MaterialDescriptor::new("test", MaterialKind::Shape, "")
+            .layout()
+            .unwrap();
+        assert!(!layout.has_uniforms());
+    }
+
+    #[test]
+    fn uniforms_must_fit_in_a_block() {
+        let count = MAX_UNIFORM_BLOCK_SIZE / 64;
+        let fits = (0..count).fold(
+            MaterialDescriptor::new("test", MaterialKind::Sprite, ""),
+            |descriptor, i| descriptor.with_uniform(&format!("m{}", i), UniformType::Mat4),
+        );
+        assert_eq!(fits.layout().unwrap().uniform_size, MAX_UNIFORM_BLOCK_SIZE);
+
+        let error = fits
+            .with_uniform("one_more", UniformType::F32)
+            .layout()
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("more than the limit"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn names_must_be_unique_identifiers() {
+        let error = descriptor()
+            .with_uniform("base", UniformType::F32)
+            .layout()
+            .unwrap_err();
+        assert!(error.to_string().contains("`base` is declared twice"));
+
+        for name in ["", "2d", "with space", "dash-ed"] {
+            let error = descriptor().with_texture(name).layout().unwrap_err();
+            assert!(
+                error.to_string().contains("isn't a valid WGSL identifier"),
+                "{}",
+                error
+            );
+        }
+        descriptor().with_texture("_under_2").layout().unwrap();
+    }
+
+    #[test]
+    fn uniforms_are_written_at_their_offsets() {
+        let layout = descriptor().layout().unwrap();
+        let mut data = vec![0; layout.uniform_size as usize];
+        layout
+            .write_uniform("test", &mut data, "tint", Vec3::new(1.0, 2.0, 3.0).into())
+            .unwrap();
+        layout
+            .write_uniform("test", &mut data, "fade", 4.0.into())
+            .unwrap();
+        let floats: &[f32] = bytemuck::cast_slice(&data);
+        assert_eq!(floats, [0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        let error = layout
+            .write_uniform("test", &mut data, "tint", Vec4::ONE.into())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "test: uniform `tint` is Vec3, not Vec4");
+        let error = layout
+            .write_uniform("test", &mut data, "missing", 1.0.into())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "test has no uniform `missing`");
+    }
+
+    #[test]
+    fn generated_shaders_validate_and_match_their_layouts() {
+        let mut preprocessor = Preprocessor::new();
+        for (name, source) in BUILTIN_SHADER_SOURCES {
+            preprocessor.add_include(name, *source);
+        }
+        for kind in [MaterialKind::Sprite, MaterialKind::Shape] {
+            let mut descriptor = descriptor();
+            descriptor.kind = kind;
+            if kind == MaterialKind::Shape {
+                // Shapes have no texture coordinates.
+                descriptor.fragment_source = FRAGMENT.replace("in.tex_coords", "in.local_position");
+            }
+            let layout = descriptor.layout().unwrap();
+            let source = preprocessor
+                .preprocess("test", &layout.source, &descriptor.defines)
+                .unwrap();
+            let shader = Shader::from_preprocessed("test", &source).unwrap();
+            shader
+                .check_bind_group_layouts(&[
+                    FRAME_UNIFORMS_LAYOUT_ENTRIES,
+                    &layout.entries,
+                    FRAME_UNIFORMS_LAYOUT_ENTRIES,
+                ])
+                .unwrap();
+            // naga sizes the uniform struct the same.
+            let uniforms = shader
+                .bindings()
+                .unwrap()
+                .into_iter()
+                .find(|binding| binding.group == 2)
+                .unwrap();
+            assert_eq!(
+                uniforms.ty,
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(layout.uniform_size),
+                }
+            );
+        }
+    }
+}
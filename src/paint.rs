@@ -0,0 +1,348 @@
+use std::sync::Arc;
+
+use anyhow::*;
+use glam::{Affine2, Vec2};
+use lyon::geom::Box2D;
+
+use crate::texture::Texture;
+
+/// The most stops a single gradient can have. They are uploaded in a fixed size uniform array.
+pub const MAX_GRADIENT_STOPS: usize = 16;
+
+/// How a gradient is extended outside of the range between its first and last stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpreadMode {
+    /// Use the color of the nearest end stop.
+    #[default]
+    Pad,
+    Repeat,
+    /// Repeat, mirroring every other repetition.
+    Reflect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientShape {
+    /// Colors change along the line from `start` to `end`.
+    Linear { start: Vec2, end: Vec2 },
+    /// Colors change along circles that grow from `focal` at offset 0 to the circle of `radius`
+    /// around `center` at offset 1.
+    Radial {
+        center: Vec2,
+        radius: f32,
+        focal: Vec2,
+    },
+    /// Colors sweep anti-clockwise around `center`, starting at `angle` radians.
+    Conic { center: Vec2, angle: f32 },
+}
+
+/// A gradient evaluated per pixel, so it doesn't depend on how finely a shape is tessellated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub shape: GradientShape,
+    /// Sorted by offset.
+    stops: Vec<GradientStop>,
+    pub spread: SpreadMode,
+    /// Maps gradient space, which `shape` is described in, to the space of the shape being painted.
+    pub transform: Affine2,
+}
+
+impl Gradient {
+    pub fn new(shape: GradientShape) -> Self {
+        Self {
+            shape,
+            stops: Vec::new(),
+            spread: SpreadMode::default(),
+            transform: Affine2::IDENTITY,
+        }
+    }
+
+    pub fn linear(start: Vec2, end: Vec2) -> Self {
+        Self::new(GradientShape::Linear { start, end })
+    }
+
+    pub fn radial(center: Vec2, radius: f32) -> Self {
+        Self::new(GradientShape::Radial {
+            center,
+            radius,
+            focal: center,
+        })
+    }
+
+    pub fn conic(center: Vec2, angle: f32) -> Self {
+        Self::new(GradientShape::Conic { center, angle })
+    }
+
+    pub fn with_stop(mut self, offset: f32, color: [f32; 4]) -> Self {
+        let index = self.stops.partition_point(|stop| stop.offset <= offset);
+        self.stops.insert(index, GradientStop { offset, color });
+        self
+    }
+
+    pub fn with_spread(mut self, spread: SpreadMode) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: impl Into<Affine2>) -> Self {
+        self.transform = transform.into();
+        self
+    }
+
+    pub fn stops(&self) -> &[GradientStop] {
+        &self.stops
+    }
+}
+
+/// A texture repeated across a shape.
+#[derive(Clone)]
+pub struct Pattern {
+    pub texture: Arc<Texture>,
+    /// Maps texture coordinates, with `(0, 0)` at the top left of the texture and `(1, 1)` at the
+    /// bottom right, to the space of the shape being painted.
+    pub transform: Affine2,
+}
+
+impl Pattern {
+    /// Place one tile of the texture over `rect`, upright in y up space.
+    pub fn new(texture: Arc<Texture>, rect: Box2D<f32>) -> Self {
+        let size = rect.size();
+        Self {
+            texture,
+            transform: Affine2::from_cols(
+                Vec2::new(size.width, 0.0),
+                Vec2::new(0.0, -size.height),
+                Vec2::new(rect.min.x, rect.max.y),
+            ),
+        }
+    }
+}
+
+/// How the inside of a fill or stroke is colored.
+#[derive(Clone)]
+pub enum Paint {
+    Solid([f32; 4]),
+    Gradient(Gradient),
+    Pattern(Pattern),
+}
+
+impl From<[f32; 4]> for Paint {
+    fn from(color: [f32; 4]) -> Self {
+        Paint::Solid(color)
+    }
+}
+
+impl From<Gradient> for Paint {
+    fn from(gradient: Gradient) -> Self {
+        Paint::Gradient(gradient)
+    }
+}
+
+impl From<Pattern> for Paint {
+    fn from(pattern: Pattern) -> Self {
+        Paint::Pattern(pattern)
+    }
+}
+
+impl Paint {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Paint::Gradient(gradient) = self {
+            ensure!(
+                !gradient.stops.is_empty(),
+                "a gradient needs at least one stop"
+            );
+            ensure!(
+                gradient.stops.len() <= MAX_GRADIENT_STOPS,
+                "gradients can have at most {} stops, found {}",
+                MAX_GRADIENT_STOPS,
+                gradient.stops.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+// Must match the constants in shape.wgsl.
+const PAINT_SOLID: u32 = 0;
+const PAINT_LINEAR: u32 = 1;
+const PAINT_RADIAL: u32 = 2;
+const PAINT_CONIC: u32 = 3;
+const PAINT_PATTERN: u32 = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PaintUniform {
+    /// The rows of the affine transform from shape space to paint space.
+    paint_from_local: [[f32; 4]; 2],
+    params: [f32; 4],
+    focal: [f32; 4],
+    kind: u32,
+    spread: u32,
+    stop_count: u32,
+    _padding: u32,
+    stop_offsets: [[f32; 4]; MAX_GRADIENT_STOPS / 4],
+    stop_colors: [[f32; 4]; MAX_GRADIENT_STOPS],
+}
+
+impl PaintUniform {
+    fn new(paint: &Paint) -> Self {
+        let mut uniform: Self = bytemuck::Zeroable::zeroed();
+        match paint {
+            // Solid colors are baked into the vertices.
+            Paint::Solid(_) => {
+                uniform.kind = PAINT_SOLID;
+                uniform.paint_from_local = affine_rows(Affine2::IDENTITY);
+            }
+            Paint::Gradient(gradient) => {
+                uniform.paint_from_local = affine_rows(gradient.transform.inverse());
+                match gradient.shape {
+                    GradientShape::Linear { start, end } => {
+                        uniform.kind = PAINT_LINEAR;
+                        uniform.params = [start.x, start.y, end.x, end.y];
+                    }
+                    GradientShape::Radial {
+                        center,
+                        radius,
+                        focal,
+                    } => {
+                        uniform.kind = PAINT_RADIAL;
+                        uniform.params = [center.x, center.y, radius, 0.0];
+                        uniform.focal = [focal.x, focal.y, 0.0, 0.0];
+                    }
+                    GradientShape::Conic { center, angle } => {
+                        uniform.kind = PAINT_CONIC;
+                        uniform.params = [center.x, center.y, angle, 0.0];
+                    }
+                }
+                uniform.spread = match gradient.spread {
+                    SpreadMode::Pad => 0,
+                    SpreadMode::Repeat => 1,
+                    SpreadMode::Reflect => 2,
+                };
+                uniform.stop_count = gradient.stops.len() as u32;
+                for (i, stop) in gradient.stops.iter().enumerate() {
+                    uniform.stop_offsets[i / 4][i % 4] = stop.offset;
+                    uniform.stop_colors[i] = stop.color;
+                }
+            }
+            Paint::Pattern(pattern) => {
+                uniform.kind = PAINT_PATTERN;
+                uniform.paint_from_local = affine_rows(pattern.transform.inverse());
+            }
+        }
+        uniform
+    }
+}
+
+fn affine_rows(transform: Affine2) -> [[f32; 4]; 2] {
+    let [a, b, c, d, tx, ty] = transform.to_cols_array();
+    [[a, c, tx, 0.0], [b, d, ty, 0.0]]
+}
+
+/// The paint bind group, group 1 of the shape pipeline: the paint uniform and the pattern
+/// texture and sampler.
+pub(crate) const PAINT_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+];
+
+/// Upload `paints` and create a bind group for each of them with `layout`, the renderer's layout
+/// of [`PAINT_LAYOUT_ENTRIES`].
+pub(crate) fn create_paint_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    label: &str,
+    paints: &[Paint],
+) -> Vec<wgpu::BindGroup> {
+    use wgpu::util::DeviceExt;
+
+    // Patterns repeat, unlike the clamped sampler textures are created with.
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    // Paints that don't sample a texture still have to bind one.
+    let placeholder = device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+    paints
+        .iter()
+        .map(|paint| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&[PaintUniform::new(paint)]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let view = match paint {
+                Paint::Pattern(pattern) => &pattern.texture.view,
+                _ => &placeholder,
+            };
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+                label: Some(label),
+            })
+        })
+        .collect()
+}
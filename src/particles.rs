@@ -0,0 +1,421 @@
+use std::ops::Range;
+
+use glam::Vec2;
+use lyon::{
+    algorithms::measure::{PathMeasurements, SampleType},
+    path::Path,
+};
+
+use crate::{camera::LayerMask, renderer::InstanceBuffer};
+
+/// Something that can be linearly interpolated by a [`Curve`].
+pub trait Lerp: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+impl Lerp for Vec2 {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+}
+
+impl Lerp for [f32; 4] {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        [
+            Lerp::lerp(a[0], b[0], t),
+            Lerp::lerp(a[1], b[1], t),
+            Lerp::lerp(a[2], b[2], t),
+            Lerp::lerp(a[3], b[3], t),
+        ]
+    }
+}
+
+/// A piecewise linear curve over the normalised range `0.0..=1.0`, used for values that change
+/// over the life of a particle.
+#[derive(Clone, Debug)]
+pub struct Curve<T> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    pub fn new(mut keys: Vec<(f32, T)>) -> Self {
+        assert!(!keys.is_empty(), "a curve needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new(vec![(0.0, value)])
+    }
+
+    pub fn linear(from: T, to: T) -> Self {
+        Self::new(vec![(0.0, from), (1.0, to)])
+    }
+
+    pub fn with_key(mut self, t: f32, value: T) -> Self {
+        let index = self.keys.partition_point(|(key, _)| *key <= t);
+        self.keys.insert(index, (t, value));
+        self
+    }
+
+    pub fn sample(&self, t: f32) -> T {
+        let index = self.keys.partition_point(|(key, _)| *key <= t);
+        if index == 0 {
+            return self.keys[0].1;
+        }
+        if index == self.keys.len() {
+            return self.keys[index - 1].1;
+        }
+
+        let (t0, a) = self.keys[index - 1];
+        let (t1, b) = self.keys[index];
+        T::lerp(a, b, (t - t0) / (t1 - t0))
+    }
+}
+
+/// The area that new particles are spawned in, relative to the emitter position.
+pub enum EmitterShape {
+    Point,
+    Circle {
+        radius: f32,
+    },
+    Rectangle {
+        width: f32,
+        height: f32,
+    },
+    Path {
+        path: Path,
+        measurements: PathMeasurements,
+    },
+}
+
+impl EmitterShape {
+    /// Spawn particles uniformly along the length of a lyon path.
+    pub fn path(path: Path, tolerance: f32) -> Self {
+        let measurements = PathMeasurements::from_path(&path, tolerance);
+        Self::Path { path, measurements }
+    }
+
+    fn sample(&self, rng: &fastrand::Rng) -> Vec2 {
+        match self {
+            EmitterShape::Point => Vec2::ZERO,
+            EmitterShape::Circle { radius } => {
+                // The square root keeps the distribution uniform over the area of the disc.
+                let r = radius * rng.f32().sqrt();
+                Vec2::from_angle(rng.f32() * std::f32::consts::TAU) * r
+            }
+            EmitterShape::Rectangle { width, height } => {
+                Vec2::new((rng.f32() - 0.5) * width, (rng.f32() - 0.5) * height)
+            }
+            EmitterShape::Path { path, measurements } => {
+                if measurements.length() <= 0.0 {
+                    return Vec2::ZERO;
+                }
+                let mut sampler = measurements.create_sampler(path, SampleType::Normalized);
+                let p = sampler.sample(rng.f32()).position();
+                Vec2::new(p.x, p.y)
+            }
+        }
+    }
+}
+
+pub struct EmitterConfig {
+    pub shape: EmitterShape,
+    /// Particles spawned per second.
+    pub spawn_rate: f32,
+    pub max_particles: usize,
+    /// Lifetime in seconds.
+    pub lifetime: Range<f32>,
+    /// Initial speed in world units per second.
+    pub speed: Range<f32>,
+    /// Initial direction of travel in radians, measured anti-clockwise from the positive x axis.
+    pub direction: f32,
+    /// Total angle in radians that the initial direction is randomly spread across.
+    pub spread: f32,
+    /// Initial rotation speed in radians per second.
+    pub angular_velocity: Range<f32>,
+    pub gravity: Vec2,
+    pub color_over_life: Curve<[f32; 4]>,
+    pub size_over_life: Curve<f32>,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        Self {
+            shape: EmitterShape::Point,
+            spawn_rate: 10.0,
+            max_particles: 1000,
+            lifetime: 1.0..1.0,
+            speed: 50.0..50.0,
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.0,
+            angular_velocity: 0.0..0.0,
+            gravity: Vec2::ZERO,
+            color_over_life: Curve::constant([1.0, 1.0, 1.0, 1.0]),
+            size_over_life: Curve::constant(1.0),
+        }
+    }
+}
+
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    rotation: f32,
+    angular_velocity: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+pub struct Emitter {
+    pub position: Vec2,
+    pub config: EmitterConfig,
+    /// Inactive emitters stop spawning but keep simulating the particles already alive.
+    pub active: bool,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+    rng: fastrand::Rng,
+}
+
+impl Emitter {
+    pub fn new(position: Vec2, config: EmitterConfig) -> Self {
+        Self {
+            position,
+            config,
+            active: true,
+            particles: Vec::new(),
+            spawn_accumulator: 0.0,
+            rng: fastrand::Rng::new(),
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// Spawn `count` particles immediately, ignoring the spawn rate.
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let gravity = self.config.gravity;
+        self.particles.retain_mut(|particle| {
+            particle.age += dt;
+            particle.velocity += gravity * dt;
+            particle.position += particle.velocity * dt;
+            particle.rotation += particle.angular_velocity * dt;
+            particle.age < particle.lifetime
+        });
+
+        if !self.active {
+            self.spawn_accumulator = 0.0;
+            return;
+        }
+
+        self.spawn_accumulator += self.config.spawn_rate * dt;
+        if !self.spawn_accumulator.is_finite() {
+            self.spawn_accumulator = 0.0;
+        }
+        let due = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= due;
+        // Particles that don't fit are dropped rather than spawned later.
+        let room = self
+            .config
+            .max_particles
+            .saturating_sub(self.particles.len());
+        for _ in 0..(due as usize).min(room) {
+            self.spawn();
+        }
+    }
+
+    fn spawn(&mut self) {
+        if self.particles.len() >= self.config.max_particles {
+            return;
+        }
+
+        let config = &self.config;
+        let rng = &self.rng;
+        let angle = config.direction + (rng.f32() - 0.5) * config.spread;
+        let speed = random_in(rng, &config.speed);
+
+        self.particles.push(Particle {
+            position: self.position + config.shape.sample(rng),
+            velocity: Vec2::from_angle(angle) * speed,
+            rotation: 0.0,
+            angular_velocity: random_in(rng, &config.angular_velocity),
+            age: 0.0,
+            lifetime: random_in(rng, &config.lifetime).max(f32::EPSILON),
+        });
+    }
+
+    fn instances(&self) -> impl Iterator<Item = ParticleInstance> + '_ {
+        self.particles.iter().map(|particle| {
+            let t = particle.age / particle.lifetime;
+            ParticleInstance {
+                position: particle.position.to_array(),
+                size: self.config.size_over_life.sample(t),
+                rotation: particle.rotation,
+                color: self.config.color_over_life.sample(t),
+            }
+        })
+    }
+}
+
+fn random_in(rng: &fastrand::Rng, range: &Range<f32>) -> f32 {
+    range.start + (range.end - range.start) * rng.f32()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ParticleInstance {
+    position: [f32; 2],
+    size: f32,
+    rotation: f32,
+    color: [f32; 4],
+}
+
+impl ParticleInstance {
+    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ParticleInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            // Locations 0-2 are used by the quad vertices.
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// Owns a set of emitters and the GPU instance buffer their particles are drawn from.
+pub struct ParticleSystem {
+    emitters: Vec<Emitter>,
+    instances: Vec<ParticleInstance>,
+    pub(crate) instance_buffer: InstanceBuffer<ParticleInstance>,
+    layers: LayerMask,
+}
+
+impl ParticleSystem {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            emitters: Vec::new(),
+            instances: Vec::new(),
+            instance_buffer: InstanceBuffer::new(device, "Particle Instance Buffer"),
+            layers: LayerMask::DEFAULT,
+        }
+    }
+
+    /// Only draw the particles with cameras that draw one of `layers`.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn set_layers(&mut self, layers: LayerMask) {
+        self.layers = layers;
+    }
+
+    pub fn layers(&self) -> LayerMask {
+        self.layers
+    }
+
+    pub fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        self.emitters.push(emitter);
+        self.emitters.len() - 1
+    }
+
+    pub fn emitter(&self, index: usize) -> Option<&Emitter> {
+        self.emitters.get(index)
+    }
+
+    pub fn emitter_mut(&mut self, index: usize) -> Option<&mut Emitter> {
+        self.emitters.get_mut(index)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for emitter in &mut self.emitters {
+            emitter.update(dt);
+        }
+    }
+
+    /// Upload the current particles to the instance buffer. Must be called before the render pass
+    /// that draws them begins.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.instances.clear();
+        for emitter in &self.emitters {
+            self.instances.extend(emitter.instances());
+        }
+
+        self.instance_buffer.write(device, queue, &self.instances);
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instance_buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawning_stops_at_max_particles() {
+        let config = EmitterConfig {
+            spawn_rate: 100.0,
+            max_particles: 30,
+            lifetime: 10.0..10.0,
+            ..Default::default()
+        };
+        let mut emitter = Emitter::new(Vec2::ZERO, config).with_seed(1);
+        emitter.update(0.25);
+        assert_eq!(emitter.particle_count(), 25);
+        emitter.update(0.25);
+        assert_eq!(emitter.particle_count(), 30);
+    }
+
+    #[test]
+    fn huge_time_steps_spawn_a_bounded_number() {
+        let config = EmitterConfig {
+            spawn_rate: 1e20,
+            max_particles: 10,
+            ..Default::default()
+        };
+        let mut emitter = Emitter::new(Vec2::ZERO, config).with_seed(1);
+        emitter.update(1e10);
+        assert_eq!(emitter.particle_count(), 10);
+        emitter.update(f32::INFINITY);
+        assert!(emitter.particle_count() <= 10);
+    }
+}
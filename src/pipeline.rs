@@ -0,0 +1,95 @@
+use anyhow::*;
+
+use crate::shader::Shader;
+
+/// Everything about a render pipeline that depends on what it draws, rather than where.
+pub(crate) struct PipelineDescriptor<'a> {
+    pub(crate) label: &'a str,
+    pub(crate) layout: &'a wgpu::PipelineLayout,
+    /// The entries of each bind group layout in `layout`, for checking against the shader.
+    pub(crate) bind_group_layouts: &'a [&'a [wgpu::BindGroupLayoutEntry]],
+    pub(crate) buffers: &'a [wgpu::VertexBufferLayout<'a>],
+    /// Overrides the renderer's blend state.
+    pub(crate) blend: Option<wgpu::BlendState>,
+    pub(crate) cull_mode: Option<wgpu::Face>,
+}
+
+/// The render state shared by every pipeline the renderer creates: the color target, blending
+/// and depth testing.
+#[derive(Debug, Clone)]
+pub(crate) struct PipelineFactory {
+    pub(crate) color_format: wgpu::TextureFormat,
+    pub(crate) blend_state: wgpu::BlendState,
+    pub(crate) depth_stencil_state: Option<wgpu::DepthStencilState>,
+}
+
+impl PipelineFactory {
+    /// The same state, but testing depth with `compare` instead.
+    pub(crate) fn with_depth_compare(&self, compare: wgpu::CompareFunction) -> Self {
+        let mut factory = self.clone();
+        if let Some(depth_stencil_state) = &mut factory.depth_stencil_state {
+            depth_stencil_state.depth_compare = compare;
+        }
+        factory
+    }
+
+    /// Check `shader` against `descriptor` and create a pipeline from its `vs_main` and `fs_main`
+    /// entry points. Errors that wgpu reports while creating the pipeline are returned rather
+    /// than panicking, so callers can keep using a previous pipeline.
+    pub(crate) fn create(
+        &self,
+        device: &wgpu::Device,
+        shader: &Shader,
+        descriptor: &PipelineDescriptor,
+    ) -> Result<wgpu::RenderPipeline> {
+        shader.check_pipeline(descriptor.bind_group_layouts, "vs_main", descriptor.buffers)?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = shader.create_module(device);
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(descriptor.label),
+            layout: Some(descriptor.layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: descriptor.buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: Some(descriptor.blend.unwrap_or(self.blend_state)),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: descriptor.cull_mode,
+                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+                // or Features::POLYGON_MODE_POINT
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: self.depth_stencil_state.clone(),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // If the pipeline will be used with a multiview render pass, this
+            // indicates how many array layers the attachments will have.
+            multiview: None,
+        });
+
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(error) => Err(anyhow!("{}: {}", shader.label(), error)),
+            None => Ok(pipeline),
+        }
+    }
+}
@@ -0,0 +1,831 @@
+use std::sync::Arc;
+
+use anyhow::*;
+
+use crate::{
+    pipeline::{PipelineDescriptor, PipelineFactory},
+    preprocess::{Preprocessor, ShaderDefines},
+    renderer::Bananas,
+    shader::Shader,
+    target::RenderTarget,
+    texture::Texture,
+};
+
+/// Spreads the bright parts of the scene into a glow around them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bloom {
+    /// Brightness, from 0 to 1, above which colors bloom.
+    pub threshold: f32,
+    /// How gradually colors below the threshold fade out of the bloom, as a fraction of the
+    /// threshold.
+    pub soft_knee: f32,
+    pub intensity: f32,
+    /// How far the glow spreads, in half resolution texels per blur tap.
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            soft_knee: 0.5,
+            intensity: 1.0,
+            radius: 1.5,
+        }
+    }
+}
+
+/// A color lookup table for [`ColorGrade`]: `size` slices of `size` by `size` texels side by
+/// side, with red increasing to the right within a slice, green downwards and blue from slice to
+/// slice. Both the colors it is indexed by and the colors in it are sRGB encoded.
+#[derive(Debug, Clone)]
+pub struct ColorLut {
+    pub texture: Arc<Texture>,
+    pub size: u32,
+}
+
+impl ColorLut {
+    /// Load a lookup table from an image `size * size` texels wide and `size` high.
+    pub fn from_image_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self> {
+        let image = image::load_from_memory(bytes)?;
+        let size = image.height();
+        ensure!(
+            size > 1 && image.width() == size * size,
+            "{}: a color lookup table must be size * size wide and size high, not {}x{}",
+            label,
+            image.width(),
+            image.height()
+        );
+        Ok(Self {
+            texture: Arc::new(Texture::from_image(device, queue, &image, Some(label))?),
+            size,
+        })
+    }
+
+    /// Build a lookup table of `size` slices by applying `grade` to every color in it.
+    pub fn from_fn(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        label: &str,
+        grade: impl Fn([f32; 3]) -> [f32; 3],
+    ) -> Result<Self> {
+        ensure!(size > 1, "a color lookup table needs at least 2 slices");
+        let max = (size - 1) as f32;
+        let mut bytes = Vec::with_capacity((size * size * size * 4) as usize);
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    let color = grade([red as f32 / max, green as f32 / max, blue as f32 / max]);
+                    for channel in color {
+                        bytes.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    }
+                    bytes.push(255);
+                }
+            }
+        }
+        let texture = Texture::from_bytes(device, queue, size * size, size, &bytes, Some(label))?;
+        Ok(Self {
+            texture: Arc::new(texture),
+            size,
+        })
+    }
+
+    /// A lookup table that leaves colors as they are.
+    pub fn identity(device: &wgpu::Device, queue: &wgpu::Queue, size: u32) -> Result<Self> {
+        Self::from_fn(device, queue, size, "Identity LUT", |color| color)
+    }
+}
+
+/// Maps every color through a lookup table.
+#[derive(Debug, Clone)]
+pub struct ColorGrade {
+    pub lut: ColorLut,
+    /// How much of the graded color to use, from 0 to 1.
+    pub intensity: f32,
+}
+
+impl ColorGrade {
+    pub fn new(lut: ColorLut) -> Self {
+        Self {
+            lut,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// Darkens the edges of the screen towards a color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vignette {
+    pub intensity: f32,
+    /// Distance from the center, where the corners are 1, at which darkening starts.
+    pub radius: f32,
+    /// Distance over which the darkening fades in.
+    pub softness: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.6,
+            radius: 0.5,
+            softness: 0.6,
+            color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Splits the red and blue channels apart towards the edges of the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromaticAberration {
+    /// How far apart the channels are at the edges, in pixels.
+    pub amount: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        Self { amount: 3.0 }
+    }
+}
+
+/// A curved screen with dark scanlines between the rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crt {
+    pub curvature: f32,
+    /// How dark the scanlines are, from 0 to 1.
+    pub scanline_intensity: f32,
+    /// The number of scanlines from the top to the bottom of the screen.
+    pub scanline_count: f32,
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self {
+            curvature: 0.04,
+            scanline_intensity: 0.3,
+            scanline_count: 240.0,
+        }
+    }
+}
+
+/// A gaussian blur of the whole screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blur {
+    /// The distance between blur taps, in pixels. The blur spreads over 4 taps each way.
+    pub radius: f32,
+}
+
+impl Default for Blur {
+    fn default() -> Self {
+        Self { radius: 2.0 }
+    }
+}
+
+/// One effect of a [`PostProcessor`]'s chain.
+#[derive(Debug, Clone)]
+pub enum PostEffect {
+    Bloom(Bloom),
+    ColorGrade(ColorGrade),
+    Vignette(Vignette),
+    ChromaticAberration(ChromaticAberration),
+    Crt(Crt),
+    Blur(Blur),
+}
+
+impl From<Bloom> for PostEffect {
+    fn from(effect: Bloom) -> Self {
+        PostEffect::Bloom(effect)
+    }
+}
+
+impl From<ColorGrade> for PostEffect {
+    fn from(effect: ColorGrade) -> Self {
+        PostEffect::ColorGrade(effect)
+    }
+}
+
+impl From<Vignette> for PostEffect {
+    fn from(effect: Vignette) -> Self {
+        PostEffect::Vignette(effect)
+    }
+}
+
+impl From<ChromaticAberration> for PostEffect {
+    fn from(effect: ChromaticAberration) -> Self {
+        PostEffect::ChromaticAberration(effect)
+    }
+}
+
+impl From<Crt> for PostEffect {
+    fn from(effect: Crt) -> Self {
+        PostEffect::Crt(effect)
+    }
+}
+
+impl From<Blur> for PostEffect {
+    fn from(effect: Blur) -> Self {
+        PostEffect::Blur(effect)
+    }
+}
+
+/// The full screen passes effects are made of, one for each `shaders/post_*.wgsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PostShader {
+    Copy,
+    BloomExtract,
+    Blur,
+    BloomComposite,
+    ColorGrade,
+    Vignette,
+    ChromaticAberration,
+    Crt,
+}
+
+impl PostShader {
+    const ALL: [Self; 8] = [
+        Self::Copy,
+        Self::BloomExtract,
+        Self::Blur,
+        Self::BloomComposite,
+        Self::ColorGrade,
+        Self::Vignette,
+        Self::ChromaticAberration,
+        Self::Crt,
+    ];
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Copy => "post_copy.wgsl",
+            Self::BloomExtract => "post_bloom_extract.wgsl",
+            Self::Blur => "post_blur.wgsl",
+            Self::BloomComposite => "post_bloom_composite.wgsl",
+            Self::ColorGrade => "post_color_grade.wgsl",
+            Self::Vignette => "post_vignette.wgsl",
+            Self::ChromaticAberration => "post_chromatic_aberration.wgsl",
+            Self::Crt => "post_crt.wgsl",
+        }
+    }
+}
+
+/// Group 0 of every post processing pass, as declared in `post.wgsl`.
+const POST_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 4,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<PostUniform>() as u64),
+        },
+        count: None,
+    },
+];
+
+/// The `Params` of a pass: the texel size of its input, then the pass's own parameters laid
+/// out as its shader's `Params` struct declares them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    texel_size: [f32; 2],
+    params: [f32; 14],
+}
+
+/// The textures passes read from and render to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// What the scene is rendered into, and then the ping pong targets effects alternate
+    /// between.
+    Scene,
+    Ping,
+    /// Half resolution targets for bloom.
+    BloomA,
+    BloomB,
+    /// The texture passed to [`PostProcessor::apply`].
+    Output,
+    /// The lookup table of the `ColorGrade` effect at this index.
+    Lut(usize),
+}
+
+/// The second texture a pass samples.
+#[derive(Debug, Clone)]
+enum Extra {
+    None,
+    Target(Slot),
+    Lut(Arc<Texture>),
+}
+
+impl PartialEq for Extra {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Extra::None, Extra::None) => true,
+            (Extra::Target(a), Extra::Target(b)) => a == b,
+            (Extra::Lut(a), Extra::Lut(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// The bind group of the textures a pass reads, kept from frame to frame. Each pass binds its
+/// uniforms at its own dynamic offset, so passes that read the same textures share one.
+#[derive(Debug)]
+struct PassBindGroup {
+    input: Slot,
+    extra: Extra,
+    bind_group: wgpu::BindGroup,
+}
+
+struct Pass {
+    shader: PostShader,
+    input: Slot,
+    extra: Option<Slot>,
+    output: Slot,
+    params: [f32; 14],
+}
+
+#[derive(Debug)]
+struct PostTargets {
+    scene: RenderTarget,
+    ping: RenderTarget,
+    bloom: [RenderTarget; 2],
+}
+
+impl PostTargets {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        Self {
+            // The renderer draws the scene, so it needs depth.
+            scene: RenderTarget::new(device, format, width, height, true, "Post Scene Target"),
+            ping: RenderTarget::new(device, format, width, height, false, "Post Ping Target"),
+            bloom: [
+                RenderTarget::new(
+                    device,
+                    format,
+                    width / 2,
+                    height / 2,
+                    false,
+                    "Post Bloom Target",
+                ),
+                RenderTarget::new(
+                    device,
+                    format,
+                    width / 2,
+                    height / 2,
+                    false,
+                    "Post Bloom Target",
+                ),
+            ],
+        }
+    }
+}
+
+/// Renders the scene into an offscreen target, then runs a chain of full screen effects over it
+/// on the way to the surface. Created with [`crate::renderer::Renderer::create_post_processor`].
+#[derive(Debug)]
+pub struct PostProcessor {
+    /// Applied in order. Can be changed freely between frames.
+    pub effects: Vec<PostEffect>,
+    pipelines: Vec<wgpu::RenderPipeline>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Bound as the extra texture of passes that don't use one.
+    placeholder: RenderTarget,
+    uniform_buffer: wgpu::Buffer,
+    /// The number of passes `uniform_buffer` has room for.
+    uniform_capacity: usize,
+    uniform_stride: u64,
+    /// Dropped when the targets or uniform buffer are recreated, and when the effects change so
+    /// that no pass reads their textures.
+    bind_groups: Vec<PassBindGroup>,
+    format: wgpu::TextureFormat,
+    targets: PostTargets,
+}
+
+impl PostProcessor {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        preprocessor: &Preprocessor,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Bind Group Layout"),
+            entries: POST_LAYOUT_ENTRIES,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        // Every pass replaces the whole of its target.
+        let factory = PipelineFactory {
+            color_format: format,
+            blend_state: wgpu::BlendState::REPLACE,
+            depth_stencil_state: None,
+        };
+        let pipelines = PostShader::ALL
+            .iter()
+            .map(|shader| {
+                let source =
+                    preprocessor.preprocess_file(shader.file_name(), &ShaderDefines::new())?;
+                let shader_module = Shader::from_preprocessed(shader.file_name(), &source)?;
+                factory.create(
+                    device,
+                    &shader_module,
+                    &PipelineDescriptor {
+                        label: shader.file_name(),
+                        layout: &pipeline_layout,
+                        bind_group_layouts: &[POST_LAYOUT_ENTRIES],
+                        buffers: &[],
+                        blend: None,
+                        cull_mode: None,
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let placeholder = RenderTarget::new(device, format, 1, 1, false, "Post Placeholder");
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let uniform_stride =
+            (std::mem::size_of::<PostUniform>() as u64).div_ceil(alignment) * alignment;
+        let uniform_capacity = 8;
+        let uniform_buffer = create_uniform_buffer(device, uniform_stride, uniform_capacity);
+
+        Ok(Self {
+            effects: Vec::new(),
+            pipelines,
+            bind_group_layout,
+            sampler,
+            placeholder,
+            uniform_buffer,
+            uniform_capacity,
+            uniform_stride,
+            bind_groups: Vec::new(),
+            format,
+            targets: PostTargets::new(device, format, width, height),
+        })
+    }
+
+    pub fn with_effect(mut self, effect: impl Into<PostEffect>) -> Self {
+        self.effects.push(effect.into());
+        self
+    }
+
+    /// The target to render the scene into, instead of the surface.
+    pub fn scene_target(&self) -> &RenderTarget {
+        &self.targets.scene
+    }
+
+    /// Resize the offscreen targets to match the surface.
+    pub fn resize(&mut self, bananas: &Bananas) {
+        self.targets = PostTargets::new(
+            &bananas.device,
+            self.format,
+            bananas.config.width,
+            bananas.config.height,
+        );
+        self.bind_groups.clear();
+    }
+
+    /// Run every effect over the scene target, writing the result to `output`.
+    pub fn apply(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+    ) {
+        let passes = self.passes();
+
+        if passes.len() > self.uniform_capacity {
+            self.uniform_capacity = passes.len().next_power_of_two();
+            self.uniform_buffer =
+                create_uniform_buffer(device, self.uniform_stride, self.uniform_capacity);
+            self.bind_groups.clear();
+        }
+        let mut uniforms = vec![0; self.uniform_stride as usize * passes.len()];
+        for (i, pass) in passes.iter().enumerate() {
+            let input = self.target(pass.input);
+            let uniform = PostUniform {
+                texel_size: [1.0 / input.width() as f32, 1.0 / input.height() as f32],
+                params: pass.params,
+            };
+            let offset = i * self.uniform_stride as usize;
+            uniforms[offset..offset + std::mem::size_of::<PostUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, &uniforms);
+
+        let extras = passes
+            .iter()
+            .map(|pass| self.extra(pass.extra))
+            .collect::<Vec<_>>();
+        self.bind_groups.retain(|cached| {
+            passes
+                .iter()
+                .zip(&extras)
+                .any(|(pass, extra)| cached.input == pass.input && cached.extra == *extra)
+        });
+        for (pass, extra) in passes.iter().zip(&extras) {
+            if self.bind_group(pass.input, extra).is_none() {
+                let bind_group = self.create_bind_group(device, pass.input, extra);
+                self.bind_groups.push(PassBindGroup {
+                    input: pass.input,
+                    extra: extra.clone(),
+                    bind_group,
+                });
+            }
+        }
+
+        for (i, (pass, extra)) in passes.iter().zip(&extras).enumerate() {
+            let bind_group = self
+                .bind_group(pass.input, extra)
+                .expect("every pass's bind group was just created");
+            let target = match pass.output {
+                Slot::Output => output,
+                slot => &self.target(slot).color().view,
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.shader.file_name()),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipelines[pass.shader as usize]);
+            let offset = (i as u64 * self.uniform_stride) as u32;
+            render_pass.set_bind_group(0, bind_group, &[offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn extra(&self, slot: Option<Slot>) -> Extra {
+        match slot {
+            Some(Slot::Lut(effect)) => match &self.effects[effect] {
+                PostEffect::ColorGrade(grade) => Extra::Lut(grade.lut.texture.clone()),
+                _ => unreachable!("only color grades have lookup tables"),
+            },
+            Some(slot) => Extra::Target(slot),
+            None => Extra::None,
+        }
+    }
+
+    fn bind_group(&self, input: Slot, extra: &Extra) -> Option<&wgpu::BindGroup> {
+        self.bind_groups
+            .iter()
+            .find(|cached| cached.input == input && cached.extra == *extra)
+            .map(|cached| &cached.bind_group)
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        input: Slot,
+        extra: &Extra,
+    ) -> wgpu::BindGroup {
+        let (extra_view, extra_sampler) = match extra {
+            Extra::None => (&self.placeholder.color().view, &self.sampler),
+            Extra::Target(slot) => (&self.target(*slot).color().view, &self.sampler),
+            Extra::Lut(texture) => (&texture.view, &texture.sampler),
+        };
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.target(input).color().view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(extra_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(extra_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<PostUniform>() as u64),
+                    }),
+                },
+            ],
+        })
+    }
+
+    fn target(&self, slot: Slot) -> &RenderTarget {
+        match slot {
+            Slot::Scene => &self.targets.scene,
+            Slot::Ping => &self.targets.ping,
+            Slot::BloomA => &self.targets.bloom[0],
+            Slot::BloomB => &self.targets.bloom[1],
+            Slot::Output | Slot::Lut(_) => unreachable!("{:?} isn't a post target", slot),
+        }
+    }
+
+    /// Turn the effects into passes, ping ponging between the scene and ping targets.
+    fn passes(&self) -> Vec<Pass> {
+        let mut passes = Vec::new();
+        let mut current = Slot::Scene;
+        let other = |slot| match slot {
+            Slot::Scene => Slot::Ping,
+            _ => Slot::Scene,
+        };
+        let pass = |shader, input, extra, output, params: &[f32]| {
+            let mut padded = [0.0; 14];
+            padded[..params.len()].copy_from_slice(params);
+            Pass {
+                shader,
+                input,
+                extra,
+                output,
+                params: padded,
+            }
+        };
+
+        for (index, effect) in self.effects.iter().enumerate() {
+            // The parameters must match the `Params` of each pass's shader.
+            match effect {
+                PostEffect::Bloom(bloom) => {
+                    passes.extend([
+                        pass(
+                            PostShader::BloomExtract,
+                            current,
+                            None,
+                            Slot::BloomA,
+                            &[bloom.threshold, bloom.soft_knee],
+                        ),
+                        pass(
+                            PostShader::Blur,
+                            Slot::BloomA,
+                            None,
+                            Slot::BloomB,
+                            &[bloom.radius, 0.0],
+                        ),
+                        pass(
+                            PostShader::Blur,
+                            Slot::BloomB,
+                            None,
+                            Slot::BloomA,
+                            &[0.0, bloom.radius],
+                        ),
+                        pass(
+                            PostShader::BloomComposite,
+                            current,
+                            Some(Slot::BloomA),
+                            other(current),
+                            &[bloom.intensity],
+                        ),
+                    ]);
+                    current = other(current);
+                }
+                PostEffect::ColorGrade(grade) => {
+                    passes.push(pass(
+                        PostShader::ColorGrade,
+                        current,
+                        Some(Slot::Lut(index)),
+                        other(current),
+                        &[grade.intensity, grade.lut.size as f32],
+                    ));
+                    current = other(current);
+                }
+                PostEffect::Vignette(vignette) => {
+                    let [r, g, b, a] = vignette.color;
+                    passes.push(pass(
+                        PostShader::Vignette,
+                        current,
+                        None,
+                        other(current),
+                        // The color is aligned to 16 bytes, after 3 floats of padding.
+                        &[
+                            vignette.intensity,
+                            vignette.radius,
+                            vignette.softness,
+                            0.0,
+                            0.0,
+                            0.0,
+                            r,
+                            g,
+                            b,
+                            a,
+                        ],
+                    ));
+                    current = other(current);
+                }
+                PostEffect::ChromaticAberration(aberration) => {
+                    passes.push(pass(
+                        PostShader::ChromaticAberration,
+                        current,
+                        None,
+                        other(current),
+                        &[aberration.amount],
+                    ));
+                    current = other(current);
+                }
+                PostEffect::Crt(crt) => {
+                    passes.push(pass(
+                        PostShader::Crt,
+                        current,
+                        None,
+                        other(current),
+                        &[crt.curvature, crt.scanline_intensity, crt.scanline_count],
+                    ));
+                    current = other(current);
+                }
+                PostEffect::Blur(blur) => {
+                    passes.extend([
+                        pass(
+                            PostShader::Blur,
+                            current,
+                            None,
+                            other(current),
+                            &[blur.radius, 0.0],
+                        ),
+                        pass(
+                            PostShader::Blur,
+                            other(current),
+                            None,
+                            current,
+                            &[0.0, blur.radius],
+                        ),
+                    ]);
+                }
+            }
+        }
+
+        match passes.last_mut() {
+            Some(last) => last.output = Slot::Output,
+            None => passes.push(pass(PostShader::Copy, current, None, Slot::Output, &[])),
+        }
+        passes
+    }
+}
+
+fn create_uniform_buffer(device: &wgpu::Device, stride: u64, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Post Uniform Buffer"),
+        size: stride * capacity as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
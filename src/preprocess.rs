@@ -0,0 +1,383 @@
+//! A small preprocessor for WGSL, so that shaders can share code and be specialized into
+//! variants. Directives start a line with `#`:
+//!
+//! - `#include "name.wgsl"` pastes in another file, once per shader however often it is included,
+//! - `#define NAME` or `#define NAME value` and `#undef NAME`, where a value replaces the name
+//!   wherever it appears as an identifier,
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`.
+//!
+//! This file is also compiled into `build.rs`, so it only depends on std and anyhow.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+
+/// The names defined before a shader is preprocessed, which select its variant.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeMap<String, String>);
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define `name` without a value, for `#ifdef`.
+    pub fn with(self, name: &str) -> Self {
+        self.with_value(name, "")
+    }
+
+    pub fn with_value(mut self, name: &str, value: impl ToString) -> Self {
+        self.define(name, value);
+        self
+    }
+
+    pub fn define(&mut self, name: &str, value: impl ToString) {
+        self.0.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+/// WGSL that has been preprocessed, and where each of its lines came from.
+#[derive(Debug, Clone, Default)]
+pub struct PreprocessedSource {
+    source: String,
+    /// The preprocessed file first, then the files it includes.
+    files: Vec<String>,
+    /// The index into `files` and 1-based line number of each line of `source`.
+    lines: Vec<(usize, u32)>,
+}
+
+impl PreprocessedSource {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The preprocessed file and every file it included.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The file and line that the 1-based `line` of the preprocessed source came from.
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = *self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], line))
+    }
+}
+
+/// Resolves includes, from files in a directory or from sources registered with it.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    includes: HashMap<String, String>,
+    /// Checked before `includes`, so that files can be edited while running.
+    include_dir: Option<PathBuf>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_include(&mut self, name: &str, source: impl Into<String>) {
+        self.includes.insert(name.to_string(), source.into());
+    }
+
+    pub fn set_include_dir(&mut self, dir: Option<PathBuf>) {
+        self.include_dir = dir;
+    }
+
+    pub fn include_dir(&self) -> Option<&Path> {
+        self.include_dir.as_deref()
+    }
+
+    /// The source of the file `name`.
+    pub fn load(&self, name: &str) -> Result<String> {
+        if let Some(dir) = &self.include_dir {
+            let path = dir.join(name);
+            if path.is_file() {
+                return std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()));
+            }
+        }
+        self.includes
+            .get(name)
+            .cloned()
+            .with_context(|| format!("no shader named {}", name))
+    }
+
+    pub fn preprocess_file(
+        &self,
+        name: &str,
+        defines: &ShaderDefines,
+    ) -> Result<PreprocessedSource> {
+        let source = self.load(name)?;
+        self.preprocess(name, &source, defines)
+    }
+
+    /// Preprocess `source`, named `name` in errors and in the result.
+    pub fn preprocess(
+        &self,
+        name: &str,
+        source: &str,
+        defines: &ShaderDefines,
+    ) -> Result<PreprocessedSource> {
+        let mut defines = defines.clone();
+        let mut output = PreprocessedSource::default();
+        self.process(name, source, &mut defines, &mut output)?;
+        Ok(output)
+    }
+
+    fn process(
+        &self,
+        name: &str,
+        source: &str,
+        defines: &mut ShaderDefines,
+        output: &mut PreprocessedSource,
+    ) -> Result<()> {
+        let file = output.files.len();
+        output.files.push(name.to_string());
+
+        // Whether each enclosing #ifdef is taken, and the line it started on.
+        let mut conditions: Vec<Condition> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let active = conditions.iter().all(|condition| condition.taken);
+
+            let directive = match line.trim_start().strip_prefix('#') {
+                Some(directive) => directive,
+                None => {
+                    if active {
+                        output.source += &substitute(line, defines);
+                        output.source.push('\n');
+                        output.lines.push((file, line_number));
+                    }
+                    continue;
+                }
+            };
+
+            let (keyword, argument) = match directive.split_once(char::is_whitespace) {
+                Some((keyword, argument)) => (keyword, argument.trim()),
+                None => (directive.trim(), ""),
+            };
+            let result = match keyword {
+                "ifdef" | "ifndef" => parse_name(argument).map(|define| {
+                    conditions.push(Condition {
+                        taken: defines.contains(define) == (keyword == "ifdef"),
+                        has_else: false,
+                        line: line_number,
+                    });
+                }),
+                "else" => match conditions.last_mut() {
+                    Some(condition) if !condition.has_else => {
+                        condition.taken = !condition.taken;
+                        condition.has_else = true;
+                        Ok(())
+                    }
+                    Some(_) => Err(anyhow!("#else after #else")),
+                    None => Err(anyhow!("#else without #ifdef")),
+                },
+                "endif" => conditions
+                    .pop()
+                    .map(|_| ())
+                    .context("#endif without #ifdef"),
+                _ if !active => Ok(()),
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .unwrap_or((argument, ""));
+                    parse_name(define).map(|define| defines.define(define, value.trim()))
+                }
+                "undef" => parse_name(argument).map(|define| defines.undefine(define)),
+                "include" => self.include(argument, defines, output),
+                _ => Err(anyhow!("unknown directive #{}", keyword)),
+            };
+            result.with_context(|| format!("{}:{}", name, line_number))?;
+        }
+
+        if let Some(condition) = conditions.last() {
+            bail!("{}:{}: #ifdef without #endif", name, condition.line);
+        }
+        Ok(())
+    }
+
+    fn include(
+        &self,
+        argument: &str,
+        defines: &mut ShaderDefines,
+        output: &mut PreprocessedSource,
+    ) -> Result<()> {
+        let include = argument
+            .strip_prefix('"')
+            .and_then(|argument| argument.strip_suffix('"'))
+            .context("expected a quoted file name after #include")?;
+        // Every file is included at most once, which also breaks include cycles.
+        if output.files.iter().any(|file| file == include) {
+            return Ok(());
+        }
+        let source = self.load(include)?;
+        self.process(include, &source, defines, output)
+    }
+}
+
+struct Condition {
+    taken: bool,
+    has_else: bool,
+    line: u32,
+}
+
+fn parse_name(argument: &str) -> Result<&str> {
+    let mut chars = argument.chars();
+    ensure!(
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        "expected a name, found `{}`",
+        argument
+    );
+    Ok(argument)
+}
+
+/// Replace every identifier in `line` that is defined with a value.
+fn substitute(line: &str, defines: &ShaderDefines) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        output += &rest[..start];
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        match defines.get(word) {
+            Some(value) if !value.is_empty() => output += value,
+            _ => output += word,
+        }
+        rest = &rest[end..];
+    }
+    output + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor() -> Preprocessor {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.add_include("common.wgsl", "let common = 1;");
+        preprocessor.add_include("a.wgsl", "#include \"b.wgsl\"\nlet a = 1;");
+        preprocessor.add_include("b.wgsl", "#include \"a.wgsl\"\nlet b = 1;");
+        preprocessor
+    }
+
+    fn lines(preprocessed: &PreprocessedSource) -> Vec<&str> {
+        preprocessed.source().lines().collect()
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let source = "#include \"common.wgsl\"\n#include \"common.wgsl\"\nlet main = 1;";
+        let preprocessed = preprocessor()
+            .preprocess("main.wgsl", source, &ShaderDefines::new())
+            .unwrap();
+        assert_eq!(lines(&preprocessed), ["let common = 1;", "let main = 1;"]);
+        assert_eq!(preprocessed.files(), ["main.wgsl", "common.wgsl"]);
+        assert_eq!(preprocessed.origin(1), Some(("common.wgsl", 1)));
+        assert_eq!(preprocessed.origin(2), Some(("main.wgsl", 3)));
+        assert_eq!(preprocessed.origin(3), None);
+    }
+
+    #[test]
+    fn include_cycles_end() {
+        let preprocessed = preprocessor()
+            .preprocess_file("a.wgsl", &ShaderDefines::new())
+            .unwrap();
+        assert_eq!(lines(&preprocessed), ["let b = 1;", "let a = 1;"]);
+        assert_eq!(preprocessed.files(), ["a.wgsl", "b.wgsl"]);
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "\
+#ifdef OUTER
+#ifndef INNER
+let outer_only = 1;
+#else
+let both = 1;
+#endif
+#else
+let neither = 1;
+#endif";
+        let preprocess = |defines: ShaderDefines| {
+            let preprocessed = Preprocessor::new()
+                .preprocess("main.wgsl", source, &defines)
+                .unwrap();
+            preprocessed.source().to_string()
+        };
+        assert_eq!(preprocess(ShaderDefines::new()), "let neither = 1;\n");
+        assert_eq!(
+            preprocess(ShaderDefines::new().with("OUTER")),
+            "let outer_only = 1;\n"
+        );
+        assert_eq!(
+            preprocess(ShaderDefines::new().with("OUTER").with("INNER")),
+            "let both = 1;\n"
+        );
+        assert_eq!(
+            preprocess(ShaderDefines::new().with("INNER")),
+            "let neither = 1;\n"
+        );
+    }
+
+    #[test]
+    fn defines_substitute_identifiers() {
+        let source = "#define SIZE 4\nlet size = SIZE + SIZE_2;";
+        let preprocessed = Preprocessor::new()
+            .preprocess("main.wgsl", source, &ShaderDefines::new())
+            .unwrap();
+        assert_eq!(preprocessed.source(), "let size = 4 + SIZE_2;\n");
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        let error = |source: &str| {
+            let error = preprocessor()
+                .preprocess("main.wgsl", source, &ShaderDefines::new())
+                .unwrap_err();
+            format!("{:#}", error)
+        };
+        assert_eq!(
+            error("let a = 1;\n#ifdef A\n#else\n#else\n#endif"),
+            "main.wgsl:4: #else after #else"
+        );
+        assert_eq!(
+            error("let a = 1;\n#endif"),
+            "main.wgsl:2: #endif without #ifdef"
+        );
+        assert_eq!(
+            error("#ifdef A\nlet a = 1;"),
+            "main.wgsl:1: #ifdef without #endif"
+        );
+        assert_eq!(
+            error("\n#include \"missing.wgsl\""),
+            "main.wgsl:2: no shader named missing.wgsl"
+        );
+        assert_eq!(
+            error("#pragma once"),
+            "main.wgsl:1: unknown directive #pragma"
+        );
+    }
+}
@@ -1,18 +1,28 @@
-use std::ops::Range;
-
-use glam::Mat4;
-use lyon::{
-    geom::{point, Box2D},
-    lyon_tessellation::{
-        BuffersBuilder, FillOptions, FillTessellator, FillVertexConstructor, StrokeOptions,
-        StrokeTessellator, StrokeVertexConstructor, VertexBuffers,
-    },
-    path::{Path, Winding},
-};
+use std::{cell::Cell, collections::HashMap, marker::PhantomData, ops::Range};
+
+use anyhow::*;
+use glam::Affine2;
+use lyon::geom::{point, Box2D, Point};
 use wgpu::{util::DeviceExt, RenderPass};
 use winit::window::Window;
 
-use crate::{texture::Texture, DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH};
+use crate::{
+    camera::{Camera, ClearMode, LayerMask},
+    culling::TransformRange,
+    material::{Material, MaterialDescriptor, MaterialInstance, MaterialKind},
+    paint::PAINT_LAYOUT_ENTRIES,
+    particles::{ParticleInstance, ParticleSystem},
+    pipeline::{PipelineDescriptor, PipelineFactory},
+    postprocess::PostProcessor,
+    preprocess::{Preprocessor, ShaderDefines},
+    scene::{SceneBatch, SceneGraph},
+    shader::{Shader, ShaderError, ShaderWatcher},
+    shape::{ShapeBuilder, StrokeStyle},
+    target::{create_depth_view, RenderPassTarget, RenderTarget, DEPTH_FORMAT},
+    texture::Texture,
+    uniforms::{FrameUniforms, UniformOffset, FRAME_UNIFORMS_LAYOUT_ENTRIES},
+    vector::VectorGraphic,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -48,80 +58,449 @@ impl Vertex {
     }
 }
 
-const SHAPE_VERTICES: &[Vertex] = &[
+const SPRITE_VERTICES: &[Vertex] = &[
     Vertex {
-        position: [-8.68241, 49.240386, 0.0],
+        position: [-25.0, 25.0, 0.0],
         tex_coords: [0.0, 0.0],
-        color: [0.5, 0.0, 0.5],
+        color: [1.0, 1.0, 1.0],
     }, // A
     Vertex {
-        position: [-49.513406, 6.958647, 0.0],
-        tex_coords: [0.0, 0.0],
-        color: [0.5, 0.0, 0.5],
+        position: [-25.0, -75.0, 0.0],
+        tex_coords: [0.0, 1.0],
+        color: [1.0, 1.0, 1.0],
     }, // B
     Vertex {
-        position: [-21.918549, -44.939706, 0.0],
-        tex_coords: [0.0, 0.0],
-        color: [0.5, 0.0, 0.5],
+        position: [75.0, 25.0, 0.0],
+        tex_coords: [1.0, 0.0],
+        color: [1.0, 1.0, 1.0],
     }, // C
     Vertex {
-        position: [35.966998, -34.73291, 0.0],
-        tex_coords: [0.0, 0.0],
-        color: [0.5, 0.0, 0.5],
+        position: [75.0, -75.0, 0.0],
+        tex_coords: [1.0, 1.0],
+        color: [1.0, 1.0, 1.0],
     }, // D
-    Vertex {
-        position: [44.147372, 23.47359, 0.0],
-        tex_coords: [0.0, 0.0],
-        color: [0.5, 0.0, 0.5],
-    }, // E
 ];
 
-const SHAPE_INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, 0];
+const SPRITE_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
 
-const SPRITE_VERTICES: &[Vertex] = &[
+// A unit quad centred on the origin, scaled and positioned per instance.
+const QUAD_VERTICES: &[Vertex] = &[
     Vertex {
-        position: [-25.0, 25.0, 0.0],
+        position: [-0.5, 0.5, 0.0],
         tex_coords: [0.0, 0.0],
         color: [1.0, 1.0, 1.0],
     }, // A
     Vertex {
-        position: [-25.0, -75.0, 0.0],
+        position: [-0.5, -0.5, 0.0],
         tex_coords: [0.0, 1.0],
         color: [1.0, 1.0, 1.0],
     }, // B
     Vertex {
-        position: [75.0, 25.0, 0.0],
+        position: [0.5, 0.5, 0.0],
         tex_coords: [1.0, 0.0],
         color: [1.0, 1.0, 1.0],
     }, // C
     Vertex {
-        position: [75.0, -75.0, 0.0],
+        position: [0.5, -0.5, 0.0],
         tex_coords: [1.0, 1.0],
         color: [1.0, 1.0, 1.0],
     }, // D
 ];
 
-const SPRITE_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
+const QUAD_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
+
+/// The bounds of the unit quad every sprite is drawn on, before its transform.
+pub const QUAD_BOUNDS: Box2D<f32> = Box2D::new(Point::new(-0.5, -0.5), Point::new(0.5, 0.5));
+
+/// Per-instance data that can be collected into a [`Batch`] and drawn with one draw call.
+pub trait Instance: bytemuck::Pod {
+    const LABEL: &'static str;
+
+    /// Where the instance places its geometry, used to cull it.
+    fn transform(&self) -> Affine2;
+}
 
+/// Per-instance data for drawing a textured quad with [`Renderer::draw_sprites_instanced`].
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct GpuVertex {
-    position: [f32; 3],
-    color: [f32; 4],
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstance {
+    /// Columns of a 2D affine transform applied to the unit quad: x axis, y axis, translation.
+    pub transform: [[f32; 2]; 3],
+    /// The region of the texture to sample as `[min_u, min_v, max_u, max_v]`.
+    pub uv_rect: [f32; 4],
+    pub tint: [f32; 4],
 }
 
-unsafe impl bytemuck::Pod for GpuVertex {}
-unsafe impl bytemuck::Zeroable for GpuVertex {}
+impl SpriteInstance {
+    /// A sprite placed by `transform`, an [`Affine2`] or a [`crate::transform::Transform2D`].
+    pub fn new(transform: impl Into<Affine2>) -> Self {
+        Self {
+            transform: transform.into().to_cols_array_2d(),
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
 
-// // TODO: Combine these vertex structs
-// #[repr(C)]
-// #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-// struct ShapeVertex {
-//     position: [f32; 3],
-//     color: [f32; 3],
-// }
+    pub fn with_uv_rect(mut self, uv_rect: [f32; 4]) -> Self {
+        self.uv_rect = uv_rect;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            // Locations 0-2 are used by the quad vertices.
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+impl Instance for SpriteInstance {
+    const LABEL: &'static str = "Sprite Instance Buffer";
+
+    fn transform(&self) -> Affine2 {
+        Affine2::from_cols_array_2d(&self.transform)
+    }
+}
+
+/// Per-instance data for drawing tessellated geometry such as a [`VectorGraphic`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShapeInstance {
+    /// Columns of a 2D affine transform applied to the geometry: x axis, y axis, translation.
+    pub transform: [[f32; 2]; 3],
+    pub tint: [f32; 4],
+}
+
+impl ShapeInstance {
+    pub const IDENTITY: Self = Self {
+        transform: [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0]],
+        tint: [1.0, 1.0, 1.0, 1.0],
+    };
+
+    /// A shape placed by `transform`, an [`Affine2`] or a [`crate::transform::Transform2D`].
+    pub fn new(transform: impl Into<Affine2>) -> Self {
+        Self {
+            transform: transform.into().to_cols_array_2d(),
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShapeInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            // Locations 0-1 are used by the [`ShapeVertex`] attributes.
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+impl Instance for ShapeInstance {
+    const LABEL: &'static str = "Shape Instance Buffer";
+
+    fn transform(&self) -> Affine2 {
+        Affine2::from_cols_array_2d(&self.transform)
+    }
+}
+
+/// A growable vertex buffer of per-instance data that is rewritten every frame.
+pub(crate) struct InstanceBuffer<T> {
+    label: &'static str,
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> InstanceBuffer<T> {
+    const INITIAL_CAPACITY: usize = 1024;
+
+    pub(crate) fn new(device: &wgpu::Device, label: &'static str) -> Self {
+        Self {
+            label,
+            buffer: Self::create_buffer(device, label, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replace the contents of the buffer, growing it first if `instances` doesn't fit.
+    pub(crate) fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[T]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.label, self.capacity);
+        }
+
+        if !instances.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+        self.len = instances.len();
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.len as u32
+    }
+
+    pub(crate) fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer
+            .slice(..(self.len * std::mem::size_of::<T>()) as wgpu::BufferAddress)
+    }
+
+    fn create_buffer(device: &wgpu::Device, label: &str, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+/// A list of instances of the same thing, drawn with a single instanced draw call.
+///
+/// Cameras only draw batches that are on one of their layers and in their view. A batch spread
+/// over a world much bigger than a camera's view can instead be uploaded with
+/// [`Batch::prepare_culled`], to cull each instance for each camera.
+pub struct Batch<T> {
+    instances: Vec<T>,
+    instance_buffer: InstanceBuffer<T>,
+    layers: LayerMask,
+    /// The transforms of the uploaded instances, to cull the batch with.
+    extent: Option<TransformRange>,
+    /// The uploaded instances each camera draws, by where its view projection was pushed, if
+    /// the instances were culled for each camera. Empty if every camera draws them all.
+    views: Vec<(UniformOffset, Range<u32>)>,
+    /// The instances uploaded for every camera's view, reused each frame.
+    culled: Vec<T>,
+}
+
+/// Sprites sharing one texture, drawn with [`Renderer::draw_sprites_instanced`].
+pub type SpriteBatch = Batch<SpriteInstance>;
+
+/// Placements of one piece of geometry, drawn with [`Renderer::draw_vector_graphic`].
+pub type ShapeBatch = Batch<ShapeInstance>;
+
+impl<T: Instance> Batch<T> {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            instances: Vec::new(),
+            instance_buffer: InstanceBuffer::new(device, T::LABEL),
+            layers: LayerMask::DEFAULT,
+            extent: None,
+            views: Vec::new(),
+            culled: Vec::new(),
+        }
+    }
+
+    /// Only draw the batch with cameras that draw one of `layers`.
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn set_layers(&mut self, layers: LayerMask) {
+        self.layers = layers;
+    }
+
+    pub fn layers(&self) -> LayerMask {
+        self.layers
+    }
+
+    pub fn push(&mut self, instance: T) {
+        self.instances.push(instance);
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Upload the instances to the GPU. Must be called before the render pass that draws them
+    /// begins.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.instance_buffer.write(device, queue, &self.instances);
+        self.extent = TransformRange::of(self.instances.iter().map(T::transform));
+        self.views.clear();
+    }
+
+    /// Upload, for each of `cameras`, only the instances it can see, if each draws geometry
+    /// within `local`: [`QUAD_BOUNDS`] for sprites, or [`VectorGraphic::bounds`] for shapes.
+    /// Instances seen by several cameras are uploaded for each.
+    ///
+    /// The cameras must have been prepared with [`Camera::prepare`] this frame, and other
+    /// cameras draw none of the batch. Must be called before the render passes of those cameras
+    /// begin.
+    pub fn prepare_culled(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        local: Box2D<f32>,
+        cameras: &[&Camera],
+    ) {
+        self.prepare_views(device, queue, cameras, |camera, instances, visible| {
+            let rect = camera.visible_rect();
+            visible.extend(instances.iter().copied().filter(|instance| {
+                TransformRange::of([instance.transform()])
+                    .is_some_and(|range| range.bounds(local).intersects(&rect))
+            }));
+        });
+    }
+
+    /// Upload, for each of `cameras` drawing the batch's layers, the instances `visible` pushes
+    /// for it, given the batch's instances.
+    pub(crate) fn prepare_views(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cameras: &[&Camera],
+        mut visible: impl FnMut(&Camera, &[T], &mut Vec<T>),
+    ) {
+        let mut culled = std::mem::take(&mut self.culled);
+        culled.clear();
+        self.views.clear();
+        for camera in cameras {
+            if !camera.layers.intersects(self.layers) {
+                continue;
+            }
+            let start = culled.len() as u32;
+            visible(camera, &self.instances, &mut culled);
+            let (view_projection, _) = camera.uniform_offsets();
+            self.views
+                .push((view_projection, start..culled.len() as u32));
+        }
+        self.instance_buffer.write(device, queue, &culled);
+        self.extent = TransformRange::of(culled.iter().map(T::transform));
+        self.culled = culled;
+    }
+
+    /// The uploaded instances the camera whose view projection is at `camera` draws.
+    fn instances_for(&self, camera: Option<UniformOffset>) -> Range<u32> {
+        if self.views.is_empty() {
+            return 0..self.instance_buffer.len();
+        }
+        camera
+            .and_then(|camera| self.views.iter().find(|(view, _)| *view == camera))
+            .map_or(0..0, |(_, range)| range.clone())
+    }
+
+    /// A box holding everything the uploaded instances draw, if each draws geometry within
+    /// `local`, or `None` if there are no instances.
+    pub fn bounds(&self, local: Box2D<f32>) -> Option<Box2D<f32>> {
+        self.extent.map(|extent| extent.bounds(local))
+    }
+}
+
+impl<T> Extend<T> for Batch<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.instances.extend(iter);
+    }
+}
+
+/// The vertex format of all tessellated shapes, drawn by `shape.wgsl`. Shapes are positioned with
+/// a [`ShapeInstance`] in a second, per-instance buffer.
+///
+/// | location | attribute  | format      |
+/// |----------|------------|-------------|
+/// | 0        | `position` | `Float32x3` |
+/// | 1        | `color`    | `Float32x4` |
+///
+/// `position` is in the graphic's local space, which paints are also described in. `color` is
+/// the solid color of the fill or stroke, or white for shapes colored by a gradient or pattern.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShapeVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl ShapeVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShapeVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
 
-// // TODO: Combine these vertex structs
 // #[repr(C)]
 // #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 // struct SpriteVertex {
@@ -129,27 +508,6 @@ unsafe impl bytemuck::Zeroable for GpuVertex {}
 //     tex_coords: [f32; 2],
 // }
 
-// impl ShapeVertex {
-//     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-//         wgpu::VertexBufferLayout {
-//             array_stride: std::mem::size_of::<ShapeVertex>() as wgpu::BufferAddress,
-//             step_mode: wgpu::VertexStepMode::Vertex,
-//             attributes: &[
-//                 wgpu::VertexAttribute {
-//                     offset: 0,
-//                     shader_location: 0,
-//                     format: wgpu::VertexFormat::Float32x3,
-//                 },
-//                 wgpu::VertexAttribute {
-//                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-//                     shader_location: 1,
-//                     format: wgpu::VertexFormat::Float32x3,
-//                 },
-//             ],
-//         }
-//     }
-// }
-
 // impl SpriteVertex {
 //     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
 //         wgpu::VertexBufferLayout {
@@ -171,31 +529,6 @@ unsafe impl bytemuck::Zeroable for GpuVertex {}
 //     }
 // }
 
-// const SHAPE_VERTICES: &[ShapeVertex] = &[
-//     ShapeVertex {
-//         position: [-0.0868241, 0.49240386, 0.0],
-//         color: [0.5, 0.0, 0.5],
-//     }, // A
-//     ShapeVertex {
-//         position: [-0.49513406, 0.06958647, 0.0],
-//         color: [0.5, 0.0, 0.5],
-//     }, // B
-//     ShapeVertex {
-//         position: [-0.21918549, -0.44939706, 0.0],
-//         color: [0.5, 0.0, 0.5],
-//     }, // C
-//     ShapeVertex {
-//         position: [0.35966998, -0.3473291, 0.0],
-//         color: [0.5, 0.0, 0.5],
-//     }, // D
-//     ShapeVertex {
-//         position: [0.44147372, 0.2347359, 0.0],
-//         color: [0.5, 0.0, 0.5],
-//     }, // E
-// ];
-
-// const SHAPE_INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, 0];
-
 // const SPRITE_VERTICES: &[SpriteVertex] = &[
 //     SpriteVertex {
 //         position: [-0.25, 0.25, 0.0],
@@ -217,18 +550,221 @@ unsafe impl bytemuck::Zeroable for GpuVertex {}
 
 // const SPRITE_INDICES: &[u16] = &[0, 1, 2, 2, 1, 3];
 
+/// Group 1 of the sprite and particle pipelines: a texture and its sampler.
+pub(crate) const SPRITE_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        // This should match the filterable field of the
+        // corresponding Texture entry above.
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+];
+
+/// Every file in `shaders/`, embedded so that they can be included without the source tree.
+pub(crate) const BUILTIN_SHADER_SOURCES: &[(&str, &str)] = &[
+    (
+        "view_projection.wgsl",
+        include_str!("../shaders/view_projection.wgsl"),
+    ),
+    ("sprite.wgsl", include_str!("../shaders/sprite.wgsl")),
+    ("shape.wgsl", include_str!("../shaders/shape.wgsl")),
+    (
+        "particle_shader.wgsl",
+        include_str!("../shaders/particle_shader.wgsl"),
+    ),
+    ("clear.wgsl", include_str!("../shaders/clear.wgsl")),
+    (
+        "material_sprite.wgsl",
+        include_str!("../shaders/material_sprite.wgsl"),
+    ),
+    (
+        "material_shape.wgsl",
+        include_str!("../shaders/material_shape.wgsl"),
+    ),
+    ("post.wgsl", include_str!("../shaders/post.wgsl")),
+    ("post_copy.wgsl", include_str!("../shaders/post_copy.wgsl")),
+    (
+        "post_bloom_extract.wgsl",
+        include_str!("../shaders/post_bloom_extract.wgsl"),
+    ),
+    ("post_blur.wgsl", include_str!("../shaders/post_blur.wgsl")),
+    (
+        "post_bloom_composite.wgsl",
+        include_str!("../shaders/post_bloom_composite.wgsl"),
+    ),
+    (
+        "post_color_grade.wgsl",
+        include_str!("../shaders/post_color_grade.wgsl"),
+    ),
+    (
+        "post_vignette.wgsl",
+        include_str!("../shaders/post_vignette.wgsl"),
+    ),
+    (
+        "post_chromatic_aberration.wgsl",
+        include_str!("../shaders/post_chromatic_aberration.wgsl"),
+    ),
+    ("post_crt.wgsl", include_str!("../shaders/post_crt.wgsl")),
+];
+
+/// The shaders the renderer's own pipelines are built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BuiltinShader {
+    Sprite,
+    SpriteInstanced,
+    Shape,
+    Particle,
+    Clear,
+    ClearDepth,
+}
+
+impl BuiltinShader {
+    const ALL: [Self; 6] = [
+        Self::Sprite,
+        Self::SpriteInstanced,
+        Self::Shape,
+        Self::Particle,
+        Self::Clear,
+        Self::ClearDepth,
+    ];
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Sprite | Self::SpriteInstanced => "sprite.wgsl",
+            Self::Shape => "shape.wgsl",
+            Self::Particle => "particle_shader.wgsl",
+            Self::Clear | Self::ClearDepth => "clear.wgsl",
+        }
+    }
+
+    /// Must match the variants build.rs validates.
+    fn defines(self) -> ShaderDefines {
+        match self {
+            Self::SpriteInstanced => ShaderDefines::new().with("INSTANCED"),
+            _ => ShaderDefines::new(),
+        }
+    }
+
+    /// Returns the shader and the files it was preprocessed from.
+    fn compile(self, preprocessor: &Preprocessor) -> Result<(Shader, Vec<String>)> {
+        let source = preprocessor.preprocess_file(self.file_name(), &self.defines())?;
+        let shader = Shader::from_preprocessed(self.file_name(), &source)?;
+        Ok((shader, source.files().to_vec()))
+    }
+}
+
+/// The pipeline layouts shared by the built in pipelines.
+#[derive(Debug)]
+struct BuiltinLayouts {
+    /// Uniforms and a texture, for sprites and particles.
+    sprite: wgpu::PipelineLayout,
+    /// Uniforms and a paint.
+    shape: wgpu::PipelineLayout,
+    /// Just the frame uniforms, for the clear color.
+    clear: wgpu::PipelineLayout,
+}
+
+impl BuiltinLayouts {
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        factory: &PipelineFactory,
+        builtin: BuiltinShader,
+        shader: &Shader,
+    ) -> Result<wgpu::RenderPipeline> {
+        let sprite_layouts: &[&[wgpu::BindGroupLayoutEntry]] =
+            &[FRAME_UNIFORMS_LAYOUT_ENTRIES, SPRITE_LAYOUT_ENTRIES];
+        let descriptor = match builtin {
+            BuiltinShader::Sprite => PipelineDescriptor {
+                label: "Sprite Pipeline",
+                layout: &self.sprite,
+                bind_group_layouts: sprite_layouts,
+                buffers: &[Vertex::desc()],
+                blend: None,
+                cull_mode: Some(wgpu::Face::Back),
+            },
+            BuiltinShader::SpriteInstanced => PipelineDescriptor {
+                label: "Instanced Sprite Pipeline",
+                layout: &self.sprite,
+                bind_group_layouts: sprite_layouts,
+                buffers: &[Vertex::desc(), SpriteInstance::desc()],
+                blend: None,
+                // A negative scale in the instance transform flips the quad, so don't cull it.
+                cull_mode: None,
+            },
+            BuiltinShader::Shape => PipelineDescriptor {
+                label: "Shape Pipeline",
+                layout: &self.shape,
+                bind_group_layouts: &[FRAME_UNIFORMS_LAYOUT_ENTRIES, PAINT_LAYOUT_ENTRIES],
+                buffers: &[ShapeVertex::desc(), ShapeInstance::desc()],
+                blend: None,
+                // Lyon doesn't guarantee a consistent winding order, and instance transforms
+                // or imported SVGs may be mirrored.
+                cull_mode: None,
+            },
+            BuiltinShader::Particle => PipelineDescriptor {
+                label: "Particle Pipeline",
+                layout: &self.sprite,
+                bind_group_layouts: sprite_layouts,
+                buffers: &[Vertex::desc(), ParticleInstance::desc()],
+                // Particles fade out through their color over life, so they always blend.
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                cull_mode: Some(wgpu::Face::Back),
+            },
+            BuiltinShader::Clear => PipelineDescriptor {
+                label: "Clear Pipeline",
+                layout: &self.clear,
+                bind_group_layouts: &[FRAME_UNIFORMS_LAYOUT_ENTRIES],
+                buffers: &[],
+                blend: Some(wgpu::BlendState::REPLACE),
+                cull_mode: None,
+            },
+            BuiltinShader::ClearDepth => PipelineDescriptor {
+                label: "Clear Depth Pipeline",
+                layout: &self.clear,
+                bind_group_layouts: &[FRAME_UNIFORMS_LAYOUT_ENTRIES],
+                buffers: &[],
+                // Keep the color that's there.
+                blend: Some(wgpu::BlendState {
+                    color: KEEP_DESTINATION,
+                    alpha: KEEP_DESTINATION,
+                }),
+                cull_mode: None,
+            },
+        };
+        match builtin {
+            // Clears overwrite whatever depth is there.
+            BuiltinShader::Clear | BuiltinShader::ClearDepth => factory
+                .with_depth_compare(wgpu::CompareFunction::Always)
+                .create(device, shader, &descriptor),
+            _ => factory.create(device, shader, &descriptor),
+        }
+    }
+}
+
+const KEEP_DESTINATION: wgpu::BlendComponent = wgpu::BlendComponent {
+    src_factor: wgpu::BlendFactor::Zero,
+    dst_factor: wgpu::BlendFactor::One,
+    operation: wgpu::BlendOperation::Add,
+};
+
 #[derive(Debug)]
 pub struct Renderer {
-    shape_vertex_buffer: wgpu::Buffer,
-    shape_index_buffer: wgpu::Buffer,
-    // uniform_buffer: wgpu::Buffer,
-    // bind_group: wgpu::BindGroup,
-    shape_pipeline: wgpu::RenderPipeline, // TODO: This is for a (filled?) shape only! What if I want to draw a bounding box?
-    // TODO: What about wireframe / outlines?
     pub(crate) clear_color: wgpu::Color,
     // width: f32,
     // height: f32,
-    shape_num_indices: u32,
     /////////// Texture pipeline //////////////
     sprite_vertex_buffer: wgpu::Buffer,
     sprite_index_buffer: wgpu::Buffer,
@@ -238,15 +774,45 @@ pub struct Renderer {
     sprite_num_indices: u32,
     sprite_bind_group_layout: wgpu::BindGroupLayout,
 
-    pub view_projection_uniform_buffer: wgpu::Buffer,
-    pub uniforms_bind_group: wgpu::BindGroup,
+    uniforms_bind_group_layout: wgpu::BindGroupLayout,
+    paint_bind_group_layout: wgpu::BindGroupLayout,
     depth_texture_view: Option<wgpu::TextureView>,
-
-    geometry_render_pipeline: wgpu::RenderPipeline,
-    geometry_vbo: wgpu::Buffer,
-    geometry_ibo: wgpu::Buffer,
-    geometry_fill_range: Range<u32>,
-    geometry_stroke_range: Range<u32>,
+    /// The size of the surface, which `depth_texture_view` matches.
+    surface_size: (u32, u32),
+    /// Cameras and material instances push their uniforms here every frame.
+    frame_uniforms: FrameUniforms,
+    /// Covers the surface, for render passes begun with [`Renderer::begin`].
+    surface_camera: Camera,
+    /// The layers drawn in the current render pass, set by [`Renderer::begin_camera`].
+    camera_layers: Cell<LayerMask>,
+    /// The part of the world the current render pass sees, or `None` to draw everything.
+    camera_rect: Cell<Option<Box2D<f32>>>,
+    /// Where the current render pass's view projection was pushed, which identifies its camera
+    /// to batches culled for each camera.
+    camera_uniforms: Cell<Option<UniformOffset>>,
+    /// Clear the color and depth, or only the depth, of a camera's viewport.
+    clear_pipeline: wgpu::RenderPipeline,
+    clear_depth_pipeline: wgpu::RenderPipeline,
+
+    /// Every fill and stroke is drawn with this pipeline.
+    shape_pipeline: wgpu::RenderPipeline,
+    shape: VectorGraphic,
+    shape_instance_buffer: wgpu::Buffer,
+
+    quad_vertex_buffer: wgpu::Buffer,
+    quad_index_buffer: wgpu::Buffer,
+    quad_num_indices: u32,
+    particle_pipeline: wgpu::RenderPipeline,
+    sprite_instanced_pipeline: wgpu::RenderPipeline,
+
+    pipeline_factory: PipelineFactory,
+    pipeline_layouts: BuiltinLayouts,
+    shader_watcher: Option<ShaderWatcher>,
+    /// Resolves includes for the built in shaders and materials.
+    preprocessor: Preprocessor,
+    /// The files each built in pipeline's shader was preprocessed from, to know which to
+    /// rebuild when one changes.
+    builtin_files: HashMap<BuiltinShader, Vec<String>>,
 }
 
 impl Renderer {
@@ -258,11 +824,12 @@ impl Renderer {
         surface_format: wgpu::TextureFormat,
         clear_color: wgpu::Color,
         blend_state: wgpu::BlendState,
-    ) -> Self {
+    ) -> Result<Self> {
         let depth_stencil_state = Some(wgpu::DepthStencilState {
-            format: wgpu::TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Greater,
+            // Equal depths pass so that, within a layer, later draws are painted over earlier ones.
+            depth_compare: wgpu::CompareFunction::GreaterEqual,
             stencil: wgpu::StencilState {
                 front: wgpu::StencilFaceState::IGNORE,
                 back: wgpu::StencilFaceState::IGNORE,
@@ -272,98 +839,10 @@ impl Renderer {
             bias: wgpu::DepthBiasState::default(),
         });
 
-        ////////////////////////////// Shape pipeline /////////////////////////////////
-        // TODO: Can I use a single shader here? Should I?
-        let shape_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shape_shader.wgsl").into()),
-        });
-
-        // TODO: How do the buffers work when I want to update them each frame / don't know ahead of time all the things to draw? How does egui-wgpu do it?
-        // Vertex buffer
-        let shape_vertex_data_slice = bytemuck::cast_slice(&SHAPE_VERTICES);
-        let shape_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: shape_vertex_data_slice,
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        // Index buffer
-        let shape_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(SHAPE_INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let shape_num_indices = SHAPE_INDICES.len() as u32;
-
-        // Uniform buffer here.
-
-        // Bind group
-
-        // Shape Pipeline
-        let shape_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Shape Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-
-        let shape_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Shape Pipeline"),
-            layout: Some(&shape_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shape_shader,
-                entry_point: "vs_main",
-                // buffers: &[ShapeVertex::desc()],
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shape_shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    // blend: Some(wgpu::BlendState {
-                    //     color: wgpu::BlendComponent::REPLACE,
-                    //     alpha: wgpu::BlendComponent::REPLACE,
-                    // }),
-                    blend: Some(blend_state),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: depth_stencil_state.clone(),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
-
         ////////////////////////////// Sprite pipeline /////////////////////////////////
-        // TODO: Can I use a single shader here? Should I?
-        let sprite_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Sprite Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/sprite_shader.wgsl").into()),
-        });
-
         // TODO: How do the buffers work when I want to update them each frame / don't know ahead of time all the things to draw? How does egui-wgpu do it?
         // Vertex buffer
-        let sprite_vertex_data_slice = bytemuck::cast_slice(&SPRITE_VERTICES);
+        let sprite_vertex_data_slice = bytemuck::cast_slice(SPRITE_VERTICES);
         let sprite_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Sprite Vertex Buffer"),
             contents: sprite_vertex_data_slice,
@@ -378,259 +857,98 @@ impl Renderer {
         });
         let sprite_num_indices = SPRITE_INDICES.len() as u32;
 
-        // Uniform buffer
-        let view_projection_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("View Projection Uniform Buffer"),
-            size: std::mem::size_of::<ViewProjectionUniform>() as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
         // Bind groups
         let uniforms_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Uniforms Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: FRAME_UNIFORMS_LAYOUT_ENTRIES,
             });
 
-        let uniforms_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Uniforms Bind Group"),
-            layout: &uniforms_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: view_projection_uniform_buffer.as_entire_binding(),
-            }],
-        });
-
         let sprite_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
+                entries: SPRITE_LAYOUT_ENTRIES,
                 label: Some("texture_bind_group_layout"),
             });
 
-        // // This bind group can be swapped out on the fly with other compatible bind groups.
-        // let sprite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        //     layout: &sprite_bind_group_layout,
-        //     entries: &[
-        //         wgpu::BindGroupEntry {
-        //             binding: 0,
-        //             resource: wgpu::BindingResource::TextureView(&sprite_texture.view),
-        //         },
-        //         wgpu::BindGroupEntry {
-        //             binding: 1,
-        //             resource: wgpu::BindingResource::Sampler(&sprite_texture.sampler),
-        //         },
-        //     ],
-        //     label: Some("Sprite Bind Group"),
-        // });
-
-        // Sprite Pipeline
-        let sprite_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let paint_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: PAINT_LAYOUT_ENTRIES,
+                label: Some("paint_bind_group_layout"),
+            });
+
+        // Pipeline layouts
+        let pipeline_layouts = BuiltinLayouts {
+            sprite: device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Sprite Pipeline Layout"),
                 bind_group_layouts: &[&uniforms_bind_group_layout, &sprite_bind_group_layout],
                 push_constant_ranges: &[],
-            });
-
-        let sprite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sprite Pipeline"),
-            layout: Some(&sprite_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &sprite_shader,
-                entry_point: "vs_main",
-                // buffers: &[SpriteVertex::desc()],
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &sprite_shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    // blend: Some(wgpu::BlendState {
-                    //     color: wgpu::BlendComponent::REPLACE,
-                    //     alpha: wgpu::BlendComponent::REPLACE,
-                    // }),
-                    blend: Some(blend_state),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: depth_stencil_state.clone(),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
-
-        /////////////////////////////// Geometry pipeline ///////////////////////////////////
-        let tolerance = 0.02;
-
-        let mut geometry: VertexBuffers<GpuVertex, u16> = VertexBuffers::new();
+            shape: device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shape Pipeline Layout"),
+                bind_group_layouts: &[&uniforms_bind_group_layout, &paint_bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+            clear: device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Clear Pipeline Layout"),
+                bind_group_layouts: &[&uniforms_bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+        };
 
-        let mut fill_tess = FillTessellator::new();
-        let mut stroke_tess = StrokeTessellator::new();
+        let pipeline_factory = PipelineFactory {
+            color_format: surface_format,
+            blend_state,
+            depth_stencil_state,
+        };
+        let mut preprocessor = Preprocessor::new();
+        for (name, source) in BUILTIN_SHADER_SOURCES {
+            preprocessor.add_include(name, *source);
+        }
+        let mut builtin_files = HashMap::new();
+        let mut create_pipeline = |builtin: BuiltinShader| -> Result<wgpu::RenderPipeline> {
+            let (shader, files) = builtin.compile(&preprocessor)?;
+            builtin_files.insert(builtin, files);
+            pipeline_layouts.create_pipeline(device, &pipeline_factory, builtin, &shader)
+        };
+        let sprite_pipeline = create_pipeline(BuiltinShader::Sprite)?;
+        let sprite_instanced_pipeline = create_pipeline(BuiltinShader::SpriteInstanced)?;
+        let shape_pipeline = create_pipeline(BuiltinShader::Shape)?;
+        let particle_pipeline = create_pipeline(BuiltinShader::Particle)?;
+        let clear_pipeline = create_pipeline(BuiltinShader::Clear)?;
+        let clear_depth_pipeline = create_pipeline(BuiltinShader::ClearDepth)?;
 
+        ////////////////////////////// Shape pipeline /////////////////////////////////
         let rect = Box2D::new(point(0.0, 0.0), point(500.0, 500.0));
-        let mut builder = Path::builder();
-        builder.add_rectangle(&rect, Winding::Negative);
-        let path = builder.build();
-
-        fill_tess
-            .tessellate_path(
-                &path,
-                &FillOptions::tolerance(tolerance)
-                    .with_fill_rule(lyon::tessellation::FillRule::NonZero),
-                &mut BuffersBuilder::new(&mut geometry, WithId),
-            )
-            .unwrap();
-
-        let geometry_fill_range = 0..(geometry.indices.len() as u32);
-
-        stroke_tess
-            .tessellate_path(
-                &path,
-                &StrokeOptions::tolerance(tolerance),
-                &mut BuffersBuilder::new(&mut geometry, WithId),
-            )
-            .unwrap();
-
-        let geometry_stroke_range = geometry_fill_range.end..(geometry.indices.len() as u32);
-
-        dbg!(&geometry);
-
-        let geometry_vbo = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&geometry.vertices),
+        let mut shapes = ShapeBuilder::new();
+        shapes.fill_rect(rect, [1.0, 1.0, 1.0, 1.0])?;
+        shapes.stroke_rect(rect, [0.0, 0.0, 0.0, 1.0], &StrokeStyle::new(1.0))?;
+        let shape = shapes.build(device, &paint_bind_group_layout, "Shape");
+
+        // The built in shape is drawn once, untransformed.
+        let shape_instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shape Instance Buffer"),
+            contents: bytemuck::cast_slice(&[ShapeInstance::IDENTITY]),
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let geometry_ibo = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&geometry.indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let geometry_vs_module = &device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Geometry vs"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("./../shaders/geometry.wgsl").into()),
+        ////////////////////////////// Particle pipeline /////////////////////////////////
+        let quad_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Quad Vertex Buffer"),
+            contents: bytemuck::cast_slice(QUAD_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let geometry_fs_module = &device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Geometry fs"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("./../shaders/geometry.wgsl").into()),
+        let quad_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Quad Index Buffer"),
+            contents: bytemuck::cast_slice(QUAD_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
         });
-
-        let geometry_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                bind_group_layouts: &[&uniforms_bind_group_layout],
-                push_constant_ranges: &[],
-                label: None,
-            });
-
-        let geometry_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Geometry pipeline"),
-                layout: Some(&geometry_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &geometry_vs_module,
-                    entry_point: "vs_main",
-                    buffers: &[wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<GpuVertex>() as u64,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                format: wgpu::VertexFormat::Float32x3,
-                                shader_location: 0,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 12,
-                                format: wgpu::VertexFormat::Float32x4,
-                                shader_location: 1,
-                            },
-                        ],
-                    }],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &geometry_fs_module,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_format,
-                        blend: Some(blend_state),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    front_face: wgpu::FrontFace::Ccw,
-                    strip_index_format: None,
-                    cull_mode: Some(wgpu::Face::Back),
-                    conservative: false,
-                    unclipped_depth: false,
-                },
-                depth_stencil: depth_stencil_state.clone(),
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-            });
+        let quad_num_indices = QUAD_INDICES.len() as u32;
 
         let depth_texture_view = None;
 
-        Self {
-            shape_vertex_buffer,
-            shape_index_buffer,
-            // uniform_buffer,
-            shape_pipeline,
+        Ok(Self {
             clear_color,
-            shape_num_indices,
             sprite_vertex_buffer,
             sprite_index_buffer,
             // uniform_buffer,
@@ -638,15 +956,181 @@ impl Renderer {
             sprite_num_indices,
             sprite_bind_group_layout,
 
-            uniforms_bind_group,
-            view_projection_uniform_buffer,
             depth_texture_view,
+            surface_size: (0, 0),
+            frame_uniforms: FrameUniforms::new(device, &uniforms_bind_group_layout),
+            surface_camera: Camera::new(0.0, 0.0),
+            camera_layers: Cell::new(LayerMask::ALL),
+            camera_rect: Cell::new(None),
+            camera_uniforms: Cell::new(None),
+            clear_pipeline,
+            clear_depth_pipeline,
 
-            geometry_render_pipeline,
-            geometry_vbo,
-            geometry_ibo,
-            geometry_fill_range,
-            geometry_stroke_range,
+            shape_pipeline,
+            shape,
+            shape_instance_buffer,
+
+            quad_vertex_buffer,
+            quad_index_buffer,
+            quad_num_indices,
+            particle_pipeline,
+            sprite_instanced_pipeline,
+
+            pipeline_factory,
+            pipeline_layouts,
+            shader_watcher: None,
+            preprocessor,
+            builtin_files,
+            uniforms_bind_group_layout,
+            paint_bind_group_layout,
+        })
+    }
+
+    /// Watch `dir` for changes to the built in shaders, and rebuild their pipelines from the
+    /// files in it whenever [`Renderer::reload_changed_shaders`] finds they, or a file they
+    /// include, have changed. Materials created afterwards also include files from `dir`. Meant
+    /// for development, pointing at the `shaders` directory of the source tree.
+    pub fn watch_shaders(&mut self, dir: impl Into<std::path::PathBuf>) -> Result<()> {
+        let dir = dir.into();
+        self.shader_watcher = Some(ShaderWatcher::new(dir.clone())?);
+        self.preprocessor.set_include_dir(Some(dir));
+        Ok(())
+    }
+
+    /// Rebuild the pipelines of any watched shaders that have changed. A shader that fails to
+    /// compile is logged and its last good pipeline kept.
+    pub fn reload_changed_shaders(&mut self, device: &wgpu::Device) {
+        let changed = match &mut self.shader_watcher {
+            Some(watcher) => watcher.poll(),
+            None => return,
+        };
+        let changed = changed
+            .iter()
+            .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return;
+        }
+
+        for builtin in BuiltinShader::ALL {
+            let affected = self
+                .builtin_files
+                .get(&builtin)
+                .is_some_and(|files| files.iter().any(|file| changed.contains(file)));
+            if !affected {
+                continue;
+            }
+
+            let pipeline = builtin
+                .compile(&self.preprocessor)
+                .and_then(|(shader, files)| {
+                    let pipeline = self.pipeline_layouts.create_pipeline(
+                        device,
+                        &self.pipeline_factory,
+                        builtin,
+                        &shader,
+                    )?;
+                    Ok((pipeline, files))
+                });
+            match pipeline {
+                Result::Ok((pipeline, files)) => {
+                    *self.builtin_pipeline_mut(builtin) = pipeline;
+                    self.builtin_files.insert(builtin, files);
+                    log::info!("reloaded {} ({:?})", builtin.file_name(), builtin);
+                }
+                Err(error) => match error.downcast_ref::<ShaderError>() {
+                    Some(error) => log::error!("{}", error.diagnostic),
+                    None => log::error!("failed to reload {}: {:#}", builtin.file_name(), error),
+                },
+            }
+        }
+    }
+
+    /// Make `source` available to materials as `#include "name"`.
+    pub fn add_shader_include(&mut self, name: &str, source: impl Into<String>) {
+        self.preprocessor.add_include(name, source);
+    }
+
+    /// Compile `descriptor`'s fragment shader together with the vertex stage for its kind, and
+    /// create its pipeline and bind group layout.
+    pub fn create_material(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &MaterialDescriptor,
+    ) -> Result<Material> {
+        let layout = descriptor.layout()?;
+        let source =
+            self.preprocessor
+                .preprocess(&descriptor.label, &layout.source, &descriptor.defines)?;
+        let shader = Shader::from_preprocessed(&descriptor.label, &source)?;
+
+        let material_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(&descriptor.label),
+                entries: &layout.entries,
+            });
+        // The uniforms are pushed to the frame's uniforms, bound as group 2.
+        let mut bind_group_layouts = vec![
+            &self.uniforms_bind_group_layout,
+            &material_bind_group_layout,
+        ];
+        let mut bind_group_entries = vec![FRAME_UNIFORMS_LAYOUT_ENTRIES, &layout.entries];
+        if layout.has_uniforms() {
+            bind_group_layouts.push(&self.uniforms_bind_group_layout);
+            bind_group_entries.push(FRAME_UNIFORMS_LAYOUT_ENTRIES);
+        }
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&descriptor.label),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let buffers = match descriptor.kind {
+            MaterialKind::Sprite => [Vertex::desc(), SpriteInstance::desc()],
+            MaterialKind::Shape => [ShapeVertex::desc(), ShapeInstance::desc()],
+        };
+        let pipeline = self.pipeline_factory.create(
+            device,
+            &shader,
+            &PipelineDescriptor {
+                label: &descriptor.label,
+                layout: &pipeline_layout,
+                bind_group_layouts: &bind_group_entries,
+                buffers: &buffers,
+                blend: descriptor.blend,
+                cull_mode: None,
+            },
+        )?;
+
+        Ok(Material::new(
+            &descriptor.label,
+            descriptor.kind,
+            layout,
+            material_bind_group_layout,
+            pipeline,
+        ))
+    }
+
+    /// Create a post processing chain with offscreen targets the size of the surface. Render the
+    /// scene into [`PostProcessor::scene_target`] rather than the surface, then call
+    /// [`PostProcessor::apply`].
+    pub fn create_post_processor(&self, bananas: &Bananas) -> Result<PostProcessor> {
+        PostProcessor::new(
+            &bananas.device,
+            &self.preprocessor,
+            self.pipeline_factory.color_format,
+            bananas.config.width,
+            bananas.config.height,
+        )
+    }
+
+    fn builtin_pipeline_mut(&mut self, builtin: BuiltinShader) -> &mut wgpu::RenderPipeline {
+        match builtin {
+            BuiltinShader::Sprite => &mut self.sprite_pipeline,
+            BuiltinShader::SpriteInstanced => &mut self.sprite_instanced_pipeline,
+            BuiltinShader::Shape => &mut self.shape_pipeline,
+            BuiltinShader::Particle => &mut self.particle_pipeline,
+            BuiltinShader::Clear => &mut self.clear_pipeline,
+            BuiltinShader::ClearDepth => &mut self.clear_depth_pipeline,
         }
     }
 
@@ -671,44 +1155,184 @@ impl Renderer {
     // }
 
     pub fn resize(&mut self, bananas: &Bananas) {
-        let depth_texture = bananas.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Depth texture"),
-            size: wgpu::Extent3d {
-                width: bananas.config.width,
-                height: bananas.config.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        });
+        self.surface_size = (bananas.config.width, bananas.config.height);
+        self.surface_camera =
+            Camera::new(bananas.config.width as f32, bananas.config.height as f32);
+        self.depth_texture_view = Some(create_depth_view(
+            &bananas.device,
+            bananas.config.width,
+            bananas.config.height,
+            "Depth texture",
+        ));
+    }
+
+    /// Create an offscreen target the renderer can draw into, in the surface's format and with
+    /// its own depth texture.
+    pub fn create_render_target(
+        &self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: &str,
+    ) -> RenderTarget {
+        RenderTarget::new(
+            device,
+            self.pipeline_factory.color_format,
+            width,
+            height,
+            true,
+            label,
+        )
+    }
+
+    /// Start allocating this frame's uniforms, forgetting the last frame's. Cameras and material
+    /// instances are prepared with the result, then it's uploaded with
+    /// [`Renderer::upload_uniforms`] before any render pass begins.
+    pub fn begin_frame(&mut self) -> &mut FrameUniforms {
+        self.frame_uniforms.begin_frame();
+        self.surface_camera.prepare(&mut self.frame_uniforms);
+        &mut self.frame_uniforms
+    }
 
-        self.depth_texture_view =
-            Some(depth_texture.create_view(&wgpu::TextureViewDescriptor::default()));
+    /// Upload everything pushed to the frame's uniforms since [`Renderer::begin_frame`].
+    pub fn upload_uniforms(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.frame_uniforms
+            .upload(device, queue, &self.uniforms_bind_group_layout);
     }
 
+    /// Start a render pass that clears `target`, either a view of the surface texture or a
+    /// [`RenderTarget`], and draws every layer as seen by a [`Camera`] the size of the surface,
+    /// looking at the origin.
     pub fn begin<'pass>(
         &'pass self,
         encoder: &'pass mut wgpu::CommandEncoder,
-        render_target: &'pass wgpu::TextureView,
+        target: impl Into<RenderPassTarget<'pass>>,
+    ) -> RenderPass<'pass> {
+        let mut render_pass = self.begin_pass(
+            encoder,
+            target.into(),
+            wgpu::LoadOp::Clear(self.clear_color),
+            wgpu::LoadOp::Clear(0.0),
+        );
+        let (view_projection, _) = self.surface_camera.uniform_offsets();
+        let offset = self
+            .frame_uniforms
+            .dynamic_offset(view_projection, "the surface camera");
+        render_pass.set_bind_group(0, self.frame_uniforms.bind_group(), &[offset]);
+        self.camera_layers.set(LayerMask::ALL);
+        self.camera_rect.set(None);
+        self.camera_uniforms.set(Some(view_projection));
+        render_pass
+    }
+
+    /// Start a render pass that draws into `camera`'s viewport of `target`, seen through
+    /// `camera`, after clearing the viewport as the camera asks. Only things on one of the
+    /// camera's layers are drawn, and batches outside its [`Camera::visible_rect`] are skipped.
+    /// The camera must have been prepared with [`Camera::prepare`].
+    ///
+    /// Each camera drawing into the same frame gets its own render pass, begun in the order
+    /// they should be painted in.
+    pub fn begin_camera<'pass>(
+        &'pass self,
+        encoder: &'pass mut wgpu::CommandEncoder,
+        target: impl Into<RenderPassTarget<'pass>>,
+        camera: &'pass Camera,
+    ) -> RenderPass<'pass> {
+        let target = target.into();
+        let (width, height) = match target {
+            RenderPassTarget::Surface(_) => self.surface_size,
+            RenderPassTarget::Texture(target) => (target.width(), target.height()),
+        };
+        let viewport = camera.viewport.to_pixels(width, height);
+        // Clearing when the pass begins clears the whole target, so a smaller viewport is
+        // cleared by drawing over it instead.
+        let covers_target = viewport == [0, 0, width, height];
+        let (color_load, depth_load, clear_pipeline) = match camera.clear {
+            ClearMode::Color(color) if covers_target => {
+                (wgpu::LoadOp::Clear(color), wgpu::LoadOp::Clear(0.0), None)
+            }
+            ClearMode::Depth if covers_target => {
+                (wgpu::LoadOp::Load, wgpu::LoadOp::Clear(0.0), None)
+            }
+            ClearMode::Color(_) => (
+                wgpu::LoadOp::Load,
+                wgpu::LoadOp::Load,
+                Some(&self.clear_pipeline),
+            ),
+            ClearMode::Depth => (
+                wgpu::LoadOp::Load,
+                wgpu::LoadOp::Load,
+                Some(&self.clear_depth_pipeline),
+            ),
+            ClearMode::None => (wgpu::LoadOp::Load, wgpu::LoadOp::Load, None),
+        };
+
+        let mut render_pass = self.begin_pass(encoder, target, color_load, depth_load);
+        let [x, y, viewport_width, viewport_height] = viewport;
+        render_pass.set_viewport(
+            x as f32,
+            y as f32,
+            viewport_width as f32,
+            viewport_height as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_scissor_rect(x, y, viewport_width, viewport_height);
+        let (view_projection, clear_color) = camera.uniform_offsets();
+        if let Some(clear_pipeline) = clear_pipeline {
+            let offset = self.frame_uniforms.dynamic_offset(clear_color, "a camera");
+            render_pass.set_pipeline(clear_pipeline);
+            render_pass.set_bind_group(0, self.frame_uniforms.bind_group(), &[offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+        // Group 0 stays bound for every draw in the pass.
+        let offset = self
+            .frame_uniforms
+            .dynamic_offset(view_projection, "a camera");
+        render_pass.set_bind_group(0, self.frame_uniforms.bind_group(), &[offset]);
+        self.camera_layers.set(camera.layers);
+        self.camera_rect.set(Some(camera.visible_rect()));
+        self.camera_uniforms.set(Some(view_projection));
+        render_pass
+    }
+
+    fn begin_pass<'pass>(
+        &'pass self,
+        encoder: &'pass mut wgpu::CommandEncoder,
+        target: RenderPassTarget<'pass>,
+        color_load: wgpu::LoadOp<wgpu::Color>,
+        depth_load: wgpu::LoadOp<f32>,
     ) -> RenderPass<'pass> {
+        let (color_view, depth_view) = match target {
+            RenderPassTarget::Surface(view) => {
+                (view, self.depth_texture_view.as_ref().expect("TODO"))
+            }
+            RenderPassTarget::Texture(target) => (
+                &target.color().view,
+                // Every pipeline tests against depth.
+                target.depth_view().unwrap_or_else(|| {
+                    panic!(
+                        "render targets drawn by the renderer need a depth texture, \
+                         see Renderer::create_render_target"
+                    )
+                }),
+            ),
+        };
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &render_target,
+                view: color_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    load: color_load,
                     store: true,
                 },
             })],
             // depth_stencil_attachment: None,
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: self.depth_texture_view.as_ref().expect("TODO"),
+                view: depth_view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(0.0),
+                    load: depth_load,
                     store: true,
                 }),
                 stencil_ops: Some(wgpu::Operations {
@@ -719,45 +1343,252 @@ impl Renderer {
         })
     }
 
+    /// Whether the current render pass draws things on `layers`.
+    fn draws_layers(&self, layers: LayerMask) -> bool {
+        self.camera_layers.get().intersects(layers)
+    }
+
+    /// The instances of `batch`, whose instances each draw geometry within `local`, that the
+    /// current render pass draws, or `None` if the batch is empty, on none of the camera's
+    /// layers or out of view.
+    fn batch_instances<T: Instance>(
+        &self,
+        batch: &Batch<T>,
+        local: Box2D<f32>,
+    ) -> Option<Range<u32>> {
+        let bounds = batch.bounds(local)?;
+        let in_view = self
+            .camera_rect
+            .get()
+            .map_or(true, |rect| rect.intersects(&bounds));
+        if !self.draws_layers(batch.layers) || !in_view {
+            return None;
+        }
+        let instances = batch.instances_for(self.camera_uniforms.get());
+        (!instances.is_empty()).then_some(instances)
+    }
+
     pub fn render<'pass>(
         &'pass self,
         render_pass: &mut RenderPass<'pass>,
-        shape_bind_group: &'pass wgpu::BindGroup,
         sprite_bind_group: &'pass wgpu::BindGroup,
     ) {
         // We need to loop over all the things we want to render and do these steps for each of them.
-        // render_pass.set_pipeline(&self.shape_pipeline);
-
-        // // Draw a shape
-        // render_pass.set_pipeline(&self.sprite_pipeline);
-        // render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
-        // render_pass.set_bind_group(1, shape_bind_group, &[]);
-        // render_pass.set_vertex_buffer(0, self.shape_vertex_buffer.slice(..));
-        // render_pass.set_index_buffer(self.shape_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        // render_pass.draw_indexed(0..self.shape_num_indices, 0, 0..1);
-
-        // // Draw a sprite
-        // render_pass.set_pipeline(&self.sprite_pipeline);
-        // render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
-        // render_pass.set_bind_group(1, sprite_bind_group, &[]);
-        // render_pass.set_vertex_buffer(0, self.sprite_vertex_buffer.slice(..));
-        // render_pass.set_index_buffer(
-        //     self.sprite_index_buffer.slice(..),
-        //     wgpu::IndexFormat::Uint16,
-        // );
-        // render_pass.draw_indexed(0..self.sprite_num_indices, 0, 0..1);
-
-        // Draw the tessellated geometry
-        render_pass.set_pipeline(&self.geometry_render_pipeline);
-        render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
-        render_pass.set_index_buffer(self.geometry_ibo.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.set_vertex_buffer(0, self.geometry_vbo.slice(..));
-        render_pass.draw_indexed(self.geometry_fill_range.clone(), 0, 0..1);
-        render_pass.draw_indexed(self.geometry_stroke_range.clone(), 0, 0..1);
+        if !self.draws_layers(LayerMask::DEFAULT) {
+            return;
+        }
+
+        // Draw a sprite
+        render_pass.set_pipeline(&self.sprite_pipeline);
+        render_pass.set_bind_group(1, sprite_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.sprite_vertex_buffer.slice(..));
+        render_pass.set_index_buffer(
+            self.sprite_index_buffer.slice(..),
+            wgpu::IndexFormat::Uint16,
+        );
+        render_pass.draw_indexed(0..self.sprite_num_indices, 0, 0..1);
+
+        // Draw the built in shape
+        self.draw_shape(
+            render_pass,
+            &self.shape,
+            self.shape_instance_buffer.slice(..),
+            0..1,
+        );
 
         // Here we also need to set the uniform bind group and maybe scissor rect for the rpass?
     }
 
+    /// Draw every live particle in `particles` with a single instanced draw call. The particles
+    /// must have been uploaded with [`ParticleSystem::prepare`] first.
+    pub fn draw_particles<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        particles: &'pass ParticleSystem,
+        texture_bind_group: &'pass wgpu::BindGroup,
+    ) {
+        let instance_count = particles.instance_count();
+        if instance_count == 0 || !self.draws_layers(particles.layers()) {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.particle_pipeline);
+        render_pass.set_bind_group(1, texture_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.quad_vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, particles.instance_buffer.slice());
+        render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.quad_num_indices, 0, 0..instance_count);
+    }
+
+    /// Draw every sprite in `batch` with a single instanced draw call. The batch must have been
+    /// uploaded with [`SpriteBatch::prepare`] first.
+    pub fn draw_sprites_instanced<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        batch: &'pass SpriteBatch,
+        texture_bind_group: &'pass wgpu::BindGroup,
+    ) {
+        let Some(instances) = self.batch_instances(batch, QUAD_BOUNDS) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&self.sprite_instanced_pipeline);
+        render_pass.set_bind_group(1, texture_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.quad_vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, batch.instance_buffer.slice());
+        render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.quad_num_indices, 0, instances);
+    }
+
+    /// Draw every sprite in `batch` with a sprite material instead of a texture. Both the batch
+    /// and `instance` must have been prepared first.
+    pub fn draw_sprites_with_material<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        batch: &'pass SpriteBatch,
+        material: &'pass Material,
+        instance: &'pass MaterialInstance,
+    ) {
+        assert_eq!(
+            material.kind(),
+            MaterialKind::Sprite,
+            "{} isn't a sprite material",
+            material.label()
+        );
+        let Some(instances) = self.batch_instances(batch, QUAD_BOUNDS) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&material.pipeline);
+        self.bind_material(render_pass, material, instance);
+        render_pass.set_vertex_buffer(0, self.quad_vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, batch.instance_buffer.slice());
+        render_pass.set_index_buffer(self.quad_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.quad_num_indices, 0, instances);
+    }
+
+    /// Draw `graphic` once for every instance in `instances` with a shape material, which
+    /// replaces the graphic's paints. Both `instances` and `instance` must have been prepared
+    /// first.
+    pub fn draw_vector_graphic_with_material<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        graphic: &'pass VectorGraphic,
+        instances: &'pass ShapeBatch,
+        material: &'pass Material,
+        instance: &'pass MaterialInstance,
+    ) {
+        assert_eq!(
+            material.kind(),
+            MaterialKind::Shape,
+            "{} isn't a shape material",
+            material.label()
+        );
+        let indices = match (graphic.ranges.first(), graphic.ranges.last()) {
+            (Some(first), Some(last)) => first.indices.start..last.indices.end,
+            _ => return,
+        };
+        let Some(instance_range) = self.batch_instances(instances, graphic.bounds()) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&material.pipeline);
+        self.bind_material(render_pass, material, instance);
+        render_pass.set_vertex_buffer(0, graphic.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instances.instance_buffer.slice());
+        render_pass.set_index_buffer(graphic.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(indices, 0, instance_range);
+    }
+
+    /// Draw `graphic` once for every instance in `instances`, with a single draw call. The
+    /// instances must have been uploaded with [`ShapeBatch::prepare`] first.
+    pub fn draw_vector_graphic<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        graphic: &'pass VectorGraphic,
+        instances: &'pass ShapeBatch,
+    ) {
+        let Some(instance_range) = self.batch_instances(instances, graphic.bounds()) else {
+            return;
+        };
+
+        self.draw_shape(
+            render_pass,
+            graphic,
+            instances.instance_buffer.slice(),
+            instance_range,
+        );
+    }
+
+    /// Draw every visible node of `graph`, batch by batch. The graph must have been prepared
+    /// with [`SceneGraph::prepare`] first.
+    pub fn draw_scene_graph<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        graph: &'pass SceneGraph,
+    ) {
+        for batch in graph.batches() {
+            match batch {
+                SceneBatch::Sprites { bind_group, batch } => {
+                    self.draw_sprites_instanced(render_pass, batch, bind_group)
+                }
+                SceneBatch::Shapes { graphic, batch } => {
+                    self.draw_vector_graphic(render_pass, graphic, batch)
+                }
+            }
+        }
+    }
+
+    /// Draw each painted range of `graphic` with its paint bound to group 1.
+    fn draw_shape<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        graphic: &'pass VectorGraphic,
+        instances: wgpu::BufferSlice<'pass>,
+        instance_range: Range<u32>,
+    ) {
+        if graphic.ranges.is_empty() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.shape_pipeline);
+        render_pass.set_vertex_buffer(0, graphic.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instances);
+        render_pass.set_index_buffer(graphic.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for range in &graphic.ranges {
+            render_pass.set_bind_group(1, &graphic.paint_bind_groups[range.paint], &[]);
+            render_pass.draw_indexed(range.indices.clone(), 0, instance_range.clone());
+        }
+    }
+
+    /// Bind `instance`'s textures as group 1 and its uniforms as group 2.
+    fn bind_material<'pass>(
+        &'pass self,
+        render_pass: &mut RenderPass<'pass>,
+        material: &Material,
+        instance: &'pass MaterialInstance,
+    ) {
+        let (bind_group, uniforms) = instance.prepared().unwrap_or_else(|| {
+            panic!(
+                "an instance of {} was drawn before being prepared",
+                material.label()
+            )
+        });
+        render_pass.set_bind_group(1, bind_group, &[]);
+        if let Some(uniforms) = uniforms {
+            let offset = self
+                .frame_uniforms
+                .dynamic_offset(uniforms, material.label());
+            render_pass.set_bind_group(2, self.frame_uniforms.bind_group(), &[offset]);
+        }
+    }
+
+    /// The layout of the paint bind groups shapes are drawn with, to build a [`VectorGraphic`]
+    /// with.
+    pub fn paint_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.paint_bind_group_layout
+    }
+
     pub fn create_sprite_bind_group(
         &self,
         texture: &Texture,
@@ -809,7 +1640,8 @@ impl Bananas {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    // Optional, for samplers that clamp to a border color.
+                    features: adapter.features() & wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
                     limits: wgpu::Limits::default(),
                 },
                 None, // Trace path
@@ -845,87 +1677,9 @@ impl Bananas {
     }
 }
 
-pub struct WithId;
-
-// var transformed_pos = world_pos * vec3<f32>(globals.zoom / (0.5 * globals.resolution.x), globals.zoom / (0.5 * globals.resolution.y), 1.0);
-// TODO: Pass in color and ZIndex
-impl FillVertexConstructor<GpuVertex> for WithId {
-    fn new_vertex(&mut self, vertex: lyon::tessellation::FillVertex) -> GpuVertex {
-        let p = vertex.position().to_array();
-        let z_index = 0.0; // 1.0;
-        GpuVertex {
-            position: [p[0], p[1], z_index],
-            color: [1.0, 1.0, 1.0, 1.0],
-        }
-    }
-}
-
-// TODO: We want the color, ZIndex and the width passed in.
-impl StrokeVertexConstructor<GpuVertex> for WithId {
-    fn new_vertex(&mut self, vertex: lyon::tessellation::StrokeVertex) -> GpuVertex {
-        let stroke_width = 1.0;
-        let p = (vertex.position() + vertex.normal() * stroke_width).to_array();
-        let z_index = 0.0; // 2.0;
-        GpuVertex {
-            position: [p[0], p[1], z_index],
-            color: [0.0, 0.0, 0.0, 1.0],
-        }
-    }
-}
-
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ViewProjectionUniform {
-    pub(crate) view: [[f32; 4]; 4],
-    pub(crate) projection: [[f32; 4]; 4],
-}
-
-#[allow(dead_code)]
-pub struct Camera {
-    width: f32,
-    height: f32,
-    view: Mat4,
-    projection: Mat4,
-}
-
-impl Camera {
-    pub fn new(width: f32, height: f32) -> Self {
-        let projection = glam::Mat4::orthographic_lh(0.0, width, 0.0, height, -1.0, 1.0);
-
-        Self {
-            width,
-            height,
-            view: Mat4::IDENTITY,
-            projection,
-        }
-    }
-
-    pub fn resize(&mut self, width: f32, height: f32) {
-        let projection = glam::Mat4::orthographic_lh(0.0, width, 0.0, height, -1.0, 1.0);
-
-        self.width = width;
-        self.height = height;
-        self.projection = projection;
-    }
-
-    pub fn get_view(&self) -> Mat4 {
-        // Just use some jankey values for look at for now.
-        let view = glam::Mat4::look_at_lh(
-            glam::Vec3::new(-200.0, -200.0, -1.0),
-            glam::Vec3::new(-200.0, -200.0, 0.0),
-            glam::Vec3::Y,
-        );
-
-        // let view = glam::Mat4::look_at_lh(
-        //     glam::Vec3::new(0.0, 0.0, -1.0),
-        //     glam::Vec3::new(0.0, 0.0, 0.0),
-        //     glam::Vec3::Y,
-        // );
-
-        view
-    }
-
-    pub fn get_projection(&self) -> Mat4 {
-        self.projection
-    }
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
 }
@@ -0,0 +1,623 @@
+//! A scene graph: a hierarchy of nodes, each placed relative to its parent, that draw sprites
+//! and vector graphics. [`SceneGraph::prepare`] works out where every node is in the world and
+//! batches what the visible ones draw, for [`crate::renderer::Renderer::draw_scene_graph`].
+
+use std::{collections::HashMap, sync::Arc};
+
+use glam::{Affine2, Vec2};
+
+use crate::{
+    camera::LayerMask,
+    renderer::{ShapeBatch, ShapeInstance, SpriteBatch, SpriteInstance},
+    transform::Transform2D,
+    vector::VectorGraphic,
+};
+
+/// Identifies a node in a [`SceneGraph`]. Ids of removed nodes aren't reused, so they never refer
+/// to a different node later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+/// What a node draws, at its world transform.
+#[derive(Debug, Clone, Default)]
+pub enum Drawable {
+    /// Only places its children.
+    #[default]
+    None,
+    /// A `size` quad centred on the node's origin, showing `uv_rect` of the texture in
+    /// `bind_group`.
+    Sprite {
+        bind_group: Arc<wgpu::BindGroup>,
+        size: Vec2,
+        uv_rect: [f32; 4],
+    },
+    /// A vector graphic, in its own units.
+    Shape { graphic: Arc<VectorGraphic> },
+}
+
+/// A node's own properties.
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// From the node's space to its parent's. The node rotates and scales around its origin,
+    /// which lands on its translation in the parent's space.
+    pub transform: Transform2D,
+    /// Hiding a node hides its children too.
+    pub visible: bool,
+    /// Which cameras draw the node. Unlike visibility, children don't inherit it.
+    pub layers: LayerMask,
+    pub tint: [f32; 4],
+    pub drawable: Drawable,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            transform: Transform2D::IDENTITY,
+            visible: true,
+            layers: LayerMask::DEFAULT,
+            tint: [1.0, 1.0, 1.0, 1.0],
+            drawable: Drawable::None,
+        }
+    }
+}
+
+impl Node {
+    /// A node that draws nothing, to group others under.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A node drawing the whole texture in `bind_group` at `size`.
+    pub fn sprite(bind_group: Arc<wgpu::BindGroup>, size: Vec2) -> Self {
+        Self {
+            drawable: Drawable::Sprite {
+                bind_group,
+                size,
+                uv_rect: [0.0, 0.0, 1.0, 1.0],
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn shape(graphic: Arc<VectorGraphic>) -> Self {
+        Self {
+            drawable: Drawable::Shape { graphic },
+            ..Self::default()
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform2D) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_translation(mut self, translation: Vec2) -> Self {
+        self.transform.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.transform.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.transform.scale = scale;
+        self
+    }
+
+    pub fn with_origin(mut self, origin: Vec2) -> Self {
+        self.transform.origin = origin;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: LayerMask) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    node: Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// The transform from the node's space to the world's, as of the last update.
+    world: Affine2,
+    /// Whether the node has changed since `world` was computed.
+    dirty: bool,
+}
+
+#[derive(Debug)]
+struct Slot {
+    generation: u32,
+    entry: Option<Entry>,
+}
+
+/// The sprites or shapes of one texture or graphic, on the same layers, drawn together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BatchKey {
+    Sprites(*const wgpu::BindGroup, LayerMask),
+    Shapes(*const VectorGraphic, LayerMask),
+}
+
+/// Everything in a scene graph drawn with one draw call.
+pub enum SceneBatch {
+    Sprites {
+        bind_group: Arc<wgpu::BindGroup>,
+        batch: SpriteBatch,
+    },
+    Shapes {
+        graphic: Arc<VectorGraphic>,
+        batch: ShapeBatch,
+    },
+}
+
+impl SceneBatch {
+    fn clear(&mut self) {
+        match self {
+            SceneBatch::Sprites { batch, .. } => batch.clear(),
+            SceneBatch::Shapes { batch, .. } => batch.clear(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            SceneBatch::Sprites { batch, .. } => batch.is_empty(),
+            SceneBatch::Shapes { batch, .. } => batch.is_empty(),
+        }
+    }
+}
+
+/// A forest of [`Node`]s. World transforms are cached, and only recomputed for nodes that have
+/// changed, or whose ancestors have, since the last [`Self::update_transforms`].
+///
+/// Nodes are drawn parents first, then children in the order they were added. Nodes drawing the
+/// same texture or graphic are batched together, though, so a batch is drawn where its first
+/// node would be, and may cover nodes between its first and last.
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+    batches: HashMap<BatchKey, SceneBatch>,
+    /// The keys of this frame's batches, in the order they're drawn.
+    order: Vec<BatchKey>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `node` at the top of the hierarchy, placed in world space.
+    pub fn add(&mut self, node: Node) -> NodeId {
+        let id = self.insert(node, None);
+        self.roots.push(id);
+        id
+    }
+
+    /// Add `node` as the last child of `parent`, placed in its space.
+    ///
+    /// Panics if `parent` has been removed.
+    pub fn add_child(&mut self, parent: NodeId, node: Node) -> NodeId {
+        assert!(self.contains(parent), "{:?} isn't in the scene", parent);
+        let id = self.insert(node, Some(parent));
+        self.entry_mut(parent).children.push(id);
+        id
+    }
+
+    fn insert(&mut self, node: Node, parent: Option<NodeId>) -> NodeId {
+        let entry = Entry {
+            node,
+            parent,
+            children: Vec::new(),
+            world: Affine2::IDENTITY,
+            dirty: true,
+        };
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.entry = Some(entry);
+            NodeId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            self.slots.push(Slot {
+                generation: 0,
+                entry: Some(entry),
+            });
+            NodeId {
+                index: self.slots.len() as u32 - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    /// Remove `id` and all its descendants, returning it, or `None` if it was already removed.
+    pub fn remove(&mut self, id: NodeId) -> Option<Node> {
+        let parent = self.entry(id)?.parent;
+        self.detach(id, parent);
+
+        let mut removed = None;
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let entry = slot
+                .entry
+                .take()
+                .expect("a removed node was still in the scene");
+            // Wrapping would only reuse an id after four billion removals from one slot.
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(id.index);
+            stack.extend(entry.children);
+            removed.get_or_insert(entry.node);
+        }
+        removed
+    }
+
+    /// Move `id`, and its descendants, under `parent`, or to the top of the hierarchy for `None`.
+    /// Its transform is kept relative to its new parent, so it may move in the world.
+    ///
+    /// Panics if either node has been removed, or if `parent` is `id` or one of its descendants.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let old_parent = self
+            .entry(id)
+            .unwrap_or_else(|| panic!("{:?} isn't in the scene", id))
+            .parent;
+        if let Some(parent) = parent {
+            assert!(self.contains(parent), "{:?} isn't in the scene", parent);
+            assert!(
+                !self.ancestors(parent).any(|ancestor| ancestor == id) && parent != id,
+                "a node can't be parented to itself or its descendants"
+            );
+        }
+
+        self.detach(id, old_parent);
+        match parent {
+            Some(parent) => self.entry_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+        let entry = self.entry_mut(id);
+        entry.parent = parent;
+        entry.dirty = true;
+    }
+
+    /// Remove `id` from its parent's children, or the roots.
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let siblings = match parent {
+            Some(parent) => &mut self.entry_mut(parent).children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.entry(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.entry(id).map(|entry| &entry.node)
+    }
+
+    /// The node `id`, to be changed. Its world transform, and its descendants', are recomputed at
+    /// the next update.
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        let entry = self.slots.get_mut(id.index as usize)?;
+        if entry.generation != id.generation {
+            return None;
+        }
+        let entry = entry.entry.as_mut()?;
+        entry.dirty = true;
+        Some(&mut entry.node)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.entry(id)?.parent
+    }
+
+    /// The children of `id`, in drawing order.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.entry(id).map_or(&[], |entry| &entry.children)
+    }
+
+    /// The nodes at the top of the hierarchy, in drawing order.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// The parent of `id`, its parent, and so on.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |&id| self.parent(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The transform from `id`'s space to the world's, as of the last
+    /// [`Self::update_transforms`].
+    pub fn world_transform(&self, id: NodeId) -> Option<Transform2D> {
+        self.entry(id)
+            .map(|entry| Transform2D::from_affine(entry.world))
+    }
+
+    /// Whether `id` and all its ancestors are visible.
+    pub fn is_visible(&self, id: NodeId) -> bool {
+        self.get(id).is_some_and(|node| node.visible)
+            && self
+                .ancestors(id)
+                .all(|ancestor| self.get(ancestor).is_some_and(|node| node.visible))
+    }
+
+    fn entry(&self, id: NodeId) -> Option<&Entry> {
+        let slot = self.slots.get(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.entry.as_ref()
+    }
+
+    fn entry_mut(&mut self, id: NodeId) -> &mut Entry {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.entry.as_mut())
+            .unwrap_or_else(|| panic!("{:?} isn't in the scene", id))
+    }
+
+    /// Recompute the world transforms of nodes that have changed, and their descendants.
+    pub fn update_transforms(&mut self) {
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .map(|&id| (id, Affine2::IDENTITY, false))
+            .collect();
+        while let Some((id, parent_world, parent_moved)) = stack.pop() {
+            let entry = self.entry_mut(id);
+            let moved = entry.dirty || parent_moved;
+            if moved {
+                entry.world = parent_world * entry.node.transform.to_affine();
+                entry.dirty = false;
+            }
+            let world = entry.world;
+            stack.extend(entry.children.iter().map(|&child| (child, world, moved)));
+        }
+    }
+
+    /// Update the world transforms, then batch and upload what every visible node draws. Must be
+    /// called after changing the scene, before the render pass that draws it begins.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.update_transforms();
+
+        for batch in self.batches.values_mut() {
+            batch.clear();
+        }
+        self.order.clear();
+
+        // Visit parents before children, and children in order.
+        let mut stack: Vec<_> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let entry = self.slots[id.index as usize]
+                .entry
+                .as_ref()
+                .expect("a removed node was still in the scene");
+            let node = &entry.node;
+            if !node.visible {
+                continue;
+            }
+            stack.extend(entry.children.iter().rev());
+
+            match &node.drawable {
+                Drawable::None => {}
+                Drawable::Sprite {
+                    bind_group,
+                    size,
+                    uv_rect,
+                } => {
+                    let key = BatchKey::Sprites(Arc::as_ptr(bind_group), node.layers);
+                    let batch = self
+                        .batches
+                        .entry(key)
+                        .or_insert_with(|| SceneBatch::Sprites {
+                            bind_group: bind_group.clone(),
+                            batch: SpriteBatch::new(device).with_layers(node.layers),
+                        });
+                    if batch.is_empty() {
+                        self.order.push(key);
+                    }
+                    if let SceneBatch::Sprites { batch, .. } = batch {
+                        batch.push(
+                            SpriteInstance::new(entry.world * Affine2::from_scale(*size))
+                                .with_uv_rect(*uv_rect)
+                                .with_tint(node.tint),
+                        );
+                    }
+                }
+                Drawable::Shape { graphic } => {
+                    let key = BatchKey::Shapes(Arc::as_ptr(graphic), node.layers);
+                    let batch = self
+                        .batches
+                        .entry(key)
+                        .or_insert_with(|| SceneBatch::Shapes {
+                            graphic: graphic.clone(),
+                            batch: ShapeBatch::new(device).with_layers(node.layers),
+                        });
+                    if batch.is_empty() {
+                        self.order.push(key);
+                    }
+                    if let SceneBatch::Shapes { batch, .. } = batch {
+                        batch.push(ShapeInstance::new(entry.world).with_tint(node.tint));
+                    }
+                }
+            }
+        }
+
+        // Batches nothing drew into this frame are dropped, and with them their textures and
+        // graphics, which the scene may have been the last user of.
+        self.batches.retain(|_, batch| !batch.is_empty());
+        for batch in self.batches.values_mut() {
+            match batch {
+                SceneBatch::Sprites { batch, .. } => batch.prepare(device, queue),
+                SceneBatch::Shapes { batch, .. } => batch.prepare(device, queue),
+            }
+        }
+    }
+
+    /// The batches of the last [`Self::prepare`], in the order they're drawn.
+    pub fn batches(&self) -> impl Iterator<Item = &SceneBatch> {
+        self.order.iter().map(|key| &self.batches[key])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translated(x: f32, y: f32) -> Node {
+        Node::new().with_translation(Vec2::new(x, y))
+    }
+
+    fn world_point(graph: &SceneGraph, id: NodeId) -> Vec2 {
+        graph
+            .world_transform(id)
+            .unwrap()
+            .transform_point(Vec2::ZERO)
+    }
+
+    #[test]
+    fn hierarchy() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(Node::new());
+        let a = graph.add_child(root, Node::new());
+        let b = graph.add_child(root, Node::new());
+        let c = graph.add_child(a, Node::new());
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.roots(), [root]);
+        assert_eq!(graph.children(root), [a, b]);
+        assert_eq!(graph.parent(c), Some(a));
+        assert_eq!(graph.ancestors(c).collect::<Vec<_>>(), [a, root]);
+    }
+
+    #[test]
+    fn remove_takes_the_subtree() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(Node::new());
+        let a = graph.add_child(root, Node::new().with_tint([1.0, 0.0, 0.0, 1.0]));
+        let b = graph.add_child(root, Node::new());
+        let c = graph.add_child(a, Node::new());
+
+        let removed = graph.remove(a).unwrap();
+        assert_eq!(removed.tint, [1.0, 0.0, 0.0, 1.0]);
+        assert!(!graph.contains(a) && !graph.contains(c));
+        assert_eq!(graph.children(root), [b]);
+        assert_eq!(graph.len(), 2);
+        assert!(graph.remove(a).is_none());
+
+        // Freed slots are reused, without the old ids reaching the new nodes.
+        let d = graph.add(Node::new());
+        let e = graph.add(Node::new());
+        assert!(graph.contains(d) && graph.contains(e));
+        assert!(!graph.contains(a) && !graph.contains(c));
+        assert!(graph.get(a).is_none() && graph.get_mut(c).is_none());
+        assert_eq!(graph.roots(), [root, d, e]);
+    }
+
+    #[test]
+    fn set_parent_moves_between_lists() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(Node::new());
+        let b = graph.add(Node::new());
+        let c = graph.add_child(a, Node::new());
+
+        graph.set_parent(c, Some(b));
+        assert_eq!(graph.children(a), []);
+        assert_eq!(graph.children(b), [c]);
+        assert_eq!(graph.parent(c), Some(b));
+
+        graph.set_parent(b, None);
+        assert_eq!(graph.roots(), [a, b]);
+
+        graph.set_parent(c, None);
+        assert_eq!(graph.roots(), [a, b, c]);
+        assert_eq!(graph.parent(c), None);
+        assert_eq!(graph.children(b), []);
+    }
+
+    #[test]
+    #[should_panic(expected = "parented to itself or its descendants")]
+    fn set_parent_rejects_cycles() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(Node::new());
+        let b = graph.add_child(a, Node::new());
+        let c = graph.add_child(b, Node::new());
+        graph.set_parent(a, Some(c));
+    }
+
+    #[test]
+    #[should_panic(expected = "parented to itself or its descendants")]
+    fn set_parent_rejects_itself() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(Node::new());
+        graph.set_parent(a, Some(a));
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't in the scene")]
+    fn add_child_rejects_removed_parents() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(Node::new());
+        graph.remove(a);
+        graph.add_child(a, Node::new());
+    }
+
+    #[test]
+    fn world_transforms_follow_parents() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(translated(10.0, 0.0).with_scale(Vec2::splat(2.0)));
+        let child = graph.add_child(root, translated(5.0, 1.0));
+        let grandchild = graph.add_child(child, translated(0.0, 1.0));
+        graph.update_transforms();
+        assert_eq!(world_point(&graph, child), Vec2::new(20.0, 2.0));
+        assert_eq!(world_point(&graph, grandchild), Vec2::new(20.0, 4.0));
+
+        // Only the root changed, but its descendants move with it.
+        graph.get_mut(root).unwrap().transform.translation = Vec2::ZERO;
+        graph.update_transforms();
+        assert_eq!(world_point(&graph, grandchild), Vec2::new(10.0, 4.0));
+
+        // Reparenting keeps the local transform.
+        graph.set_parent(grandchild, None);
+        graph.update_transforms();
+        assert_eq!(world_point(&graph, grandchild), Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn visibility_is_inherited() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(Node::new());
+        let child = graph.add_child(root, Node::new());
+        assert!(graph.is_visible(child));
+        graph.get_mut(root).unwrap().visible = false;
+        assert!(!graph.is_visible(child));
+        graph.set_parent(child, None);
+        assert!(graph.is_visible(child));
+    }
+}
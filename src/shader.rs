@@ -0,0 +1,836 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    num::NonZeroU64,
+    path::{Path as FilePath, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::preprocess::PreprocessedSource;
+use anyhow::*;
+
+use naga::{
+    valid::{Capabilities, ModuleInfo, ValidationFlags, Validator},
+    AddressSpace, Binding, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage,
+    StorageAccess, TypeInner, VectorSize,
+};
+
+/// A WGSL shader that failed to parse or validate, with the position of the first problem.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub label: String,
+    /// 1-based line and column of the first problem, if naga reported one.
+    pub location: Option<(u32, u32)>,
+    pub message: String,
+    /// The error formatted with the offending source lines, for logging.
+    pub diagnostic: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => {
+                write!(f, "{}:{}:{}: {}", self.label, line, column, self.message)
+            }
+            None => write!(f, "{}: {}", self.label, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// A binding that a shader declares, and the stages that use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBinding {
+    pub name: Option<String>,
+    pub group: u32,
+    pub binding: u32,
+    pub ty: wgpu::BindingType,
+    pub visibility: wgpu::ShaderStages,
+}
+
+impl ReflectedBinding {
+    pub fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: self.binding,
+            visibility: self.visibility,
+            ty: self.ty,
+            count: None,
+        }
+    }
+}
+
+/// An input of a vertex entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflectedVertexInput {
+    pub location: u32,
+    pub format: wgpu::VertexFormat,
+}
+
+/// A WGSL shader that has been parsed and validated with naga, so that it can be reflected on
+/// and its pipelines checked before wgpu ever sees it.
+#[derive(Debug)]
+pub struct Shader {
+    label: String,
+    source: String,
+    module: Module,
+    info: ModuleInfo,
+}
+
+impl Shader {
+    pub fn from_wgsl(label: &str, source: impl Into<String>) -> Result<Self, ShaderError> {
+        let source = source.into();
+        let module = naga::front::wgsl::parse_str(&source).map_err(|error| ShaderError {
+            label: label.to_string(),
+            location: error
+                .location(&source)
+                .map(|location| (location.line_number, location.line_position)),
+            message: error.to_string(),
+            // naga names the source "wgsl" in the location line under the message.
+            diagnostic: error
+                .emit_to_string(&source)
+                .replace("─ wgsl:", &format!("─ {}:", label)),
+        })?;
+
+        let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .map_err(|error| {
+                let location = error
+                    .location(&source)
+                    .map(|location| (location.line_number, location.line_position));
+                let mut diagnostic = format!("{}: {}", label, error.as_inner());
+                for (span, context) in error.spans() {
+                    let location = span.location(&source);
+                    diagnostic += &format!(
+                        "\n  {}:{}:{}: {}",
+                        label, location.line_number, location.line_position, context
+                    );
+                }
+                ShaderError {
+                    label: label.to_string(),
+                    location,
+                    message: error.as_inner().to_string(),
+                    diagnostic,
+                }
+            })?;
+
+        Result::Ok(Self {
+            label: label.to_string(),
+            source,
+            module,
+            info,
+        })
+    }
+
+    /// Like [`Self::from_wgsl`], but errors are located in the file they came from rather than
+    /// in the preprocessed source.
+    pub fn from_preprocessed(
+        label: &str,
+        source: &PreprocessedSource,
+    ) -> Result<Self, ShaderError> {
+        Self::from_wgsl(label, source.source()).map_err(|mut error| {
+            if let Some((line, column)) = error.location {
+                if let Some((file, file_line)) = source.origin(line) {
+                    error.diagnostic += &format!(
+                        "\n  line {} of the preprocessed {} is {}:{}",
+                        line, label, file, file_line
+                    );
+                    error.label = file.to_string();
+                    error.location = Some((file_line, column));
+                }
+            }
+            error
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<FilePath>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read shader {}", path.display()))?;
+        Ok(Self::from_wgsl(&path.to_string_lossy(), source)?)
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn create_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.label),
+            source: wgpu::ShaderSource::Wgsl(self.source.as_str().into()),
+        })
+    }
+
+    /// Every resource binding used by at least one entry point, ordered by group and binding.
+    pub fn bindings(&self) -> Result<Vec<ReflectedBinding>> {
+        let mut layouter = naga::proc::Layouter::default();
+        layouter
+            .update(&self.module.types, &self.module.constants)
+            .map_err(|error| anyhow!("{}: {}", self.label, error))?;
+
+        let mut bindings = BTreeMap::new();
+        for (handle, global) in self.module.global_variables.iter() {
+            let resource = match &global.binding {
+                Some(resource) => resource,
+                None => continue,
+            };
+
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (index, entry_point) in self.module.entry_points.iter().enumerate() {
+                if !self.info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= match entry_point.stage {
+                        ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                    };
+                }
+            }
+            if visibility.is_empty() {
+                continue;
+            }
+
+            let ty = match global.space {
+                AddressSpace::Uniform => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(layouter[global.ty].size as u64),
+                },
+                AddressSpace::Storage { access } => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage {
+                        read_only: !access.contains(StorageAccess::STORE),
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                AddressSpace::Handle => self.handle_binding_type(global.ty)?,
+                space => bail!(
+                    "{}: binding {}.{} is in unsupported address space {:?}",
+                    self.label,
+                    resource.group,
+                    resource.binding,
+                    space
+                ),
+            };
+
+            bindings.insert(
+                (resource.group, resource.binding),
+                ReflectedBinding {
+                    name: global.name.clone(),
+                    group: resource.group,
+                    binding: resource.binding,
+                    ty,
+                    visibility,
+                },
+            );
+        }
+
+        Ok(bindings.into_values().collect())
+    }
+
+    fn handle_binding_type(&self, ty: naga::Handle<naga::Type>) -> Result<wgpu::BindingType> {
+        Ok(match self.module.types[ty].inner {
+            TypeInner::Sampler { comparison: false } => {
+                // Whether a sampler filters can't be known from the shader alone.
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+            }
+            TypeInner::Sampler { comparison: true } => {
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison)
+            }
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let view_dimension = match (dim, arrayed) {
+                    (ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                    (ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                    (ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                    (ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                    (ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                    (ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                };
+                let (sample_type, multisampled) = match class {
+                    ImageClass::Sampled { kind, multi } => (
+                        match kind {
+                            ScalarKind::Float => {
+                                wgpu::TextureSampleType::Float { filterable: true }
+                            }
+                            ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            ScalarKind::Bool => bail!("{}: boolean textures", self.label),
+                        },
+                        multi,
+                    ),
+                    ImageClass::Depth { multi } => (wgpu::TextureSampleType::Depth, multi),
+                    ImageClass::Storage { .. } => {
+                        bail!("{}: storage textures aren't supported", self.label)
+                    }
+                };
+                wgpu::BindingType::Texture {
+                    sample_type,
+                    view_dimension,
+                    multisampled,
+                }
+            }
+            ref inner => bail!("{}: unexpected handle type {:?}", self.label, inner),
+        })
+    }
+
+    /// Layout entries for bind group `group`, generated from the shader.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        Ok(self
+            .bindings()?
+            .iter()
+            .filter(|binding| binding.group == group)
+            .map(ReflectedBinding::layout_entry)
+            .collect())
+    }
+
+    /// The inputs of the vertex entry point `entry_point`, ordered by location.
+    pub fn vertex_inputs(&self, entry_point: &str) -> Result<Vec<ReflectedVertexInput>> {
+        let entry_point = self
+            .module
+            .entry_points
+            .iter()
+            .find(|e| e.name == entry_point && e.stage == ShaderStage::Vertex)
+            .with_context(|| format!("{} has no vertex entry point {}", self.label, entry_point))?;
+
+        // Inputs can be passed directly or as the members of a struct.
+        let mut inputs = Vec::new();
+        for argument in &entry_point.function.arguments {
+            match (&argument.binding, &self.module.types[argument.ty].inner) {
+                (Some(binding), _) => inputs.push((binding, argument.ty)),
+                (None, TypeInner::Struct { members, .. }) => inputs.extend(
+                    members
+                        .iter()
+                        .filter_map(|member| Some((member.binding.as_ref()?, member.ty))),
+                ),
+                (None, _) => {}
+            }
+        }
+
+        let mut inputs = inputs
+            .into_iter()
+            .filter_map(|(binding, ty)| match *binding {
+                Binding::Location { location, .. } => Some((location, ty)),
+                Binding::BuiltIn(_) => None,
+            })
+            .map(|(location, ty)| {
+                let format = vertex_format(&self.module.types[ty].inner).with_context(|| {
+                    format!(
+                        "{}: unsupported vertex input at location {}",
+                        self.label, location
+                    )
+                })?;
+                Ok(ReflectedVertexInput { location, format })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        inputs.sort_by_key(|input| input.location);
+        Ok(inputs)
+    }
+
+    /// Check that `layouts`, the bind group layout entries of a pipeline layout in group order,
+    /// provide every binding the shader uses, with a compatible type and visibility.
+    pub fn check_bind_group_layouts(
+        &self,
+        layouts: &[&[wgpu::BindGroupLayoutEntry]],
+    ) -> Result<()> {
+        for binding in self.bindings()? {
+            let entry = layouts
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|e| e.binding == binding.binding))
+                .with_context(|| {
+                    format!(
+                        "{}: the pipeline layout is missing binding {}.{} ({})",
+                        self.label,
+                        binding.group,
+                        binding.binding,
+                        binding.name.as_deref().unwrap_or("unnamed")
+                    )
+                })?;
+
+            ensure!(
+                entry.visibility.contains(binding.visibility),
+                "{}: binding {}.{} is used in {:?} but only visible to {:?}",
+                self.label,
+                binding.group,
+                binding.binding,
+                binding.visibility,
+                entry.visibility
+            );
+            ensure!(
+                binding_types_compatible(&entry.ty, &binding.ty),
+                "{}: binding {}.{} is {:?} in the shader but {:?} in the layout",
+                self.label,
+                binding.group,
+                binding.binding,
+                binding.ty,
+                entry.ty
+            );
+        }
+        Ok(())
+    }
+
+    /// Check that `buffers` provide every input of `entry_point` with a matching format.
+    pub fn check_vertex_buffers(
+        &self,
+        entry_point: &str,
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<()> {
+        for input in self.vertex_inputs(entry_point)? {
+            let attribute = buffers
+                .iter()
+                .flat_map(|buffer| buffer.attributes)
+                .find(|attribute| attribute.shader_location == input.location)
+                .with_context(|| {
+                    format!(
+                        "{}: no vertex buffer provides location {}",
+                        self.label, input.location
+                    )
+                })?;
+            ensure!(
+                shader_format(attribute.format) == input.format,
+                "{}: location {} is {:?} in the shader but {:?} in the vertex buffer",
+                self.label,
+                input.location,
+                input.format,
+                attribute.format
+            );
+        }
+        Ok(())
+    }
+
+    /// Check a render pipeline's layout and vertex buffers against the shader.
+    pub fn check_pipeline(
+        &self,
+        bind_group_layouts: &[&[wgpu::BindGroupLayoutEntry]],
+        vertex_entry_point: &str,
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<()> {
+        self.check_bind_group_layouts(bind_group_layouts)?;
+        self.check_vertex_buffers(vertex_entry_point, buffers)
+    }
+}
+
+/// Polls a directory of shaders for files that have been modified since the last poll.
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    /// How often the directory is actually checked, however often [`Self::poll`] is called.
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let mut watcher = Self {
+            dir: dir.into(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.scan()?;
+        Ok(watcher)
+    }
+
+    pub fn dir(&self) -> &FilePath {
+        &self.dir
+    }
+
+    /// The `.wgsl` files that have been created or modified since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < Self::POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        match self.scan() {
+            Result::Ok(changed) => changed,
+            Err(error) => {
+                log::warn!("{:#}", error);
+                Vec::new()
+            }
+        }
+    }
+
+    fn scan(&mut self) -> Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to read shader directory {}", self.dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "wgsl")
+            {
+                continue;
+            }
+            // Editors often replace files on save, so a file can briefly be missing.
+            let modified = match std::fs::metadata(&path).and_then(|m| m.modified()) {
+                Result::Ok(modified) => modified,
+                Err(_) => continue,
+            };
+            if self.modified.insert(path.clone(), modified) != Some(modified) {
+                changed.push(path);
+            }
+        }
+        changed.sort();
+        Ok(changed)
+    }
+}
+
+fn binding_types_compatible(layout: &wgpu::BindingType, shader: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType::*;
+
+    match (layout, shader) {
+        (
+            Buffer {
+                ty: layout_ty,
+                min_binding_size: layout_size,
+                ..
+            },
+            Buffer {
+                ty: shader_ty,
+                min_binding_size: shader_size,
+                ..
+            },
+        ) => {
+            // A read-write storage layout can't be used by a read-only binding, and vice versa.
+            layout_ty == shader_ty
+                && match (layout_size, shader_size) {
+                    (Some(layout_size), Some(shader_size)) => layout_size >= shader_size,
+                    _ => true,
+                }
+        }
+        (
+            Texture {
+                sample_type: layout_sample_type,
+                view_dimension: layout_view_dimension,
+                multisampled: layout_multisampled,
+            },
+            Texture {
+                sample_type: shader_sample_type,
+                view_dimension: shader_view_dimension,
+                multisampled: shader_multisampled,
+            },
+        ) => {
+            let sample_types_compatible = match (layout_sample_type, shader_sample_type) {
+                (wgpu::TextureSampleType::Float { .. }, wgpu::TextureSampleType::Float { .. }) => {
+                    true
+                }
+                (layout, shader) => layout == shader,
+            };
+            sample_types_compatible
+                && layout_view_dimension == shader_view_dimension
+                && layout_multisampled == shader_multisampled
+        }
+        (Sampler(layout), Sampler(shader)) => match shader {
+            wgpu::SamplerBindingType::Comparison => *layout == wgpu::SamplerBindingType::Comparison,
+            _ => *layout != wgpu::SamplerBindingType::Comparison,
+        },
+        _ => false,
+    }
+}
+
+/// The format a vertex attribute appears as in the shader, after any normalization.
+fn shader_format(format: wgpu::VertexFormat) -> wgpu::VertexFormat {
+    use wgpu::VertexFormat::*;
+
+    match format {
+        Uint8x2 | Uint16x2 => Uint32x2,
+        Uint8x4 | Uint16x4 => Uint32x4,
+        Sint8x2 | Sint16x2 => Sint32x2,
+        Sint8x4 | Sint16x4 => Sint32x4,
+        Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 | Float16x2 => Float32x2,
+        Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 | Float16x4 => Float32x4,
+        format => format,
+    }
+}
+
+fn vertex_format(inner: &TypeInner) -> Option<wgpu::VertexFormat> {
+    use wgpu::VertexFormat::*;
+
+    let (kind, width, components) = match *inner {
+        TypeInner::Scalar { kind, width } => (kind, width, 1),
+        TypeInner::Vector { size, kind, width } => (
+            kind,
+            width,
+            match size {
+                VectorSize::Bi => 2,
+                VectorSize::Tri => 3,
+                VectorSize::Quad => 4,
+            },
+        ),
+        _ => return None,
+    };
+    if width != 4 {
+        return None;
+    }
+
+    Some(match (kind, components) {
+        (ScalarKind::Float, 1) => Float32,
+        (ScalarKind::Float, 2) => Float32x2,
+        (ScalarKind::Float, 3) => Float32x3,
+        (ScalarKind::Float, 4) => Float32x4,
+        (ScalarKind::Sint, 1) => Sint32,
+        (ScalarKind::Sint, 2) => Sint32x2,
+        (ScalarKind::Sint, 3) => Sint32x3,
+        (ScalarKind::Sint, 4) => Sint32x4,
+        (ScalarKind::Uint, 1) => Uint32,
+        (ScalarKind::Uint, 2) => Uint32x2,
+        (ScalarKind::Uint, 3) => Uint32x3,
+        (ScalarKind::Uint, 4) => Uint32x4,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors_are_located_in_the_labelled_file() {
+        let source = "// wgsl\nfn main() {\n    let wgsl_value = ;\n}\n";
+        let error = Shader::from_wgsl("broken.wgsl", source).unwrap_err();
+        assert_eq!(error.location.map(|(line, _)| line), Some(3));
+        assert!(error.diagnostic.contains("broken.wgsl:3:"));
+        assert!(error.diagnostic.contains("let wgsl_value = ;"));
+    }
+
+    const SOURCE: &str = "
+        struct Camera {
+            view_projection: mat4x4<f32>,
+        }
+        struct Data {
+            values: array<f32>,
+        }
+        @group(0) @binding(0) var<uniform> camera: Camera;
+        @group(0) @binding(1) var<storage, read> data: Data;
+        @group(1) @binding(0) var color_texture: texture_2d<f32>;
+        @group(1) @binding(1) var color_sampler: sampler;
+        @group(1) @binding(2) var unused: texture_2d<f32>;
+
+        struct VertexInput {
+            @location(1) color: vec4<f32>,
+            @location(0) position: vec3<f32>,
+            @builtin(instance_index) instance: u32,
+        }
+        struct VertexOutput {
+            @builtin(position) position: vec4<f32>,
+            @location(0) color: vec4<f32>,
+        }
+
+        @vertex
+        fn vs_main(
+            input: VertexInput,
+            @location(2) index: u32,
+            @builtin(vertex_index) vertex: u32,
+        ) -> VertexOutput {
+            var out: VertexOutput;
+            out.position = camera.view_projection * vec4<f32>(input.position, data.values[index]);
+            out.color = input.color;
+            return out;
+        }
+
+        @fragment
+        fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+            let uv = in.position.xy * camera.view_projection[0].xy;
+            return in.color * textureSample(color_texture, color_sampler, uv);
+        }
+    ";
+
+    fn shader() -> Shader {
+        Shader::from_wgsl("test.wgsl", SOURCE).unwrap()
+    }
+
+    fn layouts(shader: &Shader) -> [Vec<wgpu::BindGroupLayoutEntry>; 2] {
+        [0, 1].map(|group| shader.bind_group_layout_entries(group).unwrap())
+    }
+
+    fn check(shader: &Shader, layouts: &[Vec<wgpu::BindGroupLayoutEntry>]) -> Result<()> {
+        shader.check_bind_group_layouts(&layouts.iter().map(Vec::as_slice).collect::<Vec<_>>())
+    }
+
+    fn check_error(shader: &Shader, layouts: &[Vec<wgpu::BindGroupLayoutEntry>]) -> String {
+        check(shader, layouts).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn bindings_are_reflected_with_the_stages_using_them() {
+        let bindings = shader().bindings().unwrap();
+        let summary = bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.name.as_deref().unwrap(),
+                    binding.group,
+                    binding.binding,
+                    binding.visibility,
+                )
+            })
+            .collect::<Vec<_>>();
+        // The unused texture is left out.
+        assert_eq!(
+            summary,
+            [
+                ("camera", 0, 0, wgpu::ShaderStages::VERTEX_FRAGMENT),
+                ("data", 0, 1, wgpu::ShaderStages::VERTEX),
+                ("color_texture", 1, 0, wgpu::ShaderStages::FRAGMENT),
+                ("color_sampler", 1, 1, wgpu::ShaderStages::FRAGMENT),
+            ]
+        );
+        assert_eq!(
+            bindings[0].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(64),
+            }
+        );
+        assert_eq!(
+            bindings[1].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            }
+        );
+        assert_eq!(
+            bindings[2].ty,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(
+            bindings[3].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+    }
+
+    #[test]
+    fn vertex_inputs_include_struct_members_but_not_builtins() {
+        let shader = shader();
+        let inputs = shader.vertex_inputs("vs_main").unwrap();
+        assert_eq!(
+            inputs,
+            [
+                ReflectedVertexInput {
+                    location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                ReflectedVertexInput {
+                    location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                ReflectedVertexInput {
+                    location: 2,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ]
+        );
+        assert!(shader.vertex_inputs("fs_main").is_err());
+        assert!(shader.vertex_inputs("missing").is_err());
+    }
+
+    #[test]
+    fn generated_layouts_pass_their_checks() {
+        let shader = shader();
+        check(&shader, &layouts(&shader)).unwrap();
+
+        // Layouts may be visible to more stages, and have bigger buffers, than the shader needs.
+        let mut layouts = layouts(&shader);
+        layouts[0][1].visibility = wgpu::ShaderStages::all();
+        layouts[0][0].ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: NonZeroU64::new(256),
+        };
+        check(&shader, &layouts).unwrap();
+    }
+
+    #[test]
+    fn mismatched_layouts_fail_their_checks() {
+        let shader = shader();
+
+        let mut missing = layouts(&shader);
+        missing[1].remove(1);
+        assert!(check_error(&shader, &missing).contains("missing binding 1.1 (color_sampler)"));
+        assert!(check_error(&shader, &missing[..1]).contains("missing binding 1.0"));
+
+        let mut hidden = layouts(&shader);
+        hidden[0][0].visibility = wgpu::ShaderStages::VERTEX;
+        assert!(check_error(&shader, &hidden).contains("binding 0.0 is used in"));
+
+        let mut small = layouts(&shader);
+        small[0][0].ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(16),
+        };
+        assert!(check_error(&shader, &small).contains("binding 0.0 is"));
+
+        let mut writable = layouts(&shader);
+        writable[0][1].ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        assert!(check_error(&shader, &writable).contains("binding 0.1 is"));
+
+        let mut comparison = layouts(&shader);
+        comparison[1][1].ty = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison);
+        assert!(check_error(&shader, &comparison).contains("binding 1.1 is"));
+    }
+
+    #[test]
+    fn vertex_buffers_are_checked_after_normalization() {
+        let shader = shader();
+        let attributes = wgpu::vertex_attr_array![0 => Float32x3, 1 => Unorm8x4];
+        let instance_attributes = wgpu::vertex_attr_array![2 => Uint32];
+        let buffer = |attributes| wgpu::VertexBufferLayout {
+            array_stride: 16,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        };
+        shader
+            .check_vertex_buffers(
+                "vs_main",
+                &[buffer(&attributes), buffer(&instance_attributes)],
+            )
+            .unwrap();
+
+        let error = shader
+            .check_vertex_buffers("vs_main", &[buffer(&attributes)])
+            .unwrap_err();
+        assert!(error.to_string().contains("provides location 2"));
+
+        let wrong = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4, 2 => Uint32];
+        let error = shader
+            .check_vertex_buffers("vs_main", &[buffer(&wrong)])
+            .unwrap_err();
+        assert!(error.to_string().contains("location 0 is Float32x3"));
+    }
+
+    #[test]
+    fn formats_are_normalized_as_the_shader_sees_them() {
+        use wgpu::VertexFormat::*;
+
+        for (format, expected) in [
+            (Uint8x2, Uint32x2),
+            (Uint16x4, Uint32x4),
+            (Sint16x2, Sint32x2),
+            (Sint8x4, Sint32x4),
+            (Unorm8x2, Float32x2),
+            (Snorm16x2, Float32x2),
+            (Float16x4, Float32x4),
+            (Unorm8x4, Float32x4),
+            (Float32x3, Float32x3),
+            (Uint32, Uint32),
+        ] {
+            assert_eq!(shader_format(format), expected, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::ops::Range;
+
+use anyhow::*;
+use glam::Vec2;
+use lyon::{
+    algorithms::measure::{PathMeasurements, SampleType},
+    geom::{point, Box2D},
+    lyon_tessellation::{
+        BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, FillVertexConstructor,
+        StrokeOptions, StrokeTessellator, StrokeVertex, StrokeVertexConstructor, VertexBuffers,
+    },
+    path::{Path, PathEvent, Winding},
+};
+
+pub use lyon::lyon_tessellation::{LineCap, LineJoin};
+
+use crate::{paint::Paint, renderer::ShapeVertex, vector::VectorGraphic};
+
+/// The most dashes and gaps a single sub-path is split into. Patterns tiny compared to the path
+/// stop partway along it rather than tessellating millions of pieces.
+const MAX_DASH_SEGMENTS: usize = 1 << 16;
+
+/// Alternating lengths of dashes and gaps, starting with a dash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashPattern {
+    dashes: Vec<f32>,
+    /// How far into the pattern each sub-path starts.
+    pub offset: f32,
+}
+
+impl DashPattern {
+    /// As in SVG, a pattern with an odd number of lengths is repeated to make it even.
+    pub fn new(dashes: &[f32], offset: f32) -> Self {
+        let mut dashes = dashes.iter().map(|d| d.max(0.0)).collect::<Vec<_>>();
+        if dashes.len() % 2 == 1 {
+            dashes.extend_from_within(..);
+        }
+        Self { dashes, offset }
+    }
+
+    pub fn dashes(&self) -> &[f32] {
+        &self.dashes
+    }
+
+    /// Split `path` into the dashes of this pattern, restarting the pattern for each sub-path.
+    fn apply(&self, path: &Path, tolerance: f32) -> Path {
+        let period: f32 = self.dashes.iter().sum();
+        if period <= 0.0 {
+            return path.clone();
+        }
+
+        let mut builder = Path::builder();
+        for subpath in subpaths(path) {
+            let measurements = PathMeasurements::from_path(&subpath, tolerance);
+            let mut sampler = measurements.create_sampler(&subpath, SampleType::Distance);
+            let length = sampler.length();
+
+            // Find where in the pattern the sub-path starts.
+            let mut index = 0;
+            let mut into_dash = self.offset.rem_euclid(period);
+            while into_dash >= self.dashes[index] {
+                into_dash -= self.dashes[index];
+                index = (index + 1) % self.dashes.len();
+            }
+
+            let mut distance = -into_dash;
+            let mut segments = 0;
+            while distance < length && segments < MAX_DASH_SEGMENTS {
+                // Far enough along the path, a whole period can round away to nothing.
+                if distance + period <= distance {
+                    break;
+                }
+                let end = distance + self.dashes[index];
+                if index % 2 == 0 {
+                    sampler.split_range(distance.max(0.0)..end.min(length), builder.inner_mut());
+                }
+                distance = end;
+                index = (index + 1) % self.dashes.len();
+                segments += 1;
+            }
+        }
+
+        builder.build()
+    }
+}
+
+fn subpaths(path: &Path) -> Vec<Path> {
+    let mut subpaths = Vec::new();
+    let mut builder = Path::builder();
+    for event in path.iter() {
+        match event {
+            PathEvent::Begin { at } => {
+                builder.begin(at);
+            }
+            PathEvent::Line { to, .. } => {
+                builder.line_to(to);
+            }
+            PathEvent::Quadratic { ctrl, to, .. } => {
+                builder.quadratic_bezier_to(ctrl, to);
+            }
+            PathEvent::Cubic {
+                ctrl1, ctrl2, to, ..
+            } => {
+                builder.cubic_bezier_to(ctrl1, ctrl2, to);
+            }
+            PathEvent::End { close, .. } => {
+                builder.end(close);
+                subpaths.push(std::mem::replace(&mut builder, Path::builder()).build());
+            }
+        }
+    }
+    subpaths
+}
+
+/// How the outline of a shape is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub line_join: LineJoin,
+    pub start_cap: LineCap,
+    pub end_cap: LineCap,
+    /// The ratio of miter length to stroke width at which miter joins fall back to bevels.
+    /// Must be at least 1.0.
+    pub miter_limit: f32,
+    pub dash: Option<DashPattern>,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            width: StrokeOptions::DEFAULT_LINE_WIDTH,
+            line_join: StrokeOptions::DEFAULT_LINE_JOIN,
+            start_cap: StrokeOptions::DEFAULT_LINE_CAP,
+            end_cap: StrokeOptions::DEFAULT_LINE_CAP,
+            miter_limit: StrokeOptions::DEFAULT_MITER_LIMIT,
+            dash: None,
+        }
+    }
+}
+
+impl StrokeStyle {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_line_join(mut self, line_join: LineJoin) -> Self {
+        self.line_join = line_join;
+        self
+    }
+
+    /// Use the same cap at both ends of each sub-path.
+    pub fn with_line_cap(mut self, cap: LineCap) -> Self {
+        self.start_cap = cap;
+        self.end_cap = cap;
+        self
+    }
+
+    pub fn with_start_cap(mut self, cap: LineCap) -> Self {
+        self.start_cap = cap;
+        self
+    }
+
+    pub fn with_end_cap(mut self, cap: LineCap) -> Self {
+        self.end_cap = cap;
+        self
+    }
+
+    pub fn with_miter_limit(mut self, miter_limit: f32) -> Self {
+        self.miter_limit = miter_limit;
+        self
+    }
+
+    pub fn with_dash(mut self, dashes: &[f32], offset: f32) -> Self {
+        self.dash = Some(DashPattern::new(dashes, offset));
+        self
+    }
+
+    fn to_options(&self, tolerance: f32) -> StrokeOptions {
+        StrokeOptions::tolerance(tolerance)
+            .with_line_width(self.width)
+            .with_line_join(self.line_join)
+            .with_start_cap(self.start_cap)
+            .with_end_cap(self.end_cap)
+            .with_miter_limit(self.miter_limit.max(StrokeOptions::MINIMUM_MITER_LIMIT))
+    }
+}
+
+/// A range of indices drawn with one paint.
+#[derive(Debug, Clone)]
+pub(crate) struct PaintedRange {
+    pub(crate) indices: Range<u32>,
+    /// Index into the graphic's paints.
+    pub(crate) paint: usize,
+}
+
+/// Tessellates filled and stroked paths into a single [`VectorGraphic`].
+pub struct ShapeBuilder {
+    geometry: VertexBuffers<ShapeVertex, u32>,
+    ranges: Vec<PaintedRange>,
+    /// The first paint is shared by every solid color, which is baked into the vertices instead.
+    paints: Vec<Paint>,
+    fill_tessellator: FillTessellator,
+    stroke_tessellator: StrokeTessellator,
+    tolerance: f32,
+}
+
+impl Default for ShapeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShapeBuilder {
+    pub fn new() -> Self {
+        Self::with_tolerance(0.02)
+    }
+
+    /// `tolerance` is the maximum distance between the tessellated geometry and the true curves.
+    pub fn with_tolerance(tolerance: f32) -> Self {
+        Self {
+            geometry: VertexBuffers::new(),
+            ranges: Vec::new(),
+            paints: vec![Paint::Solid([1.0, 1.0, 1.0, 1.0])],
+            fill_tessellator: FillTessellator::new(),
+            stroke_tessellator: StrokeTessellator::new(),
+            tolerance,
+        }
+    }
+
+    pub fn fill(&mut self, path: &Path, paint: impl Into<Paint>) -> Result<()> {
+        self.fill_with_rule(path, FillRule::NonZero, paint)
+    }
+
+    pub fn fill_with_rule(
+        &mut self,
+        path: &Path,
+        fill_rule: FillRule,
+        paint: impl Into<Paint>,
+    ) -> Result<()> {
+        let (color, paint) = self.add_paint(paint.into())?;
+        let start = self.geometry.indices.len() as u32;
+        self.fill_tessellator.tessellate_path(
+            path,
+            &FillOptions::tolerance(self.tolerance).with_fill_rule(fill_rule),
+            &mut BuffersBuilder::new(&mut self.geometry, ColoredVertex { color }),
+        )?;
+        self.add_range(start, paint);
+        Ok(())
+    }
+
+    pub fn stroke(
+        &mut self,
+        path: &Path,
+        paint: impl Into<Paint>,
+        style: &StrokeStyle,
+    ) -> Result<()> {
+        let dashed;
+        let path = match &style.dash {
+            Some(dash) => {
+                dashed = dash.apply(path, self.tolerance);
+                &dashed
+            }
+            None => path,
+        };
+
+        let (color, paint) = self.add_paint(paint.into())?;
+        let start = self.geometry.indices.len() as u32;
+        self.stroke_tessellator.tessellate_path(
+            path,
+            &style.to_options(self.tolerance),
+            &mut BuffersBuilder::new(&mut self.geometry, ColoredVertex { color }),
+        )?;
+        self.add_range(start, paint);
+        Ok(())
+    }
+
+    pub fn fill_rect(&mut self, rect: Box2D<f32>, paint: impl Into<Paint>) -> Result<()> {
+        self.fill(&rect_path(rect), paint)
+    }
+
+    pub fn stroke_rect(
+        &mut self,
+        rect: Box2D<f32>,
+        paint: impl Into<Paint>,
+        style: &StrokeStyle,
+    ) -> Result<()> {
+        self.stroke(&rect_path(rect), paint, style)
+    }
+
+    pub fn fill_circle(
+        &mut self,
+        center: Vec2,
+        radius: f32,
+        paint: impl Into<Paint>,
+    ) -> Result<()> {
+        self.fill(&circle_path(center, radius), paint)
+    }
+
+    pub fn stroke_circle(
+        &mut self,
+        center: Vec2,
+        radius: f32,
+        paint: impl Into<Paint>,
+        style: &StrokeStyle,
+    ) -> Result<()> {
+        self.stroke(&circle_path(center, radius), paint, style)
+    }
+
+    /// `paint_layout` is [`crate::renderer::Renderer::paint_bind_group_layout`].
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        paint_layout: &wgpu::BindGroupLayout,
+        label: &str,
+    ) -> VectorGraphic {
+        VectorGraphic::from_geometry(
+            device,
+            paint_layout,
+            label,
+            &self.geometry,
+            self.bounds(),
+            &self.ranges,
+            &self.paints,
+        )
+    }
+
+    /// The bounding box of the geometry so far.
+    pub fn bounds(&self) -> Box2D<f32> {
+        Box2D::from_points(
+            self.geometry
+                .vertices
+                .iter()
+                .map(|v| point(v.position[0], v.position[1])),
+        )
+    }
+
+    /// Returns the vertex color and paint index to tessellate with.
+    fn add_paint(&mut self, paint: Paint) -> Result<([f32; 4], usize)> {
+        paint.validate()?;
+        if let Paint::Solid(color) = paint {
+            return Ok((color, 0));
+        }
+        self.paints.push(paint);
+        Ok(([1.0, 1.0, 1.0, 1.0], self.paints.len() - 1))
+    }
+
+    fn add_range(&mut self, start: u32, paint: usize) {
+        let end = self.geometry.indices.len() as u32;
+        match self.ranges.last_mut() {
+            Some(last) if last.paint == paint && last.indices.end == start => {
+                last.indices.end = end;
+            }
+            _ => self.ranges.push(PaintedRange {
+                indices: start..end,
+                paint,
+            }),
+        }
+    }
+}
+
+fn rect_path(rect: Box2D<f32>) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rectangle(&rect, Winding::Positive);
+    builder.build()
+}
+
+fn circle_path(center: Vec2, radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_circle(point(center.x, center.y), radius, Winding::Positive);
+    builder.build()
+}
+
+struct ColoredVertex {
+    color: [f32; 4],
+}
+
+impl FillVertexConstructor<ShapeVertex> for ColoredVertex {
+    fn new_vertex(&mut self, vertex: FillVertex) -> ShapeVertex {
+        let p = vertex.position();
+        ShapeVertex {
+            position: [p.x, p.y, 0.0],
+            color: self.color,
+        }
+    }
+}
+
+impl StrokeVertexConstructor<ShapeVertex> for ColoredVertex {
+    fn new_vertex(&mut self, vertex: StrokeVertex) -> ShapeVertex {
+        let p = vertex.position();
+        ShapeVertex {
+            position: [p.x, p.y, 0.0],
+            color: self.color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(length: f32) -> Path {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.line_to(point(length, 0.0));
+        builder.end(false);
+        builder.build()
+    }
+
+    #[test]
+    fn dashes_split_a_line() {
+        let dashed = DashPattern::new(&[10.0, 5.0], 0.0).apply(&line(100.0), 0.1);
+        // Dashes start every 15 units: 0, 15, ..., 90.
+        assert_eq!(subpaths(&dashed).len(), 7);
+    }
+
+    #[test]
+    fn tiny_dashes_are_capped() {
+        let dashed = DashPattern::new(&[1e-3], 0.0).apply(&line(1e6), 0.1);
+        assert_eq!(subpaths(&dashed).len(), MAX_DASH_SEGMENTS / 2);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::texture::Texture;
+
+/// The format of the depth textures the renderer's pipelines test against.
+pub(crate) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// An offscreen color texture, and optionally a depth texture, to render into instead of the
+/// surface. The color texture can then be drawn like any other, for minimaps, portals or
+/// previews.
+#[derive(Debug)]
+pub struct RenderTarget {
+    color: Texture,
+    depth: Option<wgpu::TextureView>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    label: String,
+}
+
+impl RenderTarget {
+    /// A target in `format`, which must match the pipelines that draw into it. The renderer's
+    /// pipelines need a depth texture, so targets for it are best created with
+    /// [`crate::renderer::Renderer::create_render_target`].
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        with_depth: bool,
+        label: &str,
+    ) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            color: Texture {
+                texture,
+                view,
+                sampler,
+                format,
+                width,
+                height,
+            },
+            depth: with_depth.then(|| create_depth_view(device, width, height, label)),
+            format,
+            width,
+            height,
+            label: label.to_string(),
+        }
+    }
+
+    /// The color texture, which can be bound with
+    /// [`crate::renderer::Renderer::create_sprite_bind_group`]. Bind groups have to be created
+    /// again after the target is resized.
+    pub fn color(&self) -> &Texture {
+        &self.color
+    }
+
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        self.depth.as_ref()
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Recreate the textures at a new size, discarding their contents.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        *self = Self::new(
+            device,
+            self.format,
+            width,
+            height,
+            self.depth.is_some(),
+            &self.label,
+        );
+    }
+}
+
+pub(crate) fn create_depth_view(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    label: &str,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// What [`crate::renderer::Renderer::begin`] draws into.
+#[derive(Debug, Clone, Copy)]
+pub enum RenderPassTarget<'a> {
+    /// A view of the surface texture, drawn with the renderer's own depth texture.
+    Surface(&'a wgpu::TextureView),
+    Texture(&'a RenderTarget),
+}
+
+impl<'a> From<&'a wgpu::TextureView> for RenderPassTarget<'a> {
+    fn from(view: &'a wgpu::TextureView) -> Self {
+        RenderPassTarget::Surface(view)
+    }
+}
+
+impl<'a> From<&'a RenderTarget> for RenderPassTarget<'a> {
+    fn from(target: &'a RenderTarget) -> Self {
+        RenderPassTarget::Texture(target)
+    }
+}
@@ -1,10 +1,284 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::Cursor,
+    num::NonZeroU8,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use anyhow::*;
-use image::GenericImageView;
+use image::{codecs::gif::GifDecoder, AnimationDecoder, GenericImageView};
+
+use crate::{
+    compressed::CompressedImage,
+    pipeline::{PipelineDescriptor, PipelineFactory},
+    renderer::SPRITE_LAYOUT_ENTRIES,
+    shader::Shader,
+};
+
+/// How a texture is filtered and what happens outside of it, turned into a `wgpu::Sampler`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    /// Between mip levels, so only matters for textures with mipmaps.
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    /// Used by `AddressMode::ClampToBorder`.
+    pub border_color: wgpu::SamplerBorderColor,
+    /// The most samples anisotropic filtering takes, 1 to turn it off or a power of two up to
+    /// 16. Ignored by adapters that don't support it.
+    pub anisotropy: u8,
+    /// The range of mip levels sampled from.
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+}
+
+impl SamplerConfig {
+    /// Smooth in every direction, for textures drawn at many sizes.
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Self::default()
+        }
+    }
+
+    /// Blocky, for pixel art.
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Self::default()
+        }
+    }
+
+    /// Use `address_mode` in both directions. `AddressMode::ClampToBorder` needs
+    /// `Features::ADDRESS_MODE_CLAMP_TO_BORDER`.
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    pub fn with_border_color(mut self, border_color: wgpu::SamplerBorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: u8) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_lod_clamp(mut self, min: f32, max: f32) -> Self {
+        self.lod_min_clamp = min;
+        self.lod_max_clamp = max;
+        self
+    }
+
+    pub fn create_sampler(
+        &self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+    ) -> Result<wgpu::Sampler> {
+        ensure!(
+            matches!(self.anisotropy, 1 | 2 | 4 | 8 | 16),
+            "anisotropy must be 1, 2, 4, 8 or 16, not {}",
+            self.anisotropy
+        );
+        let clamps_to_border =
+            [self.address_mode_u, self.address_mode_v].contains(&wgpu::AddressMode::ClampToBorder);
+        ensure!(
+            !clamps_to_border
+                || device
+                    .features()
+                    .contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER),
+            "clamping to the border needs Features::ADDRESS_MODE_CLAMP_TO_BORDER"
+        );
+        Ok(device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            anisotropy_clamp: NonZeroU8::new(self.anisotropy).filter(|clamp| clamp.get() > 1),
+            border_color: clamps_to_border.then_some(self.border_color),
+            ..Default::default()
+        }))
+    }
+}
 
+impl Default for SamplerConfig {
+    /// Linear when magnified, nearest when minified, and clamped to the edge.
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            border_color: wgpu::SamplerBorderColor::TransparentBlack,
+            anisotropy: 1,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: f32::MAX,
+        }
+    }
+}
+
+/// Whether and how a texture's smaller mip levels are made, so that it doesn't shimmer when
+/// drawn smaller than it is.
+#[derive(Debug, Clone, Default)]
+pub enum Mipmaps {
+    #[default]
+    None,
+    /// Each level averages 2x2 blocks of the one before, in linear color, before uploading.
+    Cpu,
+    /// Each level is rendered from the one before with a linear sampler by the generator, which
+    /// can be shared by every texture.
+    Gpu(Arc<MipmapGenerator>),
+}
+
+impl PartialEq for Mipmaps {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Mipmaps::None, Mipmaps::None) | (Mipmaps::Cpu, Mipmaps::Cpu) => true,
+            (Mipmaps::Gpu(a), Mipmaps::Gpu(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// The formats textures can be created in from pixels or images, and how their pixels are laid
+/// out in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextureFormat {
+    /// RGBA8 in sRGB, for colors, which shaders sample in linear color.
+    #[default]
+    Rgba8Srgb,
+    /// RGBA8 sampled exactly as stored, for data such as normal maps and masks.
+    Rgba8Unorm,
+    /// One 8 bit channel, sampled as red, for masks and glyph coverage. Images are converted to
+    /// their luminance.
+    R8Unorm,
+    /// RGBA as 16 bit floats in linear color, for HDR images with values above 1.
+    Rgba16Float,
+}
+
+impl TextureFormat {
+    pub fn wgpu_format(self) -> wgpu::TextureFormat {
+        match self {
+            TextureFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            TextureFormat::R8Unorm => wgpu::TextureFormat::R8Unorm,
+            TextureFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            TextureFormat::Rgba8Srgb | TextureFormat::Rgba8Unorm => 4,
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::Rgba16Float => 8,
+        }
+    }
+
+    /// Convert an image to this format's pixels.
+    pub fn image_bytes(self, img: &image::DynamicImage) -> Vec<u8> {
+        match self {
+            TextureFormat::Rgba8Srgb | TextureFormat::Rgba8Unorm => img.to_rgba8().into_raw(),
+            TextureFormat::R8Unorm => img.to_luma8().into_raw(),
+            TextureFormat::Rgba16Float => img
+                .to_rgba32f()
+                .into_raw()
+                .into_iter()
+                .flat_map(|value| f32_to_f16(value).to_le_bytes())
+                .collect(),
+        }
+    }
+
+    /// The channels of the pixel at `bytes`, in linear color.
+    fn read_pixel(self, bytes: &[u8]) -> [f32; 4] {
+        let unorm = |value: u8| value as f32 / 255.0;
+        match self {
+            TextureFormat::Rgba8Srgb => [
+                srgb_to_linear(bytes[0]),
+                srgb_to_linear(bytes[1]),
+                srgb_to_linear(bytes[2]),
+                unorm(bytes[3]),
+            ],
+            TextureFormat::Rgba8Unorm => [0, 1, 2, 3].map(|c| unorm(bytes[c])),
+            TextureFormat::R8Unorm => [unorm(bytes[0]), 0.0, 0.0, 1.0],
+            TextureFormat::Rgba16Float => [0, 1, 2, 3]
+                .map(|c| f16_to_f32(u16::from_le_bytes([bytes[2 * c], bytes[2 * c + 1]]))),
+        }
+    }
+
+    fn write_pixel(self, pixel: [f32; 4], output: &mut Vec<u8>) {
+        let unorm = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self {
+            TextureFormat::Rgba8Srgb => output.extend([
+                linear_to_srgb(pixel[0]),
+                linear_to_srgb(pixel[1]),
+                linear_to_srgb(pixel[2]),
+                unorm(pixel[3]),
+            ]),
+            TextureFormat::Rgba8Unorm => output.extend(pixel.map(unorm)),
+            TextureFormat::R8Unorm => output.push(unorm(pixel[0])),
+            TextureFormat::Rgba16Float => output.extend(
+                pixel
+                    .into_iter()
+                    .flat_map(|value| f32_to_f16(value).to_le_bytes()),
+            ),
+        }
+    }
+}
+
+/// How a texture is created from an image.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextureOptions {
+    pub format: TextureFormat,
+    pub sampler: SamplerConfig,
+    pub mipmaps: Mipmaps,
+}
+
+impl TextureOptions {
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerConfig) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: Mipmaps) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+}
+
+/// The number of mip levels down to 1x1 for a texture of `width` by `height`.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+#[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
 }
 
 impl Texture {
@@ -16,6 +290,46 @@ impl Texture {
         bytes: &[u8],
         label: Option<&str>,
     ) -> Result<Self> {
+        Self::from_bytes_with_options(
+            device,
+            queue,
+            width,
+            height,
+            bytes,
+            label,
+            &TextureOptions::default(),
+        )
+    }
+
+    /// A texture from tightly packed pixels in `options.format`.
+    pub fn from_bytes_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        bytes: &[u8],
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let bytes_per_pixel = options.format.bytes_per_pixel();
+        ensure!(
+            bytes.len() == (bytes_per_pixel * width * height) as usize,
+            "expected {} bytes of {:?} for a {}x{} texture, found {}",
+            bytes_per_pixel * width * height,
+            options.format,
+            width,
+            height,
+            bytes.len()
+        );
+        let format = options.format.wgpu_format();
+        let mip_level_count = match options.mipmaps {
+            Mipmaps::None => 1,
+            Mipmaps::Cpu | Mipmaps::Gpu(_) => mip_level_count(width, height),
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if let Mipmaps::Gpu(_) = options.mipmaps {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
         let size = wgpu::Extent3d {
             width,
             height,
@@ -24,44 +338,275 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+
+        write_mip_level(queue, &texture, 0, bytes_per_pixel, width, height, bytes);
+        match &options.mipmaps {
+            Mipmaps::None => {}
+            Mipmaps::Cpu => {
+                let (mut level_width, mut level_height) = (width, height);
+                let mut level = bytes.to_vec();
+                for mip_level in 1..mip_level_count {
+                    (level, level_width, level_height) =
+                        downsample(&level, level_width, level_height, options.format);
+                    write_mip_level(
+                        queue,
+                        &texture,
+                        mip_level,
+                        bytes_per_pixel,
+                        level_width,
+                        level_height,
+                        &level,
+                    );
+                }
+            }
+            Mipmaps::Gpu(generator) => {
+                generator.generate(device, queue, &texture, format, mip_level_count)?
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = options.sampler.create_sampler(device, label)?;
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+            width,
+            height,
+        })
+    }
+
+    /// A texture in `options.format` that's transparent black until written to with
+    /// [`Self::write_region`], for painting, video frames or glyph caches. It has no mipmaps,
+    /// whatever `options.mipmaps` is.
+    pub fn empty(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        ensure!(
+            width > 0 && height > 0,
+            "a texture must be at least 1x1, not {}x{}",
+            width,
+            height
+        );
+        let format = options.format.wgpu_format();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = options.sampler.create_sampler(device, label)?;
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+            width,
+            height,
+        })
+    }
 
+    /// Overwrite the `width` by `height` rectangle at `x`, `y` from the top left with tightly
+    /// packed pixels in the texture's format. Only the full size mip level is written, so
+    /// textures with mipmaps keep their old smaller levels.
+    pub fn write_region(
+        &self,
+        queue: &wgpu::Queue,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let info = self.format.describe();
+        ensure!(
+            info.block_dimensions == (1, 1),
+            "regions of block-compressed {:?} textures can't be written",
+            self.format
+        );
+        ensure!(
+            x.checked_add(width)
+                .is_some_and(|right| right <= self.width)
+                && y.checked_add(height)
+                    .is_some_and(|bottom| bottom <= self.height),
+            "the {}x{} region at {}, {} is outside the {}x{} texture",
+            width,
+            height,
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let bytes_per_pixel = info.block_size as u32;
+        ensure!(
+            bytes.len() == (bytes_per_pixel * width * height) as usize,
+            "expected {} bytes of {:?} for a {}x{} region, found {}",
+            bytes_per_pixel * width * height,
+            self.format,
+            width,
+            height,
+            bytes.len()
+        );
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
-                texture: &texture,
+                texture: &self.texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin: wgpu::Origin3d { x, y, z: 0 },
             },
             bytes,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                bytes_per_row: std::num::NonZeroU32::new(bytes_per_pixel * width),
                 rows_per_image: std::num::NonZeroU32::new(height),
             },
-            size,
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
         );
+        Ok(())
+    }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
+    /// A texture from a KTX2 file of BC blocks, see [`Self::from_compressed`].
+    pub fn from_ktx2(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let image = CompressedImage::from_ktx2(bytes)
+            .with_context(|| format!("failed to load {}", label))?;
+        Self::from_compressed(device, queue, &image, Some(label), sampler)
+    }
+
+    /// A texture from a DDS file of BC blocks, see [`Self::from_compressed`].
+    pub fn from_dds(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let image = CompressedImage::from_dds(bytes)
+            .with_context(|| format!("failed to load {}", label))?;
+        Self::from_compressed(device, queue, &image, Some(label), sampler)
+    }
+
+    /// A texture with every mip level of a block-compressed image. When the device can't sample
+    /// the image compressed, it's decompressed into [`CompressedImage::decompressed_format`]
+    /// instead, at four to eight times the memory.
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: Option<&str>,
+        sampler: &SamplerConfig,
+    ) -> Result<Self> {
+        let max_size = device.limits().max_texture_dimension_2d;
+        ensure!(
+            image.width() <= max_size && image.height() <= max_size,
+            "{} is {}x{}, but the device's textures can be at most {}x{}",
+            label.unwrap_or("texture"),
+            image.width(),
+            image.height(),
+            max_size,
+            max_size
+        );
+        let compressed = image.is_supported(device);
+        if !compressed {
+            log::warn!(
+                "{} can't be sampled as {:?}, decompressing it on the CPU",
+                label.unwrap_or("texture"),
+                image.format()
+            );
+        }
+        let format = if compressed {
+            image.format()
+        } else {
+            image.decompressed_format()
+        };
+        let mip_level_count = image.levels().len() as u32;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
+        for (mip_level, blocks) in (0..mip_level_count).zip(image.levels()) {
+            let (width, height) = image.level_size(mip_level);
+            if compressed {
+                // Copies of compressed levels are in whole blocks, even past the level's edge.
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    blocks,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(
+                            width.div_ceil(4) * image.block_size(),
+                        ),
+                        rows_per_image: std::num::NonZeroU32::new(height.div_ceil(4)),
+                    },
+                    wgpu::Extent3d {
+                        width: width.div_ceil(4) * 4,
+                        height: height.div_ceil(4) * 4,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            } else {
+                let pixels = image.decompress(mip_level)?;
+                write_mip_level(queue, &texture, mip_level, 4, width, height, &pixels);
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = sampler.create_sampler(device, label)?;
+
         Ok(Self {
             texture,
             view,
             sampler,
+            format,
+            width: image.width(),
+            height: image.height(),
         })
     }
 
@@ -71,8 +616,43 @@ impl Texture {
         bytes: &[u8],
         label: &str,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image_bytes_with_options(device, queue, bytes, label, &TextureOptions::default())
+    }
+
+    pub fn from_image_bytes_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let img = load_image(bytes, label)?;
+        Self::from_image_with_options(device, queue, &img, Some(label), options)
+    }
+
+    /// A texture for each frame of an animated GIF, and how long the frame is shown for.
+    pub fn from_gif_frames(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+        options: &TextureOptions,
+    ) -> Result<Vec<(Self, Duration)>> {
+        let decoder = GifDecoder::new(Cursor::new(bytes))
+            .with_context(|| format!("failed to decode {}", label))?;
+        decoder
+            .into_frames()
+            .enumerate()
+            .map(|(index, frame)| {
+                let frame = frame.with_context(|| format!("failed to decode {}", label))?;
+                let duration = Duration::from(frame.delay());
+                let img = image::DynamicImage::ImageRgba8(frame.into_buffer());
+                let label = format!("{} frame {}", label, index);
+                let texture =
+                    Self::from_image_with_options(device, queue, &img, Some(&label), options)?;
+                Ok((texture, duration))
+            })
+            .collect()
     }
 
     pub fn from_image(
@@ -81,10 +661,28 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
+        Self::from_image_with_options(device, queue, img, label, &TextureOptions::default())
+    }
+
+    pub fn from_image_with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let bytes = options.format.image_bytes(img);
         let dimensions = img.dimensions();
 
-        Self::from_bytes(device, queue, dimensions.0, dimensions.1, &rgba, label)
+        Self::from_bytes_with_options(
+            device,
+            queue,
+            dimensions.0,
+            dimensions.1,
+            &bytes,
+            label,
+            options,
+        )
 
         // let size = wgpu::Extent3d {
         //     width: dimensions.0,
@@ -135,3 +733,436 @@ impl Texture {
         // })
     }
 }
+
+fn write_mip_level(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    bytes_per_pixel: u32,
+    width: u32,
+    height: u32,
+    bytes: &[u8],
+) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        bytes,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(bytes_per_pixel * width),
+            rows_per_image: std::num::NonZeroU32::new(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Decode an image, in the format its bytes start with or else the one its name ends with, since
+/// formats such as TGA can't be recognised from their bytes.
+pub fn load_image(bytes: &[u8], name: &str) -> Result<image::DynamicImage> {
+    let format = image::guess_format(bytes)
+        .or_else(|_| image::ImageFormat::from_path(name))
+        .with_context(|| format!("unrecognised image format for {}", name))?;
+    image::load_from_memory_with_format(bytes, format)
+        .with_context(|| format!("failed to decode {}", name))
+}
+
+/// Halve pixels in each direction, averaging 2x2 blocks in linear color. An odd last row or
+/// column is averaged into the one before it.
+fn downsample(bytes: &[u8], width: u32, height: u32, format: TextureFormat) -> (Vec<u8>, u32, u32) {
+    let bytes_per_pixel = format.bytes_per_pixel() as usize;
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let pixel = |x: u32, y: u32| {
+        let i = bytes_per_pixel * (y * width + x) as usize;
+        format.read_pixel(&bytes[i..i + bytes_per_pixel])
+    };
+    let mut output = Vec::with_capacity(bytes_per_pixel * (half_width * half_height) as usize);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            // The 2x2 block, plus the odd row or column past it at the edges.
+            let xs = 2 * x..if x + 1 == half_width {
+                width
+            } else {
+                2 * x + 2
+            };
+            let ys = 2 * y..if y + 1 == half_height {
+                height
+            } else {
+                2 * y + 2
+            };
+            let mut sum = [0.0; 4];
+            let mut count = 0.0;
+            for y in ys {
+                for x in xs.clone() {
+                    for (sum, value) in sum.iter_mut().zip(pixel(x, y)) {
+                        *sum += value;
+                    }
+                    count += 1.0;
+                }
+            }
+            format.write_pixel(sum.map(|sum| sum / count), &mut output);
+        }
+    }
+    (output, half_width, half_height)
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// The bits of the half precision float nearest to `value`, which saturates to infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity, and NaN stays NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        // Subnormal, or too small and flushed to zero.
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = 1 << (shift - 1);
+        sign | ((mantissa + half - 1 + ((mantissa >> shift) & 1)) >> shift) as u16
+    } else {
+        // Round to nearest even, carrying into the exponent if the mantissa overflows.
+        let rounded = mantissa + 0xfff + ((mantissa >> 13) & 1);
+        let value = ((exponent as u32) << 10) + (rounded >> 13);
+        sign | value.min(0x7c00) as u16
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, normalised by shifting the mantissa up to its leading one.
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        0x1f => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | ((exponent + 112) << 23) | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+/// Two textures that take turns being drawn and being written to, for contents that change every
+/// frame such as video or a simulation. Each frame's pixels go to the texture that wasn't drawn
+/// last frame, so writing them never waits on the GPU to finish reading the previous ones.
+///
+/// Draw with a bind group for each of [`Self::textures`], picking the one at
+/// [`Self::front_index`].
+#[derive(Debug)]
+pub struct DynamicTexture {
+    textures: [Texture; 2],
+    front: usize,
+}
+
+impl DynamicTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: &str,
+        options: &TextureOptions,
+    ) -> Result<Self> {
+        let texture = |index| {
+            let label = format!("{} {}", label, index);
+            Texture::empty(device, width, height, Some(&label), options)
+        };
+        Ok(Self {
+            textures: [texture(0)?, texture(1)?],
+            front: 0,
+        })
+    }
+
+    /// Write a whole frame of pixels to the back texture and make it the front.
+    pub fn write(&mut self, queue: &wgpu::Queue, bytes: &[u8]) -> Result<()> {
+        let back = &self.textures[1 - self.front];
+        back.write_region(queue, 0, 0, back.width, back.height, bytes)?;
+        self.front = 1 - self.front;
+        Ok(())
+    }
+
+    /// The texture last written, to draw this frame.
+    pub fn front(&self) -> &Texture {
+        &self.textures[self.front]
+    }
+
+    pub fn front_index(&self) -> usize {
+        self.front
+    }
+
+    pub fn textures(&self) -> &[Texture; 2] {
+        &self.textures
+    }
+
+    pub fn width(&self) -> u32 {
+        self.front().width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.front().height
+    }
+}
+
+/// Fills in the mip levels of textures by rendering each from the one before. The pipeline for
+/// a format is compiled the first time a texture in it is generated, so share one generator
+/// between textures with [`Mipmaps::Gpu`].
+#[derive(Debug)]
+pub struct MipmapGenerator {
+    shader: Shader,
+    layout: wgpu::PipelineLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipelines: Mutex<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = Shader::from_wgsl("mipmap.wgsl", include_str!("../shaders/mipmap.wgsl"))?;
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mipmap Bind Group Layout"),
+            entries: SPRITE_LAYOUT_ENTRIES,
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = SamplerConfig::linear().create_sampler(device, Some("Mipmap Sampler"))?;
+
+        Ok(Self {
+            shader,
+            layout,
+            bind_group_layout,
+            sampler,
+            pipelines: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<wgpu::RenderPipeline> {
+        let factory = PipelineFactory {
+            color_format: format,
+            blend_state: wgpu::BlendState::REPLACE,
+            depth_stencil_state: None,
+        };
+        factory.create(
+            device,
+            &self.shader,
+            &PipelineDescriptor {
+                label: "Mipmap Pipeline",
+                layout: &self.layout,
+                bind_group_layouts: &[SPRITE_LAYOUT_ENTRIES],
+                buffers: &[],
+                blend: None,
+                cull_mode: None,
+            },
+        )
+    }
+
+    /// Render mip levels 1 to `mip_level_count` of `texture`, which is in `format`, from level 0.
+    /// The texture must be usable as a render attachment.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        mip_level_count: u32,
+    ) -> Result<()> {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        let pipeline = match pipelines.entry(format) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.create_pipeline(device, format)?),
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+        let level_view = |mip_level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mip Level"),
+                base_mip_level: mip_level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+        for mip_level in 1..mip_level_count {
+            let source = level_view(mip_level - 1);
+            let destination = level_view(mip_level);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Mipmap Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &destination,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.1), 0x2e66);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        // Past the largest half, saturating to infinity.
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        // The smallest subnormal, and half of it rounding to even.
+        assert_eq!(f32_to_f16(2.0f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2.0f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_f16(3.0 * 2.0f32.powi(-25)), 0x0002);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0x0001), 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2.0f32.powi(-24));
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn f16_round_trips() {
+        for bits in 0..=u16::MAX {
+            let value = f16_to_f32(bits);
+            if value.is_nan() {
+                assert!(f16_to_f32(f32_to_f16(value)).is_nan());
+            } else {
+                assert_eq!(f32_to_f16(value), bits, "{:#06x}", bits);
+            }
+        }
+    }
+
+    #[test]
+    fn mip_level_counts() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(257, 3), 9);
+        assert_eq!(mip_level_count(0, 0), 1);
+    }
+
+    #[test]
+    fn downsample_even() {
+        let bytes = [0, 40, 80, 120];
+        let (level, width, height) = downsample(&bytes, 2, 2, TextureFormat::R8Unorm);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(level, [60]);
+    }
+
+    #[test]
+    fn downsample_folds_odd_edges_in() {
+        #[rustfmt::skip]
+        let bytes = [
+            0, 0, 90, 10, 10,
+            0, 0, 90, 10, 40,
+        ];
+        let (level, width, height) = downsample(&bytes, 5, 2, TextureFormat::R8Unorm);
+        assert_eq!((width, height), (2, 1));
+        // The last block is 3 columns wide.
+        assert_eq!(level, [0, 42]);
+
+        let bytes = [0, 0, 0, 0, 0, 0, 90, 90, 90];
+        let (level, width, height) = downsample(&bytes, 3, 3, TextureFormat::R8Unorm);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(level, [30]);
+
+        let (level, width, height) = downsample(&[7, 9, 11], 1, 3, TextureFormat::R8Unorm);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(level, [9]);
+    }
+
+    #[test]
+    fn downsample_averages_in_linear_color() {
+        let bytes = [0, 0, 0, 255, 255, 255, 255, 255];
+        let (level, _, _) = downsample(&bytes, 2, 1, TextureFormat::Rgba8Srgb);
+        // Half way between black and white in linear color, but not alpha.
+        assert_eq!(level, [188, 188, 188, 255]);
+
+        let (level, _, _) = downsample(&bytes, 2, 1, TextureFormat::Rgba8Unorm);
+        assert_eq!(level, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn downsample_half_floats() {
+        let bytes = [1.0, 2.0, 3.0, 4.0, 3.0, 2.0, 1.0, 0.0]
+            .into_iter()
+            .flat_map(|value| f32_to_f16(value).to_le_bytes())
+            .collect::<Vec<_>>();
+        let (level, width, height) = downsample(&bytes, 2, 1, TextureFormat::Rgba16Float);
+        assert_eq!((width, height), (1, 1));
+        let expected = [2.0f32; 4]
+            .into_iter()
+            .flat_map(|value| f32_to_f16(value).to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(level, expected);
+    }
+}
@@ -0,0 +1,322 @@
+//! [`Transform2D`], the placement of things in 2D used across the drawing API.
+
+use std::ops::Mul;
+
+use glam::{Affine2, Mat2, Mat4, Vec2, Vec4};
+use lyon::{
+    geom::{point, Box2D},
+    path::Path,
+};
+
+/// A 2D affine transform described by its parts. Applied to a point, it moves `origin` to
+/// `(0, 0)`, then scales, skews and rotates around it, then translates.
+///
+/// Any affine transform without a zero scale can be described this way, so transforms compose
+/// and invert into another `Transform2D`, with `origin` at zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    pub translation: Vec2,
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    pub scale: Vec2,
+    /// The angle, in radians, the y axis leans away from perpendicular towards the x axis. Points
+    /// move along x by `tan(skew)` for each unit of y.
+    pub skew: f32,
+    /// The point rotated, skewed and scaled around, in the space being transformed.
+    pub origin: Vec2,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform2D {
+    pub const IDENTITY: Self = Self {
+        translation: Vec2::ZERO,
+        rotation: 0.0,
+        scale: Vec2::ONE,
+        skew: 0.0,
+        origin: Vec2::ZERO,
+    };
+
+    pub fn from_translation(translation: Vec2) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_rotation(rotation: f32) -> Self {
+        Self {
+            rotation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec2) -> Self {
+        Self {
+            scale,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// The same as [`Affine2::from_scale_angle_translation`].
+    pub fn from_scale_rotation_translation(scale: Vec2, rotation: f32, translation: Vec2) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Break `affine` into its parts. If it has a zero scale, the skew may be lost.
+    pub fn from_affine(affine: Affine2) -> Self {
+        let x_axis = affine.matrix2.x_axis;
+        let rotation = x_axis.y.atan2(x_axis.x);
+        // Unrotate the y axis to find how far it's skewed along x, and its length along y.
+        let y_axis = Vec2::from_angle(-rotation).rotate(affine.matrix2.y_axis);
+        let skew = if y_axis.y == 0.0 {
+            0.0
+        } else {
+            (y_axis.x / y_axis.y).atan()
+        };
+        Self {
+            translation: affine.translation,
+            rotation,
+            scale: Vec2::new(x_axis.length(), y_axis.y),
+            skew,
+            origin: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_translation(mut self, translation: Vec2) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_skew(mut self, skew: f32) -> Self {
+        self.skew = skew;
+        self
+    }
+
+    pub fn with_origin(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn to_affine(&self) -> Affine2 {
+        let matrix2 = Mat2::from_angle(self.rotation)
+            * Mat2::from_cols(Vec2::X, Vec2::new(self.skew.tan(), 1.0))
+            * Mat2::from_diagonal(self.scale);
+        Affine2::from_mat2_translation(matrix2, self.translation - matrix2 * self.origin)
+    }
+
+    /// The transform as a 3D matrix, leaving z alone.
+    pub fn to_mat4(&self) -> Mat4 {
+        let affine = self.to_affine();
+        Mat4::from_cols(
+            affine.matrix2.x_axis.extend(0.0).extend(0.0),
+            affine.matrix2.y_axis.extend(0.0).extend(0.0),
+            Vec4::Z,
+            affine.translation.extend(0.0).extend(1.0),
+        )
+    }
+
+    pub fn to_lyon(&self) -> lyon::math::Transform {
+        let [[m11, m12], [m21, m22], [m31, m32]] = self.to_affine().to_cols_array_2d();
+        lyon::math::Transform::new(m11, m12, m21, m22, m31, m32)
+    }
+
+    /// The transform undoing this one. It isn't finite if either scale is zero.
+    pub fn inverse(&self) -> Self {
+        Self::from_affine(self.to_affine().inverse())
+    }
+
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        self.to_affine().transform_point2(point)
+    }
+
+    /// Transform a direction or offset, which isn't translated.
+    pub fn transform_vector(&self, vector: Vec2) -> Vec2 {
+        self.to_affine().transform_vector2(vector)
+    }
+
+    /// The bounding box of `rect` once transformed.
+    pub fn transform_rect(&self, rect: Box2D<f32>) -> Box2D<f32> {
+        let affine = self.to_affine();
+        let corners = [
+            Vec2::new(rect.min.x, rect.min.y),
+            Vec2::new(rect.max.x, rect.min.y),
+            Vec2::new(rect.min.x, rect.max.y),
+            Vec2::new(rect.max.x, rect.max.y),
+        ]
+        .map(|corner| affine.transform_point2(corner));
+        let min = corners
+            .iter()
+            .copied()
+            .reduce(Vec2::min)
+            .unwrap_or_default();
+        let max = corners
+            .iter()
+            .copied()
+            .reduce(Vec2::max)
+            .unwrap_or_default();
+        Box2D::new(point(min.x, min.y), point(max.x, max.y))
+    }
+
+    /// A copy of `path` with every point transformed.
+    pub fn transform_path(&self, path: &Path) -> Path {
+        path.clone().transformed(&self.to_lyon())
+    }
+}
+
+/// `a * b` applies `b`, then `a`.
+impl Mul for Transform2D {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::from_affine(self.to_affine() * rhs.to_affine())
+    }
+}
+
+impl From<Transform2D> for Affine2 {
+    fn from(transform: Transform2D) -> Self {
+        transform.to_affine()
+    }
+}
+
+impl From<Transform2D> for Mat4 {
+    fn from(transform: Transform2D) -> Self {
+        transform.to_mat4()
+    }
+}
+
+impl From<Affine2> for Transform2D {
+    fn from(affine: Affine2) -> Self {
+        Self::from_affine(affine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn transforms() -> [Transform2D; 4] {
+        [
+            Transform2D::IDENTITY,
+            Transform2D::from_scale_rotation_translation(
+                Vec2::new(2.0, 3.0),
+                0.7,
+                Vec2::new(10.0, -4.0),
+            ),
+            Transform2D::from_rotation(-2.5)
+                .with_scale(Vec2::new(-1.5, 0.5))
+                .with_skew(0.4)
+                .with_origin(Vec2::new(3.0, 8.0)),
+            Transform2D::from_translation(Vec2::new(-7.0, 2.0)).with_skew(-0.9),
+        ]
+    }
+
+    fn assert_affine_eq(a: Affine2, b: Affine2) {
+        assert!(a.abs_diff_eq(b, EPSILON), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn parts_apply_in_order() {
+        let transform = Transform2D::from_scale_rotation_translation(
+            Vec2::new(2.0, 1.0),
+            std::f32::consts::FRAC_PI_2,
+            Vec2::new(5.0, 0.0),
+        )
+        .with_origin(Vec2::new(1.0, 0.0));
+        // Moved to the origin, scaled, rotated a quarter turn, then translated.
+        let point = transform.transform_point(Vec2::new(2.0, 0.0));
+        assert!(point.abs_diff_eq(Vec2::new(5.0, 2.0), EPSILON), "{}", point);
+
+        let skewed = Transform2D::IDENTITY.with_skew(std::f32::consts::FRAC_PI_4);
+        let point = skewed.transform_point(Vec2::new(0.0, 2.0));
+        assert!(point.abs_diff_eq(Vec2::new(2.0, 2.0), EPSILON), "{}", point);
+    }
+
+    #[test]
+    fn from_affine_round_trips() {
+        for transform in transforms() {
+            let affine = transform.to_affine();
+            let parts = Transform2D::from_affine(affine);
+            assert_eq!(parts.origin, Vec2::ZERO);
+            assert_affine_eq(parts.to_affine(), affine);
+            assert_affine_eq(Transform2D::from(affine).into(), affine);
+        }
+    }
+
+    #[test]
+    fn inverse_undoes() {
+        for transform in transforms() {
+            assert_affine_eq(
+                (transform * transform.inverse()).to_affine(),
+                Affine2::IDENTITY,
+            );
+            assert_affine_eq(
+                (transform.inverse() * transform).to_affine(),
+                Affine2::IDENTITY,
+            );
+        }
+        assert!(!Transform2D::from_scale(Vec2::new(0.0, 1.0))
+            .inverse()
+            .to_affine()
+            .is_finite());
+    }
+
+    #[test]
+    fn multiplying_applies_the_right_first() {
+        let [_, a, b, c] = transforms();
+        let point = Vec2::new(3.0, -2.0);
+        let expected = a.transform_point(b.transform_point(point));
+        assert!((a * b)
+            .transform_point(point)
+            .abs_diff_eq(expected, EPSILON));
+        assert_affine_eq(((a * b) * c).to_affine(), (a * (b * c)).to_affine());
+        assert_affine_eq((a * b).to_affine(), a.to_affine() * b.to_affine());
+    }
+
+    #[test]
+    fn conversions_agree() {
+        for transform in transforms() {
+            let local = Vec2::new(-1.0, 4.0);
+            let expected = transform.transform_point(local);
+            let mat4 = transform
+                .to_mat4()
+                .transform_point3(local.extend(0.5))
+                .truncate();
+            assert!(mat4.abs_diff_eq(expected, EPSILON));
+            let lyon = transform.to_lyon().transform_point(point(local.x, local.y));
+            assert!(Vec2::new(lyon.x, lyon.y).abs_diff_eq(expected, EPSILON));
+        }
+    }
+
+    #[test]
+    fn transformed_rects_bound_their_corners() {
+        let transform = Transform2D::from_rotation(std::f32::consts::FRAC_PI_4);
+        let rect = transform.transform_rect(Box2D::new(point(0.0, 0.0), point(2.0, 2.0)));
+        let half_diagonal = 2.0f32.sqrt();
+        assert!((rect.min.x + half_diagonal).abs() < EPSILON);
+        assert!((rect.max.x - half_diagonal).abs() < EPSILON);
+        assert!(rect.min.y.abs() < EPSILON);
+        assert!((rect.max.y - 2.0 * half_diagonal).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,159 @@
+/// The largest block of uniforms that can be pushed. Every binding of the frame's buffer is this
+/// large, so the buffer is kept this much larger than what's been pushed.
+pub const MAX_UNIFORM_BLOCK_SIZE: u64 = 1024;
+
+const INITIAL_CAPACITY: u64 = 16 * 1024;
+
+/// The layout of every bind group that binds [`FrameUniforms`]: one uniform buffer, bound at a
+/// dynamic offset. Group 0 of every pipeline, and group 2 of materials with uniforms.
+pub(crate) const FRAME_UNIFORMS_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] =
+    &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+
+/// Where a block of uniforms was pushed to [`FrameUniforms`], valid until the next frame begins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniformOffset {
+    offset: u32,
+    frame: u64,
+}
+
+/// One uniform buffer that every camera and material instance pushes its uniforms to each frame,
+/// uploaded with a single write and bound with one bind group at different dynamic offsets,
+/// instead of each owning a buffer. Created by the renderer, which starts a frame with
+/// [`crate::renderer::Renderer::begin_frame`] and uploads it with
+/// [`crate::renderer::Renderer::upload_uniforms`].
+///
+/// The buffer is reused from the start each frame. Writes are queued before the frame's
+/// commands, so this doesn't disturb the previous frame on the GPU. It grows when a frame
+/// pushes more than fits.
+#[derive(Debug)]
+pub struct FrameUniforms {
+    buffer: wgpu::Buffer,
+    /// The size of `buffer`.
+    capacity: u64,
+    bind_group: wgpu::BindGroup,
+    data: Vec<u8>,
+    /// The device's minimum uniform buffer offset alignment, which every block starts on.
+    alignment: u64,
+    frame: u64,
+    uploaded: bool,
+}
+
+impl FrameUniforms {
+    /// `layout` is the renderer's layout of [`FRAME_UNIFORMS_LAYOUT_ENTRIES`].
+    pub(crate) fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let (buffer, bind_group) = create_buffer(device, layout, INITIAL_CAPACITY);
+        Self {
+            buffer,
+            capacity: INITIAL_CAPACITY,
+            bind_group,
+            data: Vec::new(),
+            alignment: device.limits().min_uniform_buffer_offset_alignment as u64,
+            frame: 0,
+            uploaded: false,
+        }
+    }
+
+    /// Forget the previous frame's uniforms, whose offsets can no longer be drawn with.
+    pub(crate) fn begin_frame(&mut self) {
+        self.data.clear();
+        self.frame += 1;
+        self.uploaded = false;
+    }
+
+    pub fn push<T: bytemuck::Pod>(&mut self, value: &T) -> UniformOffset {
+        self.push_bytes(bytemuck::bytes_of(value))
+    }
+
+    /// Push a block of uniforms laid out as the shader's struct. Panics if it's larger than
+    /// [`MAX_UNIFORM_BLOCK_SIZE`].
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> UniformOffset {
+        assert!(
+            bytes.len() as u64 <= MAX_UNIFORM_BLOCK_SIZE,
+            "a block of {} bytes of uniforms is larger than the limit of {}",
+            bytes.len(),
+            MAX_UNIFORM_BLOCK_SIZE
+        );
+        let offset = (self.data.len() as u64).div_ceil(self.alignment) * self.alignment;
+        self.data.resize(offset as usize, 0);
+        self.data.extend_from_slice(bytes);
+        UniformOffset {
+            offset: offset as u32,
+            frame: self.frame,
+        }
+    }
+
+    /// Write everything pushed this frame to the buffer, growing it first if needed.
+    pub(crate) fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+    ) {
+        // Buffer writes must be a multiple of 4 bytes.
+        self.data
+            .resize((self.data.len() as u64).div_ceil(4) as usize * 4, 0);
+        let size = self.data.len() as u64 + MAX_UNIFORM_BLOCK_SIZE;
+        if size > self.capacity {
+            self.capacity = size.next_power_of_two();
+            (self.buffer, self.bind_group) = create_buffer(device, layout, self.capacity);
+        }
+        if !self.data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.data);
+        }
+        self.uploaded = true;
+    }
+
+    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// The dynamic offset to bind `offset` at, checking that it's from this frame. `owner` names
+    /// what pushed it, for the panic message.
+    pub(crate) fn dynamic_offset(&self, offset: UniformOffset, owner: &str) -> u32 {
+        assert!(
+            offset.frame == self.frame,
+            "{} was drawn with uniforms from an earlier frame, it must be prepared every frame",
+            owner
+        );
+        assert!(
+            self.uploaded,
+            "uniforms were drawn with before Renderer::upload_uniforms"
+        );
+        offset.offset
+    }
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    size: u64,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Frame Uniform Buffer"),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Frame Uniforms Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(MAX_UNIFORM_BLOCK_SIZE),
+            }),
+        }],
+    });
+    (buffer, bind_group)
+}
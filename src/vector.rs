@@ -0,0 +1,501 @@
+use std::path::Path as FilePath;
+
+use anyhow::*;
+use glam::{Affine2, Vec2};
+use lyon::{
+    geom::{point, Box2D},
+    lyon_tessellation::{FillRule, VertexBuffers},
+    path::Path,
+};
+use usvg::tiny_skia_path::PathSegment;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    paint::{
+        create_paint_bind_groups, Gradient, GradientShape, GradientStop, Paint, SpreadMode,
+        MAX_GRADIENT_STOPS,
+    },
+    renderer::ShapeVertex,
+    shape::{LineCap, LineJoin, PaintedRange, ShapeBuilder, StrokeStyle},
+    transform::Transform2D,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SvgOptions {
+    /// Maximum distance between the tessellated geometry and the true curves, in SVG user units.
+    /// Lower it if the graphic will be drawn magnified.
+    pub tolerance: f32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self { tolerance: 0.02 }
+    }
+}
+
+/// A vector graphic that is tessellated once and then drawn any number of times with
+/// [`crate::renderer::Renderer::draw_vector_graphic`]. Build one from paths with a
+/// [`ShapeBuilder`] or load one from an SVG.
+///
+/// SVG y coordinates point down, so SVGs are flipped on import: the top left of the SVG canvas
+/// is placed at the origin and the graphic extends into negative y.
+#[derive(Debug)]
+pub struct VectorGraphic {
+    pub(crate) vertex_buffer: wgpu::Buffer,
+    pub(crate) index_buffer: wgpu::Buffer,
+    pub(crate) ranges: Vec<PaintedRange>,
+    pub(crate) paint_bind_groups: Vec<wgpu::BindGroup>,
+    bounds: Box2D<f32>,
+}
+
+impl VectorGraphic {
+    pub(crate) fn from_geometry(
+        device: &wgpu::Device,
+        paint_layout: &wgpu::BindGroupLayout,
+        label: &str,
+        geometry: &VertexBuffers<ShapeVertex, u32>,
+        bounds: Box2D<f32>,
+        ranges: &[PaintedRange],
+        paints: &[Paint],
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&geometry.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&geometry.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Self {
+            vertex_buffer,
+            index_buffer,
+            ranges: ranges.to_vec(),
+            paint_bind_groups: create_paint_bind_groups(device, paint_layout, label, paints),
+            bounds,
+        }
+    }
+
+    /// `paint_layout` is [`crate::renderer::Renderer::paint_bind_group_layout`].
+    pub fn from_svg_file(
+        device: &wgpu::Device,
+        paint_layout: &wgpu::BindGroupLayout,
+        path: impl AsRef<FilePath>,
+        options: &SvgOptions,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read svg file {}", path.display()))?;
+        let label = path.to_string_lossy();
+        Self::from_svg_data(device, paint_layout, &data, &label, options)
+    }
+
+    /// `paint_layout` is [`crate::renderer::Renderer::paint_bind_group_layout`].
+    pub fn from_svg_data(
+        device: &wgpu::Device,
+        paint_layout: &wgpu::BindGroupLayout,
+        data: &[u8],
+        label: &str,
+        options: &SvgOptions,
+    ) -> Result<Self> {
+        let builder = tessellate_svg(data, label, options)?;
+        Ok(builder.build(device, paint_layout, label))
+    }
+
+    /// The bounding box of the untransformed geometry.
+    pub fn bounds(&self) -> Box2D<f32> {
+        self.bounds
+    }
+}
+
+fn tessellate_svg(data: &[u8], label: &str, options: &SvgOptions) -> Result<ShapeBuilder> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+        .with_context(|| format!("failed to parse svg {}", label))?;
+
+    let mut tessellator = SvgTessellator {
+        builder: ShapeBuilder::with_tolerance(options.tolerance),
+        label,
+    };
+    tessellator.add_group(tree.root(), 1.0)?;
+    Ok(tessellator.builder)
+}
+
+struct SvgTessellator<'a> {
+    builder: ShapeBuilder,
+    label: &'a str,
+}
+
+impl SvgTessellator<'_> {
+    fn add_group(&mut self, group: &usvg::Group, opacity: f32) -> Result<()> {
+        let opacity = opacity * group.opacity().get();
+        for node in group.children() {
+            match node {
+                usvg::Node::Group(group) => self.add_group(group, opacity)?,
+                usvg::Node::Path(path) => self.add_path(path, opacity)?,
+                // Text is only available as paths when usvg is built with its text feature, and
+                // raster images aren't supported.
+                usvg::Node::Image(_) | usvg::Node::Text(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn add_path(&mut self, svg_path: &usvg::Path, opacity: f32) -> Result<()> {
+        if !svg_path.is_visible() {
+            return Ok(());
+        }
+
+        // Flip from SVG's y down to world y up.
+        let world_from_local = Transform2D::from_scale(Vec2::new(1.0, -1.0))
+            * Transform2D::from_affine(to_affine(svg_path.abs_transform()));
+        let path = world_from_local.transform_path(&to_lyon_path(svg_path.data()));
+
+        if let Some(fill) = svg_path.fill() {
+            let paint = to_paint(
+                fill.paint(),
+                opacity * fill.opacity().get(),
+                world_from_local.into(),
+                self.label,
+            );
+            let fill_rule = match fill.rule() {
+                usvg::FillRule::NonZero => FillRule::NonZero,
+                usvg::FillRule::EvenOdd => FillRule::EvenOdd,
+            };
+            self.builder.fill_with_rule(&path, fill_rule, paint)?;
+        }
+
+        if let Some(stroke) = svg_path.stroke() {
+            let paint = to_paint(
+                stroke.paint(),
+                opacity * stroke.opacity().get(),
+                world_from_local.into(),
+                self.label,
+            );
+            let style = to_stroke_style(stroke, world_from_local);
+            self.builder.stroke(&path, paint, &style)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Strokes are tessellated in world space, so their lengths are scaled by `world_from_local`.
+fn to_stroke_style(stroke: &usvg::Stroke, world_from_local: Transform2D) -> StrokeStyle {
+    let scale = (world_from_local.scale.x * world_from_local.scale.y)
+        .abs()
+        .sqrt();
+    let cap = match stroke.linecap() {
+        usvg::LineCap::Butt => LineCap::Butt,
+        usvg::LineCap::Round => LineCap::Round,
+        usvg::LineCap::Square => LineCap::Square,
+    };
+    let join = match stroke.linejoin() {
+        usvg::LineJoin::Miter => LineJoin::Miter,
+        usvg::LineJoin::MiterClip => LineJoin::MiterClip,
+        usvg::LineJoin::Round => LineJoin::Round,
+        usvg::LineJoin::Bevel => LineJoin::Bevel,
+    };
+    let mut style = StrokeStyle::new(stroke.width().get() * scale)
+        .with_line_cap(cap)
+        .with_line_join(join)
+        .with_miter_limit(stroke.miterlimit().get());
+    if let Some(dashes) = stroke.dasharray() {
+        let dashes = dashes.iter().map(|d| d * scale).collect::<Vec<_>>();
+        style = style.with_dash(&dashes, stroke.dashoffset() * scale);
+    }
+    style
+}
+
+fn to_affine(transform: usvg::Transform) -> Affine2 {
+    Affine2::from_cols_array(&[
+        transform.sx,
+        transform.ky,
+        transform.kx,
+        transform.sy,
+        transform.tx,
+        transform.ty,
+    ])
+}
+
+fn to_lyon_path(data: &usvg::tiny_skia_path::Path) -> Path {
+    let p = |p: usvg::tiny_skia_path::Point| point(p.x, p.y);
+
+    let mut builder = Path::builder();
+    let mut open = false;
+    for segment in data.segments() {
+        match segment {
+            PathSegment::MoveTo(to) => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(p(to));
+                open = true;
+            }
+            PathSegment::LineTo(to) => {
+                builder.line_to(p(to));
+            }
+            PathSegment::QuadTo(ctrl, to) => {
+                builder.quadratic_bezier_to(p(ctrl), p(to));
+            }
+            PathSegment::CubicTo(ctrl1, ctrl2, to) => {
+                builder.cubic_bezier_to(p(ctrl1), p(ctrl2), p(to));
+            }
+            PathSegment::Close => {
+                builder.end(true);
+                open = false;
+            }
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+
+    builder.build()
+}
+
+/// Convert an SVG paint, with its opacity folded into the colors.
+fn to_paint(paint: &usvg::Paint, opacity: f32, world_from_local: Affine2, label: &str) -> Paint {
+    let (shape, base) = match paint {
+        usvg::Paint::Color(color) => return Paint::Solid(to_rgba(*color, opacity)),
+        usvg::Paint::LinearGradient(gradient) => (
+            GradientShape::Linear {
+                start: Vec2::new(gradient.x1(), gradient.y1()),
+                end: Vec2::new(gradient.x2(), gradient.y2()),
+            },
+            &**gradient as &usvg::BaseGradient,
+        ),
+        usvg::Paint::RadialGradient(gradient) => (
+            GradientShape::Radial {
+                center: Vec2::new(gradient.cx(), gradient.cy()),
+                radius: gradient.r().get(),
+                focal: Vec2::new(gradient.fx(), gradient.fy()),
+            },
+            &**gradient as &usvg::BaseGradient,
+        ),
+        // Patterns would need to be rendered to a texture first.
+        usvg::Paint::Pattern(_) => {
+            log::warn!("{}: SVG patterns aren't supported, leaving them out", label);
+            return Paint::Solid([0.0, 0.0, 0.0, 0.0]);
+        }
+    };
+
+    if base.stops().is_empty() {
+        return Paint::Solid([0.0, 0.0, 0.0, 0.0]);
+    }
+
+    let spread = match base.spread_method() {
+        usvg::SpreadMethod::Pad => SpreadMode::Pad,
+        usvg::SpreadMethod::Repeat => SpreadMode::Repeat,
+        usvg::SpreadMethod::Reflect => SpreadMode::Reflect,
+    };
+    let gradient = Gradient::new(shape)
+        .with_spread(spread)
+        .with_transform(world_from_local * to_affine(base.transform()));
+    let mut stops = base
+        .stops()
+        .iter()
+        .map(|stop| GradientStop {
+            offset: stop.offset().get(),
+            color: to_rgba(stop.color(), stop.opacity().get() * opacity),
+        })
+        .collect::<Vec<_>>();
+    if stops.len() > MAX_GRADIENT_STOPS {
+        log::warn!(
+            "{}: a gradient has {} stops, merging them down to {}",
+            label,
+            stops.len(),
+            MAX_GRADIENT_STOPS
+        );
+        merge_stops(&mut stops);
+    }
+    let gradient = stops.iter().fold(gradient, |gradient, stop| {
+        gradient.with_stop(stop.offset, stop.color)
+    });
+    Paint::Gradient(gradient)
+}
+
+/// Remove stops until there are at most [`MAX_GRADIENT_STOPS`], each time the one the stops on
+/// either side of it predict best. The first and last stops, which color past the ends, are kept.
+fn merge_stops(stops: &mut Vec<GradientStop>) {
+    while stops.len() > MAX_GRADIENT_STOPS.max(2) {
+        let error = |index: usize| {
+            let (before, stop, after) = (&stops[index - 1], &stops[index], &stops[index + 1]);
+            let span = after.offset - before.offset;
+            let t = if span > 0.0 {
+                (stop.offset - before.offset) / span
+            } else {
+                0.5
+            };
+            (0..4)
+                .map(|c| {
+                    let predicted = before.color[c] + (after.color[c] - before.color[c]) * t;
+                    (predicted - stop.color[c]).abs()
+                })
+                .fold(0.0, f32::max)
+        };
+        let index = (1..stops.len() - 1)
+            .min_by(|&a, &b| error(a).total_cmp(&error(b)))
+            .expect("there are stops between the ends");
+        stops.remove(index);
+    }
+}
+
+fn to_rgba(color: usvg::Color, alpha: f32) -> [f32; 4] {
+    [
+        color.red as f32 / 255.0,
+        color.green as f32 / 255.0,
+        color.blue as f32 / 255.0,
+        alpha,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg(body: &str) -> String {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">{}</svg>"#,
+            body
+        )
+    }
+
+    fn parse(body: &str) -> usvg::Tree {
+        usvg::Tree::from_str(&svg(body), &usvg::Options::default()).unwrap()
+    }
+
+    fn first_path(group: &usvg::Group) -> Option<&usvg::Path> {
+        group.children().iter().find_map(|node| match node {
+            usvg::Node::Group(group) => first_path(group),
+            usvg::Node::Path(path) => Some(path),
+            _ => None,
+        })
+    }
+
+    fn first_paint(body: &str) -> Paint {
+        let tree = parse(body);
+        let path = first_path(tree.root()).unwrap();
+        to_paint(path.fill().unwrap().paint(), 1.0, Affine2::IDENTITY, "test")
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn nested_groups_are_flattened_and_flipped() {
+        let builder = tessellate_svg(
+            svg(r#"<g transform="translate(10 20)">
+                    <g transform="scale(2)"><rect x="1" y="2" width="3" height="4"/></g>
+                </g>"#)
+            .as_bytes(),
+            "test",
+            &SvgOptions::default(),
+        )
+        .unwrap();
+        let bounds = builder.bounds();
+        // x from 10 + 2 * 1 to 10 + 2 * 4, and y from 20 + 2 * 2 to 20 + 2 * 6, negated.
+        assert_close(bounds.min.x, 12.0);
+        assert_close(bounds.max.x, 18.0);
+        assert_close(bounds.min.y, -32.0);
+        assert_close(bounds.max.y, -24.0);
+    }
+
+    #[test]
+    fn strokes_and_dashes_are_scaled() {
+        let tree = parse(
+            r#"<path d="M0 0 L10 0" transform="scale(3)" stroke="black" stroke-width="2"
+                stroke-dasharray="4 1 2" stroke-dashoffset="1" stroke-linecap="round"/>"#,
+        );
+        let path = first_path(tree.root()).unwrap();
+        let world_from_local = Transform2D::from_scale(Vec2::new(1.0, -1.0))
+            * Transform2D::from_affine(to_affine(path.abs_transform()));
+        let style = to_stroke_style(path.stroke().unwrap(), world_from_local);
+        assert_close(style.width, 6.0);
+        assert_eq!(style.start_cap, LineCap::Round);
+        let dash = style.dash.unwrap();
+        assert_eq!(dash.dashes(), [12.0, 3.0, 6.0, 12.0, 3.0, 6.0]);
+        assert_close(dash.offset, 3.0);
+    }
+
+    #[test]
+    fn gradients_keep_their_spread_and_stops() {
+        let paint = first_paint(
+            r#"<linearGradient id="g" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="10"
+                    y2="0" spreadMethod="reflect">
+                <stop offset="0.25" stop-color="red"/>
+                <stop offset="1" stop-color="blue" stop-opacity="0.5"/>
+            </linearGradient>
+            <rect width="10" height="10" fill="url(#g)"/>"#,
+        );
+        let Paint::Gradient(gradient) = paint else {
+            panic!("expected a gradient");
+        };
+        assert_eq!(gradient.spread, SpreadMode::Reflect);
+        assert_eq!(
+            gradient.shape,
+            GradientShape::Linear {
+                start: Vec2::ZERO,
+                end: Vec2::new(10.0, 0.0),
+            }
+        );
+        assert_eq!(
+            gradient.stops(),
+            [
+                GradientStop {
+                    offset: 0.25,
+                    color: [1.0, 0.0, 0.0, 1.0],
+                },
+                GradientStop {
+                    offset: 1.0,
+                    color: [0.0, 0.0, 1.0, 0.5],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn gradients_with_too_many_stops_are_merged() {
+        // A ramp of red with a spike of blue in the middle.
+        let stops = (0..20)
+            .map(|i| {
+                let blue = if i == 10 { 255 } else { 0 };
+                format!(
+                    r#"<stop offset="{}" stop-color="rgb({}, 0, {})"/>"#,
+                    i as f32 / 19.0,
+                    i * 13,
+                    blue
+                )
+            })
+            .collect::<String>();
+        let paint = first_paint(&format!(
+            r#"<linearGradient id="g" gradientUnits="userSpaceOnUse" x2="10">{}</linearGradient>
+            <rect width="10" height="10" fill="url(#g)"/>"#,
+            stops
+        ));
+        let Paint::Gradient(gradient) = paint else {
+            panic!("expected a gradient");
+        };
+        let stops = gradient.stops();
+        assert_eq!(stops.len(), MAX_GRADIENT_STOPS);
+        assert!(Paint::Gradient(gradient.clone()).validate().is_ok());
+        assert_eq!(stops[0].offset, 0.0);
+        assert_eq!(stops[MAX_GRADIENT_STOPS - 1].offset, 1.0);
+        assert!(stops.iter().any(|stop| stop.color[2] == 1.0));
+    }
+
+    #[test]
+    fn patterns_are_left_out() {
+        let paint = first_paint(
+            r#"<pattern id="p" width="4" height="4" patternUnits="userSpaceOnUse">
+                <rect width="2" height="2" fill="red"/>
+            </pattern>
+            <rect width="10" height="10" fill="url(#p)"/>"#,
+        );
+        let Paint::Solid(color) = paint else {
+            panic!("expected a solid color");
+        };
+        assert_eq!(color[3], 0.0);
+    }
+}